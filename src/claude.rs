@@ -1,37 +1,173 @@
 use anyhow::{anyhow, Context, Result};
 use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
-use serde_json::Value;
+use serde_json::{json, Value};
+use std::collections::HashMap;
 use std::env;
-use std::io::{self, BufRead, BufReader, IsTerminal, Write};
-use std::path::Path;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
-use std::time::Duration;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
 
 use crate::config::Config;
+use crate::policy::{Policy, Violation};
+use crate::ui::Ui;
 use crate::util::{format_cost, format_duration};
 
+/// `bypass` skips Claude's permission prompts; `allowlist` only allows the
+/// tools from [`allowed_tools`].
+pub const PERMISSION_MODES: [&str; 2] = ["bypass", "allowlist"];
+
+/// Tools every phase may use.
+const READ_TOOLS: [&str; 5] = ["Read", "Grep", "Glob", "LS", "TodoWrite"];
+const EDIT_TOOLS: [&str; 4] = ["Edit", "MultiEdit", "Write", "NotebookEdit"];
+const READ_GIT: [&str; 4] = ["git status", "git diff", "git log", "git show"];
+const WRITE_GIT: [&str; 2] = ["git add", "git commit"];
+
+/// Whether a phase may change the working tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    ReadWrite,
+    ReadOnly,
+}
+
+impl Access {
+    pub fn name(self) -> &'static str {
+        match self {
+            Access::ReadWrite => "read-write",
+            Access::ReadOnly => "read-only",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ClaudeResult {
     pub output_text: String,
     pub cost_usd: f64,
     pub duration_ms: u64,
     pub claude_session_id: String,
+    /// Forbidden commands the agent ran; a critical one stopped the agent.
+    pub policy_violations: Vec<Violation>,
+    /// The agent was stopped after `PHASE_TIMEOUT_MINUTES`.
+    pub timed_out: bool,
 }
 
 pub fn run_claude(
     prompt: &str,
     cfg: &Config,
+    access: Access,
     dry_run: bool,
     verbose: bool,
+    ui: &Ui,
 ) -> Result<ClaudeResult> {
     if dry_run {
         return Ok(simulate(prompt));
     }
+    invoke(prompt, None, cfg, access, verbose, ui)
+}
 
+/// Send a follow-up prompt into an existing Claude session.
+pub fn resume_claude(
+    session_id: &str,
+    prompt: &str,
+    cfg: &Config,
+    access: Access,
+    verbose: bool,
+    ui: &Ui,
+) -> Result<ClaudeResult> {
+    invoke(prompt, Some(session_id), cfg, access, verbose, ui)
+}
+
+/// Tools a phase may use with `PERMISSION_MODE=allowlist`: reading and
+/// searching, read-only git, the verify and test commands of the project and
+/// its targets and `ALLOWED_TOOLS`, plus editing and committing for
+/// read-write phases. A target's commands are allowed from its directory
+/// only as the exact line the prompts give.
+pub fn allowed_tools(cfg: &Config, access: Access) -> Vec<String> {
+    let mut tools: Vec<String> = READ_TOOLS.iter().map(|t| t.to_string()).collect();
+    let mut commands: Vec<&str> = READ_GIT.to_vec();
+    if access == Access::ReadWrite {
+        tools.extend(EDIT_TOOLS.iter().map(|t| t.to_string()));
+        commands.extend(WRITE_GIT);
+    }
+    let mut lines = vec![&cfg.verify_command, &cfg.test_command];
+    let mut in_dirs = vec![];
+    for target in &cfg.targets {
+        for command in [&target.verify_command, &target.test_command] {
+            lines.push(command);
+            if !command.is_empty() {
+                in_dirs.push(format!("Bash({})", target.in_dir(command)));
+            }
+        }
+    }
+    for line in lines {
+        commands.extend(
+            line.split("&&")
+                .flat_map(|part| part.split(';'))
+                .map(str::trim)
+                .filter(|c| !c.is_empty()),
+        );
+    }
+    let prefixes = commands.into_iter().map(|c| format!("Bash({}:*)", c));
+    for tool in prefixes.chain(in_dirs) {
+        if !tools.contains(&tool) {
+            tools.push(tool);
+        }
+    }
+    for extra in cfg.allowed_tools.split(',').map(str::trim) {
+        if !extra.is_empty() && !tools.iter().any(|t| t == extra) {
+            tools.push(extra.to_string());
+        }
+    }
+    tools
+}
+
+/// Write the Claude settings file for `access` under the session directory.
+fn write_permission_settings(cfg: &Config, access: Access) -> Result<PathBuf> {
+    let deny: &[&str] = match access {
+        Access::ReadWrite => &[],
+        Access::ReadOnly => &EDIT_TOOLS,
+    };
+    let settings = json!({
+        "permissions": {
+            "allow": allowed_tools(cfg, access),
+            "deny": deny,
+        }
+    });
+    fs::create_dir_all(&cfg.session_dir)
+        .with_context(|| format!("failed to create {}", cfg.session_dir))?;
+    let path = Path::new(&cfg.session_dir).join(format!("claude-settings.{}.json", access.name()));
+    fs::write(&path, serde_json::to_string_pretty(&settings)?)
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+fn invoke(
+    prompt: &str,
+    resume: Option<&str>,
+    cfg: &Config,
+    access: Access,
+    verbose: bool,
+    ui: &Ui,
+) -> Result<ClaudeResult> {
+    let policy = Policy::load(Path::new(&cfg.policy_file))?;
     let mut cmd = Command::new(&cfg.claude_bin);
-    cmd.env_remove("CLAUDECODE")
-        .arg("--dangerously-skip-permissions")
-        .arg("--print")
+    cmd.env_remove("CLAUDECODE");
+    if cfg.permission_mode == "allowlist" {
+        let settings = write_permission_settings(cfg, access)?;
+        cmd.arg("--settings")
+            .arg(settings)
+            .arg("--allowedTools")
+            .arg(allowed_tools(cfg, access).join(","));
+        if access == Access::ReadOnly {
+            cmd.arg("--disallowedTools").arg(EDIT_TOOLS.join(","));
+        }
+    } else {
+        cmd.arg("--dangerously-skip-permissions");
+    }
+    cmd.arg("--print")
         .arg("--output-format")
         .arg("stream-json")
         .arg("--verbose")
@@ -39,6 +175,10 @@ pub fn run_claude(
         .stdout(Stdio::piped())
         .stderr(Stdio::inherit());
 
+    if let Some(id) = resume {
+        cmd.arg("--resume").arg(id);
+    }
+
     if !cfg.claude_model.is_empty() {
         cmd.arg("--model").arg(&cfg.claude_model);
     }
@@ -60,58 +200,122 @@ pub fn run_claude(
             .context("failed writing prompt")?;
     }
 
+    // Read on a separate thread so a hung phase can be timed out even when
+    // the agent's own children keep the pipe open.
     let stdout = child.stdout.take().context("failed to open stdout")?;
-    let reader = BufReader::new(stdout);
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    let deadline = (cfg.phase_timeout_minutes > 0)
+        .then(|| Instant::now() + Duration::from_secs(u64::from(cfg.phase_timeout_minutes) * 60));
+    let mut timed_out = false;
     let mut lines = Vec::new();
-    let mut stream_tools = 0_u32;
     let cwd = std::env::current_dir().ok();
-    let mut last_bash_tool_id = String::new();
-    let mut stream_renderer = StreamRenderer::new(verbose);
-
-    for line in reader.lines() {
-        let line = line.context("failed to read claude stream")?;
+    let mut stream_state = StreamState::default();
+    let mut stream_renderer = StreamRenderer::new(verbose, ui);
+    let mut violations = Vec::new();
+    let mut aborted = false;
+
+    loop {
+        let next = match deadline {
+            Some(deadline) => rx.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+            None => rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let line = match next {
+            Ok(line) => line.context("failed to read claude stream")?,
+            Err(RecvTimeoutError::Timeout) => {
+                timed_out = true;
+                child.kill().ok();
+                break;
+            }
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
         if line.trim().is_empty() {
             continue;
         }
-        stream_tools = stream_tools.saturating_add(render_stream_event(
+        render_stream_event(
             &line,
             verbose,
-            stream_tools,
-            &mut last_bash_tool_id,
+            &mut stream_state,
             cwd.as_deref(),
             &mut stream_renderer,
-        ));
+        );
+        for command in bash_commands(&line) {
+            if let Some(violation) = policy.check_command(&command) {
+                aborted |= violation.critical;
+                violations.push(violation);
+            }
+        }
         lines.push(line);
+        if aborted {
+            child.kill().ok();
+            break;
+        }
     }
     stream_renderer.finish();
 
     let status = child.wait().context("failed waiting for claude")?;
-    if !status.success() {
+    if !status.success() && !aborted && !timed_out {
         return Err(anyhow!("claude failed with status {}", status));
     }
 
-    Ok(parse_stream(&lines))
+    let mut result = parse_stream(&lines);
+    result.policy_violations = violations;
+    result.timed_out = timed_out;
+    Ok(result)
+}
+
+/// Commands of the Bash tool calls in one stream event.
+fn bash_commands(line: &str) -> Vec<String> {
+    let Ok(v) = serde_json::from_str::<Value>(line) else {
+        return vec![];
+    };
+    if v.get("type").and_then(Value::as_str) != Some("assistant") {
+        return vec![];
+    }
+    v.get("message")
+        .and_then(|m| m.get("content"))
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default()
+        .iter()
+        .filter(|b| {
+            b.get("type").and_then(Value::as_str) == Some("tool_use")
+                && b.get("name").and_then(Value::as_str) == Some("Bash")
+        })
+        .filter_map(|b| b.pointer("/input/command").and_then(Value::as_str))
+        .map(ToOwned::to_owned)
+        .collect()
 }
 
 fn render_stream_event(
     line: &str,
     verbose: bool,
-    current_tool_count: u32,
-    last_bash_tool_id: &mut String,
+    state: &mut StreamState,
     cwd: Option<&Path>,
     renderer: &mut StreamRenderer,
-) -> u32 {
+) {
     let Ok(v) = serde_json::from_str::<Value>(line) else {
         if verbose {
-            renderer.print_line(&format!(
-                "      → non-json: {}",
-                trim_single_line(line, 100)
-            ));
+            renderer.step(0, &format!("non-json: {}", renderer.trim(line, 100)));
         }
-        return 0;
+        return;
     };
 
     let event_type = v.get("type").and_then(Value::as_str).unwrap_or("");
+    let depth = state.depth_for(v.get("parent_tool_use_id").and_then(Value::as_str));
+    let blocks = v
+        .get("message")
+        .and_then(|m| m.get("content"))
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
     match event_type {
         "system" => {
             let subtype = v.get("subtype").and_then(Value::as_str).unwrap_or("");
@@ -122,133 +326,21 @@ fn render_stream_event(
                     .and_then(Value::as_str)
                     .unwrap_or("unknown");
                 let short = session.chars().take(8).collect::<String>();
-                renderer.print_line(&format!("      → session  model={}  id={}", model, short));
+                renderer.step(0, &format!("session  model={}  id={}", model, short));
                 renderer.set_status(&format!("thinking - {}", model));
             }
-            0
         }
         "assistant" => {
-            if let Some(content) = v
-                .get("message")
-                .and_then(|m| m.get("content"))
-                .and_then(Value::as_array)
-                .and_then(|arr| arr.first())
-            {
-                let ctype = content.get("type").and_then(Value::as_str).unwrap_or("");
-                match ctype {
-                    "tool_use" => {
-                        let tool_id = content
-                            .get("id")
-                            .and_then(Value::as_str)
-                            .unwrap_or_default()
-                            .to_string();
-                        let tool_name = content
-                            .get("name")
-                            .and_then(Value::as_str)
-                            .unwrap_or("tool");
-                        if is_internal_tool(tool_name) && !verbose {
-                            return 0;
-                        }
-                        let cmd = content
-                            .get("input")
-                            .and_then(Value::as_object)
-                            .and_then(|obj| {
-                                obj.get("command")
-                                    .or_else(|| obj.get("file_path"))
-                                    .or_else(|| obj.get("pattern"))
-                            })
-                            .and_then(Value::as_str)
-                            .unwrap_or("");
-                        let cmd = compact_path(cmd, cwd);
-
-                        if tool_name == "Bash" {
-                            *last_bash_tool_id = tool_id;
-                        } else {
-                            last_bash_tool_id.clear();
-                        }
-
-                        if cmd.is_empty() {
-                            renderer.print_line(&format!("      → {}", tool_name));
-                            renderer.set_status(&format!(
-                                "#{} {}",
-                                current_tool_count + 1,
-                                tool_name
-                            ));
-                        } else {
-                            renderer.print_line(&format!(
-                                "      → {:<8} {}",
-                                tool_name,
-                                trim_single_line(&cmd, 80)
-                            ));
-                            renderer.set_status(&format!(
-                                "#{} {} {}",
-                                current_tool_count + 1,
-                                tool_name,
-                                trim_single_line(&cmd, 48)
-                            ));
-                        }
-                        1
-                    }
-                    "thinking" => {
-                        if renderer.has_spinner() {
-                            renderer.set_status("thinking");
-                        } else {
-                            renderer.print_line("      → thinking...");
-                        }
-                        0
-                    }
-                    "text" => {
-                        if verbose {
-                            let text = content.get("text").and_then(Value::as_str).unwrap_or("");
-                            if !text.trim().is_empty() {
-                                renderer.print_line(&format!(
-                                    "      → text: {}",
-                                    trim_single_line(text, 100)
-                                ));
-                            }
-                        }
-                        0
-                    }
-                    _ => 0,
-                }
-            } else {
-                0
+            for block in blocks {
+                render_assistant_block(block, depth, verbose, state, cwd, renderer);
             }
         }
         "user" => {
-            if let Some(content) = v
-                .get("message")
-                .and_then(|m| m.get("content"))
-                .and_then(Value::as_array)
-                .and_then(|arr| arr.first())
-            {
-                if content.get("type").and_then(Value::as_str) == Some("tool_result") {
-                    let tool_use_id = content
-                        .get("tool_use_id")
-                        .and_then(Value::as_str)
-                        .unwrap_or_default();
-                    let is_error = content
-                        .get("is_error")
-                        .and_then(Value::as_bool)
-                        .unwrap_or(false);
-                    if is_error {
-                        let msg = extract_tool_result_text(content.get("content"));
-                        renderer
-                            .print_line(&format!("      → error: {}", trim_single_line(msg, 100)));
-                        renderer.set_status("thinking");
-                    } else if !last_bash_tool_id.is_empty() && tool_use_id == last_bash_tool_id {
-                        let msg = extract_tool_result_text(content.get("content"));
-                        if msg.trim().is_empty() {
-                            renderer.print_line("      → done");
-                        } else {
-                            renderer.print_line(&format!("      → {}", trim_single_line(msg, 100)));
-                        }
-                        renderer.set_status("thinking");
-                        last_bash_tool_id.clear();
-                    }
+            for block in blocks {
+                if block.get("type").and_then(Value::as_str) == Some("tool_result") {
+                    render_tool_result(block, state, renderer);
                 }
             }
-            0
         }
         "result" => {
             let cost = v
@@ -258,26 +350,236 @@ fn render_stream_event(
             let duration_ms = v.get("duration_ms").and_then(Value::as_u64).unwrap_or(0);
             let dur = format_duration(duration_ms / 1000);
             renderer.finish();
-            renderer.print_line(&format!(
-                "      ✓ result   {} ◆ {} ◆ {} tools",
+            renderer.result_line(&format!(
+                "{} {} {} {} {} tools",
                 dur,
+                renderer.ui.diamond(),
                 format_cost(cost),
-                current_tool_count
+                renderer.ui.diamond(),
+                state.tool_count
             ));
-            0
         }
-        _ => 0,
+        _ => {}
+    }
+}
+
+fn render_assistant_block(
+    block: &Value,
+    depth: usize,
+    verbose: bool,
+    state: &mut StreamState,
+    cwd: Option<&Path>,
+    renderer: &StreamRenderer,
+) {
+    let ctype = block.get("type").and_then(Value::as_str).unwrap_or("");
+    match ctype {
+        "tool_use" => {
+            let tool_id = block
+                .get("id")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_string();
+            let tool_name = block.get("name").and_then(Value::as_str).unwrap_or("tool");
+            let input = block.get("input").unwrap_or(&Value::Null);
+
+            if is_internal_tool(tool_name) && !verbose {
+                return;
+            }
+            state.tools.insert(
+                tool_id,
+                ToolCall {
+                    name: tool_name.to_string(),
+                    depth,
+                },
+            );
+            state.tool_count = state.tool_count.saturating_add(1);
+
+            let summary = tool_summary(tool_name, input, cwd);
+            renderer.ui.emit(
+                "tool_call",
+                json!({ "tool": tool_name, "summary": summary, "depth": depth }),
+            );
+            if summary.is_empty() {
+                renderer.step(depth, tool_name);
+                renderer.set_status(&format!("#{} {}", state.tool_count, tool_name));
+            } else {
+                renderer.step(
+                    depth,
+                    &format!("{:<8} {}", tool_name, renderer.trim(&summary, 80)),
+                );
+                renderer.set_status(&format!(
+                    "#{} {} {}",
+                    state.tool_count,
+                    tool_name,
+                    renderer.trim(&summary, 48)
+                ));
+            }
+        }
+        "thinking" => {
+            if renderer.has_spinner() {
+                renderer.set_status("thinking");
+            } else {
+                renderer.step(depth, "thinking...");
+            }
+        }
+        "text" if verbose => {
+            let text = block.get("text").and_then(Value::as_str).unwrap_or("");
+            if !text.trim().is_empty() {
+                renderer.step(depth, &format!("text: {}", renderer.trim(text, 100)));
+            }
+        }
+        _ => {}
+    }
+}
+
+fn render_tool_result(block: &Value, state: &mut StreamState, renderer: &StreamRenderer) {
+    if let Some((depth, line)) = tool_result_line(block, state, renderer.ui.ellipsis()) {
+        renderer.step(depth, &line);
+        renderer.set_status("thinking");
+    }
+}
+
+/// The line shown for a tool result and its depth. Errors are shown for
+/// every tool, including internal ones whose call was not tracked.
+fn tool_result_line(
+    block: &Value,
+    state: &mut StreamState,
+    ellipsis: &str,
+) -> Option<(usize, String)> {
+    let tool_use_id = block
+        .get("tool_use_id")
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let tool = state.tools.remove(tool_use_id);
+    let is_error = block
+        .get("is_error")
+        .and_then(Value::as_bool)
+        .unwrap_or(false);
+    let msg = extract_tool_result_text(block.get("content"));
+
+    if is_error {
+        let (depth, name) = tool.map_or((0, "tool".to_string()), |t| (t.depth, t.name));
+        return Some((
+            depth,
+            format!(
+                "error ({}): {}",
+                name,
+                trim_single_line(&msg, 100, ellipsis)
+            ),
+        ));
+    }
+    let tool = tool?;
+    if tool.name == "Bash" {
+        if msg.trim().is_empty() {
+            Some((tool.depth, "done".to_string()))
+        } else {
+            Some((tool.depth, trim_single_line(&msg, 100, ellipsis)))
+        }
+    } else if is_subagent_tool(&tool.name) {
+        Some((tool.depth, format!("{} done", tool.name)))
+    } else {
+        None
+    }
+}
+
+/// Tracks tool calls across stream events so results can be matched back to
+/// their tool and subagent activity can be nested under the spawning call.
+#[derive(Default)]
+struct StreamState {
+    tool_count: u32,
+    tools: HashMap<String, ToolCall>,
+}
+
+struct ToolCall {
+    name: String,
+    depth: usize,
+}
+
+impl StreamState {
+    fn depth_for(&self, parent_tool_use_id: Option<&str>) -> usize {
+        parent_tool_use_id
+            .filter(|id| !id.is_empty())
+            .and_then(|id| self.tools.get(id))
+            .map(|parent| parent.depth + 1)
+            .unwrap_or(0)
+    }
+}
+
+fn tool_summary(tool_name: &str, input: &Value, cwd: Option<&Path>) -> String {
+    let str_field = |key: &str| input.get(key).and_then(Value::as_str).unwrap_or("");
+    let path = compact_path(str_field("file_path"), cwd);
+
+    match tool_name {
+        "Edit" => {
+            let removed = count_lines(str_field("old_string"));
+            let added = count_lines(str_field("new_string"));
+            format!("{}  +{} -{}", path, added, removed)
+        }
+        "MultiEdit" => {
+            let (added, removed) = input
+                .get("edits")
+                .and_then(Value::as_array)
+                .map(|edits| {
+                    edits.iter().fold((0, 0), |(a, r), edit| {
+                        let text = |key: &str| edit.get(key).and_then(Value::as_str).unwrap_or("");
+                        (
+                            a + count_lines(text("new_string")),
+                            r + count_lines(text("old_string")),
+                        )
+                    })
+                })
+                .unwrap_or((0, 0));
+            format!("{}  +{} -{}", path, added, removed)
+        }
+        "Write" => format!("{}  +{} lines", path, count_lines(str_field("content"))),
+        "Read" => {
+            let offset = input.get("offset").and_then(Value::as_u64);
+            let limit = input.get("limit").and_then(Value::as_u64);
+            match (offset, limit) {
+                (Some(o), Some(l)) => format!("{}:{}-{}", path, o, o + l.saturating_sub(1)),
+                (Some(o), None) => format!("{}:{}-", path, o),
+                (None, Some(l)) => format!("{}:1-{}", path, l),
+                (None, None) => path,
+            }
+        }
+        _ if is_subagent_tool(tool_name) => {
+            let description = str_field("description");
+            let agent = str_field("subagent_type");
+            if agent.is_empty() {
+                description.to_string()
+            } else {
+                format!("[{}] {}", agent, description)
+            }
+        }
+        _ => {
+            let cmd = input
+                .as_object()
+                .and_then(|obj| {
+                    obj.get("command")
+                        .or_else(|| obj.get("file_path"))
+                        .or_else(|| obj.get("pattern"))
+                        .or_else(|| obj.get("url"))
+                })
+                .and_then(Value::as_str)
+                .unwrap_or("");
+            compact_path(cmd, cwd)
+        }
     }
 }
 
-struct StreamRenderer {
+fn count_lines(text: &str) -> usize {
+    text.lines().count()
+}
+
+struct StreamRenderer<'a> {
     spinner: Option<ProgressBar>,
+    ui: &'a Ui,
 }
 
-impl StreamRenderer {
-    fn new(verbose: bool) -> Self {
-        if verbose || !io::stdout().is_terminal() {
-            return Self { spinner: None };
+impl<'a> StreamRenderer<'a> {
+    fn new(verbose: bool, ui: &'a Ui) -> Self {
+        if verbose || !ui.interactive() {
+            return Self { spinner: None, ui };
         }
 
         let pb = ProgressBar::with_draw_target(None, ProgressDrawTarget::stdout());
@@ -293,7 +595,10 @@ impl StreamRenderer {
         pb.set_message("starting");
         pb.enable_steady_tick(Duration::from_millis(120));
 
-        Self { spinner: Some(pb) }
+        Self {
+            spinner: Some(pb),
+            ui,
+        }
     }
 
     fn has_spinner(&self) -> bool {
@@ -308,12 +613,29 @@ impl StreamRenderer {
 
     fn print_line(&self, line: &str) {
         if let Some(pb) = &self.spinner {
-            pb.println(line.to_string());
+            pb.println(line);
         } else {
-            println!("{line}");
+            self.ui.line(line);
         }
     }
 
+    fn step(&self, depth: usize, text: &str) {
+        self.print_line(&format!(
+            "      {}{} {}",
+            "  ".repeat(depth),
+            self.ui.arrow(),
+            text
+        ));
+    }
+
+    fn result_line(&self, text: &str) {
+        self.print_line(&format!("      {} result   {}", self.ui.check(), text));
+    }
+
+    fn trim(&self, input: &str, max_len: usize) -> String {
+        trim_single_line(input, max_len, self.ui.ellipsis())
+    }
+
     fn finish(&mut self) {
         if let Some(pb) = self.spinner.take() {
             pb.finish_and_clear();
@@ -321,6 +643,10 @@ impl StreamRenderer {
     }
 }
 
+fn is_subagent_tool(name: &str) -> bool {
+    matches!(name, "Task" | "Agent")
+}
+
 fn is_internal_tool(name: &str) -> bool {
     matches!(
         name,
@@ -333,7 +659,7 @@ fn is_internal_tool(name: &str) -> bool {
     )
 }
 
-fn trim_single_line(input: &str, max_len: usize) -> String {
+fn trim_single_line(input: &str, max_len: usize, ellipsis: &str) -> String {
     let trimmed = input
         .replace('\n', " ")
         .split_whitespace()
@@ -344,7 +670,7 @@ fn trim_single_line(input: &str, max_len: usize) -> String {
         for ch in trimmed.chars().take(max_len) {
             out.push(ch);
         }
-        out.push('…');
+        out.push_str(ellipsis);
         out
     } else {
         trimmed
@@ -363,11 +689,16 @@ fn compact_path(input: &str, cwd: Option<&Path>) -> String {
     out
 }
 
-fn extract_tool_result_text(content: Option<&Value>) -> &str {
-    if let Some(Value::String(s)) = content {
-        return s;
+fn extract_tool_result_text(content: Option<&Value>) -> String {
+    match content {
+        Some(Value::String(s)) => s.clone(),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        _ => String::new(),
     }
-    ""
 }
 
 fn parse_stream(lines: &[String]) -> ClaudeResult {
@@ -413,6 +744,8 @@ fn parse_stream(lines: &[String]) -> ClaudeResult {
         cost_usd: cost,
         duration_ms,
         claude_session_id: session_id,
+        policy_violations: vec![],
+        timed_out: false,
     }
 }
 
@@ -433,6 +766,8 @@ fn simulate(prompt: &str) -> ClaudeResult {
         cost_usd: 0.0,
         duration_ms: 0,
         claude_session_id: "dry-run-session".to_string(),
+        policy_violations: vec![],
+        timed_out: false,
     }
 }
 
@@ -467,3 +802,76 @@ pub fn parse_kv(output: &str, key: &str) -> Option<String> {
 pub fn has_tag(output: &str, tag: &str) -> bool {
     output.contains(&format!("<promise>{}</promise>", tag))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result_block(id: &str, is_error: bool, text: &str) -> Value {
+        json!({
+            "type": "tool_result",
+            "tool_use_id": id,
+            "is_error": is_error,
+            "content": text,
+        })
+    }
+
+    #[test]
+    fn target_commands_are_allowed_only_from_their_directory() {
+        let mut cfg = Config::default();
+        cfg.targets.push(crate::config::Target {
+            path: "packages/my app".to_string(),
+            verify_command: "npm run lint".to_string(),
+            ..Default::default()
+        });
+        let tools = allowed_tools(&cfg, Access::ReadOnly);
+        assert!(tools.contains(&"Bash((cd 'packages/my app' && npm run lint))".to_string()));
+        assert!(tools.contains(&"Bash(npm run lint:*)".to_string()));
+        assert!(!tools.iter().any(|t| t.starts_with("Bash(cd ")));
+    }
+
+    #[test]
+    fn errors_of_untracked_tools_are_shown() {
+        let mut state = StreamState::default();
+        assert_eq!(
+            tool_result_line(
+                &result_block("t1", true, "bad todo list"),
+                &mut state,
+                "..."
+            ),
+            Some((0, "error (tool): bad todo list".to_string()))
+        );
+        assert_eq!(
+            tool_result_line(&result_block("t1", false, "ok"), &mut state, "..."),
+            None
+        );
+    }
+
+    #[test]
+    fn results_of_tracked_tools_keep_their_depth() {
+        let mut state = StreamState::default();
+        state.tools.insert(
+            "t1".to_string(),
+            ToolCall {
+                name: "Bash".to_string(),
+                depth: 1,
+            },
+        );
+        state.tools.insert(
+            "t2".to_string(),
+            ToolCall {
+                name: "Read".to_string(),
+                depth: 0,
+            },
+        );
+        assert_eq!(
+            tool_result_line(&result_block("t1", false, ""), &mut state, "..."),
+            Some((1, "done".to_string()))
+        );
+        assert_eq!(
+            tool_result_line(&result_block("t2", true, "no such file"), &mut state, "..."),
+            Some((0, "error (Read): no such file".to_string()))
+        );
+        assert!(state.tools.is_empty());
+    }
+}
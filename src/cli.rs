@@ -1,10 +1,23 @@
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(name = "spec-loop", about = "spec-driven autonomous development loop")]
 pub struct Cli {
     #[command(subcommand)]
     pub command: Option<Command>,
+    /// Output format
+    #[arg(long, global = true, value_enum, default_value_t = OutputMode::Human)]
+    pub output: OutputMode,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputMode {
+    /// Colored, box-drawn terminal output with a live spinner
+    Human,
+    /// ASCII-only output without colors or spinner
+    Plain,
+    /// One JSON event per line for wrappers and log parsers
+    Jsonl,
 }
 
 #[derive(Subcommand, Debug)]
@@ -15,6 +28,18 @@ pub enum Command {
     Run(RunArgs),
     /// Show current spec progress
     Status,
+    /// Scaffold a new spec or task from the templates
+    New(NewArgs),
+    /// Generate task files from spec.md
+    Plan(PlanArgs),
+    /// List, inspect and change task status
+    Task(TaskArgs),
+    /// Inspect prompt templates
+    Prompt(PromptArgs),
+    /// Undo the commits of a recorded iteration
+    Rollback(RollbackArgs),
+    /// Manage spec-loop.toml
+    Config(ConfigArgs),
     /// Show version
     Version,
 }
@@ -48,6 +73,12 @@ pub struct RunArgs {
     /// Full stream output
     #[arg(long)]
     pub verbose: bool,
+    /// Apply a `[profile.<name>]` block from the config
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Stop once this run has cost this many USD (0 = no limit)
+    #[arg(long)]
+    pub budget: Option<f64>,
 }
 
 #[derive(Args, Debug, Clone)]
@@ -64,4 +95,227 @@ pub struct InitArgs {
     /// Override test command
     #[arg(long = "test-cmd")]
     pub test_cmd: Option<String>,
+    /// Replace the installed prompt templates with this version's defaults
+    #[arg(long = "update-templates")]
+    pub update_templates: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct NewArgs {
+    #[command(subcommand)]
+    pub command: NewCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum NewCommand {
+    /// Create .agents/specs/<slug>/ with spec.md and progress.md
+    Spec(NewSpecArgs),
+    /// Create the next numbered task in a spec
+    Task(NewTaskArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct NewSpecArgs {
+    /// Feature name
+    pub name: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct NewTaskArgs {
+    /// Task title
+    pub title: String,
+    /// Spec directory (auto-detects if one active)
+    #[arg(long)]
+    pub spec: Option<String>,
+    /// Task numbers this task depends on, e.g. "1,2" or "none" (prompted when omitted)
+    #[arg(long)]
+    pub depends: Option<String>,
+    /// Task size: S, M or L (prompted when omitted)
+    #[arg(long)]
+    pub size: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TaskArgs {
+    /// Spec directory (auto-detects if one active)
+    #[arg(long, global = true)]
+    pub spec: Option<String>,
+    #[command(subcommand)]
+    pub command: TaskCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TaskCommand {
+    /// List tasks with status, size and dependencies
+    List,
+    /// Show a task's status, dependencies and contents
+    Show(TaskRefArgs),
+    /// Move a task to another status
+    SetStatus(TaskSetStatusArgs),
+    /// Move a task back to pending
+    Reset(TaskReasonArgs),
+    /// Mark a task done without running it
+    Skip(TaskSkipArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TaskRefArgs {
+    /// Task number, file name or path
+    pub task: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TaskSetStatusArgs {
+    /// Task number, file name or path
+    pub task: String,
+    /// pending, in-progress, in-review, done or blocked
+    pub status: String,
+    /// Why the status changed (required for done)
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TaskReasonArgs {
+    /// Task number, file name or path
+    pub task: String,
+    /// Why the status changed
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct TaskSkipArgs {
+    /// Task number, file name or path
+    pub task: String,
+    /// Why the task is skipped
+    #[arg(long)]
+    pub reason: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PlanArgs {
+    /// Spec directory (auto-detects if only one exists)
+    #[arg(long)]
+    pub spec: Option<String>,
+    /// Replace existing task files
+    #[arg(long)]
+    pub force: bool,
+    /// Full stream output
+    #[arg(long)]
+    pub verbose: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct RollbackArgs {
+    /// Iteration number from the session log
+    #[arg(long)]
+    pub iteration: u32,
+    /// Spec directory (auto-detects if only one exists)
+    #[arg(long)]
+    pub spec: Option<String>,
+    /// Session directory (default: the spec's latest session)
+    #[arg(long)]
+    pub session: Option<String>,
+    /// reset or revert (default: ROLLBACK_ON_FAIL, else reset)
+    #[arg(long)]
+    pub mode: Option<String>,
+    /// Status to move the task to: pending or blocked
+    #[arg(long, default_value = "pending")]
+    pub status: String,
+    /// Why the iteration is rolled back (recorded in progress.md)
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ConfigCommand {
+    /// Print every effective setting
+    Show(ConfigShowArgs),
+    /// Print one effective setting
+    Get(ConfigGetArgs),
+    /// Change a setting in spec-loop.toml, keeping comments
+    Set(ConfigSetArgs),
+    /// Check the configuration and exit non-zero if it is invalid
+    Validate,
+    /// Convert .speclooprc into spec-loop.toml
+    Migrate(ConfigMigrateArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigShowArgs {
+    /// Also show where each value came from
+    #[arg(long)]
+    pub origin: bool,
+    /// Show the settings with this profile applied
+    #[arg(long)]
+    pub profile: Option<String>,
+    /// Show the settings with this spec's overrides applied
+    #[arg(long)]
+    pub spec: Option<String>,
+    /// Also apply this task's overrides (number, file name or path)
+    #[arg(long)]
+    pub task: Option<String>,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigGetArgs {
+    /// `section.name` or legacy name (e.g. limits.max_loops or MAX_LOOPS)
+    pub key: String,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigSetArgs {
+    /// `section.name` or legacy name (e.g. limits.max_loops or MAX_LOOPS)
+    pub key: String,
+    pub value: String,
+    /// Write to the user-global config instead of the project's
+    #[arg(long)]
+    pub global: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct ConfigMigrateArgs {
+    /// Overwrite an existing spec-loop.toml
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PromptArgs {
+    #[command(subcommand)]
+    pub command: PromptCommand,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PromptCommand {
+    /// Print the resolved prompt for a phase (build, review, fix, plan)
+    Show(PromptShowArgs),
+}
+
+#[derive(Args, Debug, Clone)]
+pub struct PromptShowArgs {
+    /// Prompt name: build, review, fix or plan
+    pub name: String,
+    /// Spec directory (auto-detects if one active)
+    #[arg(long)]
+    pub spec: Option<String>,
+    /// Task file to render for (defaults to the next task)
+    #[arg(long)]
+    pub task: Option<String>,
+    /// Base commit for the review range
+    #[arg(long = "before-sha")]
+    pub before_sha: Option<String>,
+    /// File whose contents fill the review findings
+    #[arg(long)]
+    pub findings: Option<String>,
+    /// Print the unrendered template instead
+    #[arg(long)]
+    pub raw: bool,
 }
@@ -4,49 +4,88 @@ use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::time::Instant;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::json;
 
 use crate::circuit_breaker::CircuitBreaker;
-use crate::claude::{has_tag, parse_kv, run_claude};
-use crate::cli::{InitArgs, RunArgs};
+use crate::claude::{
+    allowed_tools, has_tag, parse_kv, resume_claude, run_claude, Access, ClaudeResult,
+};
+use crate::cli::{
+    ConfigArgs, ConfigCommand, InitArgs, NewArgs, NewCommand, NewSpecArgs, NewTaskArgs, PlanArgs,
+    PromptArgs, PromptCommand, RollbackArgs, RunArgs, TaskArgs, TaskCommand,
+};
 use crate::config::{
-    detect_project_type, detect_test_command, detect_verify_command, load_config, Config,
-    DEFAULT_MAX_LOOPS, DEFAULT_MAX_REVIEW_FIX_LOOPS,
+    detect_project_type, detect_targets, detect_test_command, detect_verify_command, load_config,
+    load_layers, lookup_key, migrate_legacy_config, scoped_config, set_in_file, toml_value,
+    user_config_path, Config, Target, CONFIG_FILE, KEYS, LEGACY_CONFIG_FILE,
 };
 use crate::constants::{
-    EXIT_BLOCKED, EXIT_CIRCUIT_OPEN, EXIT_MAX_ITERATIONS, EXIT_OK, SPECLOOP_VERSION,
+    EXIT_BLOCKED, EXIT_CIRCUIT_OPEN, EXIT_ERROR, EXIT_MAX_ITERATIONS, EXIT_OK, SPECLOOP_VERSION,
+};
+use crate::contract::Contract;
+use crate::policy::{Policy, Violation};
+use crate::pr::pr_description;
+use crate::prompts::{
+    installed_templates, load_template, render_prompt, PromptContext, PromptKind, TemplateState,
+    PROMPT_TEMPLATES_DIR, TASK_TEMPLATE_PATH,
+};
+use crate::review::{
+    findings_checklist, merge_consensus, parse_review, resolved_ids, ReviewFinding, ReviewReport,
 };
-use crate::prompts::{build_prompt, fix_prompt, review_prompt};
+use crate::rollback::{rollback, ROLLBACK_MODES, ROLLBACK_STATUSES};
+use crate::scope::{check_scope, claimed_by_other_tasks, ScopeReport};
 use crate::session::{
     append_iteration_log, append_run_invocation_header, append_run_iteration_header,
-    append_run_phase, clear_resume_state, ensure_session_initialized, finalize_session,
-    load_resume_state, register_claude_session, save_resume_state, session_continuation_for_spec,
-    session_iterations_count, session_started_epoch, session_total_cost, session_total_iterations,
-    IterationLogInput,
+    append_run_note, append_run_phase, clear_resume_state, ensure_session_initialized,
+    finalize_session, load_resume_state, read_session_json, record_permissions, record_review,
+    record_session_base, record_status_change, record_task_base, register_claude_session,
+    save_resume_state, session_base_for_spec, session_continuation_for_spec,
+    session_iterations_count, session_json_path, session_latest_for_spec, session_started_epoch,
+    session_total_cost, session_total_iterations, task_base, IterationLogInput, SessionReview,
+    SessionStatusChange,
 };
 use crate::spec::{
-    count_active, count_remaining, count_status, count_total, find_next_task, find_open_task,
-    get_spec_name, get_task_name, get_task_status, list_spec_dirs, resolve_spec_dir,
-    set_task_status, status_signature, TaskStatus,
+    append_progress, append_task_history, count_active, count_remaining, count_status, count_total,
+    find_next_task, find_open_task, find_task, get_spec_name, get_task_depends, get_task_name,
+    get_task_number, get_task_size, get_task_status, list_spec_dirs, list_task_files,
+    next_task_number, resolve_spec_dir, set_field, set_task_status, status_signature,
+    task_statuses, unmet_dependencies, validate_task_files, write_task_status, TaskStatus,
 };
+use crate::spec_guard::{committed_spec_files, uncommit_spec_files, SpecSnapshot};
 use crate::ui::Ui;
 use crate::util::{
-    command_exists, current_branch, format_cost, format_duration, head_sha, now_stamp, slugify,
+    changed_files_since, command_exists, current_branch, format_cost, format_duration, git,
+    head_sha, now_human, now_iso, now_stamp, slugify, today,
+};
+use crate::worktree::{
+    commit_paths, commit_subject, is_git_repo, operation_in_progress, spec_branch, stash_paths,
+    switch_branch, unrelated_changes,
 };
 
 pub fn cmd_init(args: &InitArgs, ui: &Ui) -> Result<i32> {
     ui.print_header(SPECLOOP_VERSION);
-    println!();
+    ui.blank();
 
-    let config_path = Path::new(".speclooprc");
-    if config_path.exists() && !args.force {
-        bail!(".speclooprc already exists. Use --force to overwrite.");
+    if args.update_templates {
+        update_templates(ui)?;
+        return Ok(EXIT_OK);
+    }
+    if Path::new(CONFIG_FILE).exists() && !args.force {
+        bail!("{} already exists. Use --force to overwrite.", CONFIG_FILE);
+    }
+    if Path::new(LEGACY_CONFIG_FILE).exists() && !args.force {
+        bail!(
+            "{} already exists. Run 'spec-loop config migrate' to convert it, or use --force.",
+            LEGACY_CONFIG_FILE
+        );
     }
 
     let cwd = std::env::current_dir().context("failed to get current directory")?;
     let mut project_type = detect_project_type(&cwd);
-    let mut verify_cmd = detect_verify_command(&project_type);
-    let mut test_cmd = detect_test_command(&project_type);
+    let mut verify_cmd = detect_verify_command(&project_type, &cwd);
+    let mut test_cmd = detect_test_command(&project_type, &cwd);
+    let targets = detect_targets(&cwd);
 
     if let Some(v) = &args.verify_cmd {
         verify_cmd = v.clone();
@@ -57,7 +96,7 @@ pub fn cmd_init(args: &InitArgs, ui: &Ui) -> Result<i32> {
 
     if !args.no_wizard && io::stdin().is_terminal() && io::stdout().is_terminal() {
         ui.phase("Initializing spec-loop");
-        println!();
+        ui.blank();
 
         ui.step_info(&format!(
             "Detected project type: {}",
@@ -80,15 +119,22 @@ pub fn cmd_init(args: &InitArgs, ui: &Ui) -> Result<i32> {
         test_cmd = prompt_with_default("Test command", &test_cmd)?;
     }
 
-    write_speclooprc(&project_type, &verify_cmd, &test_cmd)?;
-    ui.step_ok("Created .speclooprc");
+    write_config_file(&project_type, &verify_cmd, &test_cmd, &targets)?;
+    ui.step_ok(&format!("Created {}", CONFIG_FILE));
+    for target in &targets {
+        ui.step_info(&format!(
+            "Added target {} ({})",
+            ui.bold(&target.path),
+            target.project_type
+        ));
+    }
 
     fs::create_dir_all(".agents/specs").context("failed creating .agents/specs")?;
     fs::create_dir_all(".spec-loop/sessions").context("failed creating .spec-loop/sessions")?;
     ui.step_ok("Created .agents/ and .spec-loop/ directories");
 
     install_templates()?;
-    ui.step_ok("Copied templates to .agents/templates/");
+    ui.step_ok("Copied templates to .agents/templates/ (prompts in .agents/templates/prompts/)");
 
     if !Path::new(".agents/decisions.md").exists() {
         fs::write(
@@ -107,9 +153,9 @@ pub fn cmd_init(args: &InitArgs, ui: &Ui) -> Result<i32> {
     update_gitignore()?;
     ui.step_ok("Updated .gitignore");
 
-    println!();
+    ui.blank();
     ui.step_ok(&ui.bold("spec-loop initialized"));
-    println!();
+    ui.blank();
     ui.step_info("Next: edit AGENTS.md with your project's rules");
     ui.step_info(&format!(
         "Then:  run {} to create your first feature spec",
@@ -126,14 +172,14 @@ pub fn cmd_status(ui: &Ui) -> Result<i32> {
 
     let specs_dir = Path::new(&cfg.specs_dir);
     if !specs_dir.is_dir() {
-        println!();
+        ui.blank();
         ui.step_info("No specs directory found. Run 'spec-loop init' first.");
         return Ok(EXIT_OK);
     }
 
     let all_specs = list_spec_dirs(specs_dir)?;
     if all_specs.is_empty() {
-        println!();
+        ui.blank();
         ui.step_info("No specs found. Use /spec-loop-spec to create a feature spec.");
         return Ok(EXIT_OK);
     }
@@ -197,295 +243,1326 @@ pub fn cmd_status(ui: &Ui) -> Result<i32> {
     }
 
     if let Some(lines) = recent_commits() {
-        println!();
-        println!("  {}", ui.dim("Recent commits"));
+        ui.blank();
+        ui.line(&format!("  {}", ui.dim("Recent commits")));
         for line in lines {
-            println!("  {}", ui.dim(&format!("  {}", line)));
+            ui.line(&format!("  {}", ui.dim(&format!("  {}", line))));
         }
     }
 
     if !found_active {
-        println!();
+        ui.blank();
         ui.step_info("No active specs (all tasks done or none created).");
         ui.step_info("Use /spec-loop-spec to create a new feature spec.");
     }
 
-    println!();
+    ui.blank();
     Ok(EXIT_OK)
 }
 
-pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
-    let cfg = load_config(Some(args))?;
-    preflight(&cfg)?;
-    let panel_width = ui.panel_width(52, 72);
+pub fn cmd_new(args: &NewArgs, ui: &Ui) -> Result<i32> {
+    let cfg = load_config(None)?;
+    match &args.command {
+        NewCommand::Spec(spec) => new_spec(&cfg, spec, ui),
+        NewCommand::Task(task) => new_task(&cfg, task, ui),
+    }
+}
 
-    let session_dir = Path::new(&cfg.session_dir);
-    let mut cb = CircuitBreaker::load(session_dir)?;
+fn new_spec(cfg: &Config, args: &NewSpecArgs, ui: &Ui) -> Result<i32> {
+    let name = args.name.trim();
+    if name.is_empty() {
+        bail!("spec name must not be empty");
+    }
+    let spec_dir = Path::new(&cfg.specs_dir).join(slugify(name));
+    let spec_file = spec_dir.join("spec.md");
+    if spec_file.exists() {
+        bail!("{} already exists", spec_file.display());
+    }
+    fs::create_dir_all(spec_dir.join("tasks"))
+        .with_context(|| format!("failed to create {}", spec_dir.display()))?;
 
-    let mut spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), args.spec.as_deref())?;
-    let mut spec_name = get_spec_name(&spec_dir);
+    let mut spec = read_template(
+        ".agents/templates/spec.md",
+        include_str!("../.agents/templates/spec.md"),
+    );
+    spec = replace_heading(&spec, "# Spec:", &format!("# Spec: {}", name));
+    spec = set_field(&spec, "Status", "draft");
+    spec = set_field(&spec, "Created", &today());
+    fs::write(&spec_file, spec)
+        .with_context(|| format!("failed to write {}", spec_file.display()))?;
+
+    let progress_file = spec_dir.join("progress.md");
+    if !progress_file.exists() {
+        let progress = read_template(
+            ".agents/templates/progress.md",
+            include_str!("../.agents/templates/progress.md"),
+        );
+        let progress = replace_heading(&progress, "# Progress:", &format!("# Progress: {}", name));
+        fs::write(&progress_file, progress)
+            .with_context(|| format!("failed to write {}", progress_file.display()))?;
+    }
 
-    let mut loop_index: u32;
-    let mut resume_phase = String::new();
-    let mut resume_before_sha = String::new();
-    let session_path: PathBuf;
-    let mut mode = "new";
+    ui.step_ok(&format!("Created {}", spec_file.display()));
+    ui.step_info(&format!(
+        "Next: fill in spec.md, then run {} or add tasks with {}",
+        ui.bold(&format!("spec-loop plan --spec {}", spec_dir.display())),
+        ui.bold("spec-loop new task")
+    ));
+    Ok(EXIT_OK)
+}
 
-    if args.resume {
-        if let Some(state) = load_resume_state(&cfg) {
-            spec_dir = PathBuf::from(&state.spec_dir);
-            spec_name = get_spec_name(&spec_dir);
-            session_path = PathBuf::from(&state.session_path);
-            loop_index = state.loop_index.max(1);
-            resume_phase = state.phase;
-            resume_before_sha = state.before_sha;
-            mode = "resume";
-            ui.step_info(&format!(
-                "Resuming session for {} at iteration {} (phase: {})",
-                spec_name, loop_index, resume_phase
-            ));
-        } else {
-            let continuation = session_continuation_for_spec(session_dir, &spec_dir);
-            if let Some(path) = continuation {
-                session_path = path;
-                loop_index = session_iterations_count(&session_path) + 1;
-                mode = "continue";
-                ui.step_info(&format!(
-                    "Continuing session {} at iteration {}",
-                    session_path
-                        .file_name()
-                        .map(|s| s.to_string_lossy().to_string())
-                        .unwrap_or_else(|| "unknown".to_string()),
-                    loop_index
-                ));
+fn new_task(cfg: &Config, args: &NewTaskArgs, ui: &Ui) -> Result<i32> {
+    let title = args.title.trim();
+    if title.is_empty() {
+        bail!("task title must not be empty");
+    }
+    let spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), args.spec.as_deref())?;
+    let number = next_task_number(&spec_dir);
+    let existing: Vec<u32> = list_task_files(&spec_dir)
+        .iter()
+        .filter_map(|f| get_task_number(f))
+        .collect();
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+
+    let depends = match &args.depends {
+        Some(v) => v.clone(),
+        None if interactive => {
+            let default = if number > 1 {
+                (number - 1).to_string()
             } else {
-                session_path = new_session_path(&cfg, &spec_name);
-                loop_index = 1;
-            }
+                "none".to_string()
+            };
+            prompt_with_default("Depends on (task numbers or none)", &default)?
         }
+        None => "none".to_string(),
+    };
+    let depends = if depends.trim().eq_ignore_ascii_case("none") || depends.trim().is_empty() {
+        vec![]
     } else {
-        let continuation = session_continuation_for_spec(session_dir, &spec_dir);
-        if let Some(path) = continuation {
-            session_path = path;
-            loop_index = session_iterations_count(&session_path) + 1;
-            mode = "continue";
-            ui.step_info(&format!(
-                "Continuing session {} at iteration {}",
-                session_path
-                    .file_name()
-                    .map(|s| s.to_string_lossy().to_string())
-                    .unwrap_or_else(|| "unknown".to_string()),
-                loop_index
-            ));
-        } else {
-            session_path = new_session_path(&cfg, &spec_name);
-            loop_index = 1;
+        depends
+            .split([',', ' '])
+            .filter(|d| !d.is_empty())
+            .map(|d| {
+                d.trim()
+                    .parse::<u32>()
+                    .map_err(|_| anyhow!("invalid task number in --depends: '{}'", d))
+            })
+            .collect::<Result<Vec<u32>>>()?
+    };
+    for dep in &depends {
+        if !existing.contains(dep) {
+            bail!("task {} does not exist in {}", dep, spec_dir.display());
         }
     }
 
-    ensure_session_initialized(&session_path, &spec_dir, &spec_name, &cfg, SPECLOOP_VERSION)?;
-
-    let total_tasks = count_total(&spec_dir);
-    let done_tasks = count_status(&spec_dir, TaskStatus::Done);
-    let pending_tasks = count_status(&spec_dir, TaskStatus::Pending);
-    let in_progress_tasks = count_status(&spec_dir, TaskStatus::InProgress);
-    let in_review_tasks = count_status(&spec_dir, TaskStatus::InReview);
-    let blocked_tasks = count_status(&spec_dir, TaskStatus::Blocked);
-    let remaining_tasks = count_remaining(&spec_dir);
+    let size = match &args.size {
+        Some(v) => v.clone(),
+        None if interactive => prompt_with_default("Size (S, M, L)", "M")?,
+        None => "M".to_string(),
+    }
+    .trim()
+    .to_uppercase();
+    if !["S", "M", "L"].contains(&size.as_str()) {
+        bail!("size must be S, M or L (got: {})", size);
+    }
 
-    ui.print_header(SPECLOOP_VERSION);
-    ui.box_header("Spec", panel_width);
-    ui.box_empty(panel_width);
-    ui.box_line(&format!("  {}", spec_name), panel_width);
-    ui.box_line(
-        &format!(
-            "  {} {} {} remaining",
-            ui.progress_bar(done_tasks, total_tasks, 16, "done"),
-            ui.diamond(),
-            remaining_tasks
-        ),
-        panel_width,
-    );
-    ui.box_line(
-        &format!(
-            "  {} pending {} {} in-progress",
-            pending_tasks,
-            ui.diamond(),
-            in_progress_tasks
-        ),
-        panel_width,
+    let mut task = read_template(
+        TASK_TEMPLATE_PATH,
+        include_str!("../.agents/templates/task.md"),
     );
-    ui.box_line(
-        &format!(
-            "  {} in-review {} {} blocked",
-            in_review_tasks,
-            ui.diamond(),
-            blocked_tasks
-        ),
-        panel_width,
+    task = replace_heading(&task, "# Task", &format!("# Task {}: {}", number, title));
+    task = set_field(&task, "Status", TaskStatus::Pending.as_str());
+    task = set_field(&task, "Size", &size);
+    task = set_field(
+        &task,
+        "Depends on",
+        &if depends.is_empty() {
+            "none".to_string()
+        } else {
+            depends
+                .iter()
+                .map(u32::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        },
     );
-    ui.box_line(&format!("  {}", current_branch()), panel_width);
-    if cfg.max_tasks_per_run > 0 {
-        ui.box_line(
-            &format!("  Task budget  {} this run", cfg.max_tasks_per_run),
-            panel_width,
-        );
-    }
-    ui.box_empty(panel_width);
-    ui.box_footer(panel_width);
-
-    let mut total_cost = session_total_cost(&session_path);
-    let total_iterations_base = session_total_iterations(&session_path);
-    let started_epoch = session_started_epoch(&session_path);
-    let mut iterations_completed = 0_u32;
 
-    append_run_invocation_header(
-        &session_path,
-        mode,
-        args.once,
-        cfg.max_tasks_per_run,
-        cfg.max_loops,
-        args.skip_review,
-    )?;
-
-    while loop_index <= cfg.max_loops {
-        if !cb.check(cfg.cb_cooldown_minutes, ui)? {
-            finalize_session(
-                &session_path,
-                started_epoch,
-                total_cost,
-                "CIRCUIT_OPEN",
-                total_iterations_base + iterations_completed,
-            )?;
-            clear_resume_state(&cfg);
-            return Ok(EXIT_CIRCUIT_OPEN);
-        }
-
-        let remaining = count_remaining(&spec_dir);
-        if remaining == 0 {
-            loop_complete(
-                &session_path,
-                &spec_name,
-                total_tasks,
-                started_epoch,
-                total_cost,
-                total_iterations_base + iterations_completed,
-                ui,
-            )?;
-            clear_resume_state(&cfg);
-            return Ok(EXIT_OK);
-        }
+    let task_file = spec_dir
+        .join("tasks")
+        .join(format!("{:02}-{}.md", number, slugify(title)));
+    fs::create_dir_all(spec_dir.join("tasks"))
+        .with_context(|| format!("failed to create {}/tasks", spec_dir.display()))?;
+    fs::write(&task_file, task)
+        .with_context(|| format!("failed to write {}", task_file.display()))?;
 
-        if cfg.max_tasks_per_run > 0 && iterations_completed >= cfg.max_tasks_per_run {
-            ui.step_ok(&format!(
-                "Reached task budget ({}) for this run",
-                cfg.max_tasks_per_run
-            ));
-            finalize_session(
-                &session_path,
-                started_epoch,
-                total_cost,
-                "TASK_LIMIT",
-                total_iterations_base + iterations_completed,
-            )?;
-            clear_resume_state(&cfg);
-            return Ok(EXIT_OK);
-        }
+    ui.step_ok(&format!("Created {}", task_file.display()));
+    ui.step_info("Next: fill in What, How, Files and Acceptance");
+    Ok(EXIT_OK)
+}
 
-        let next_task_file = find_next_task(&spec_dir).or_else(|| find_open_task(&spec_dir));
-        let next_task_name = next_task_file
-            .as_ref()
-            .map(|p| get_task_name(p))
-            .unwrap_or_default();
-        let next_task_status = next_task_file
-            .as_ref()
-            .map(|p| get_task_status(p))
-            .unwrap_or(TaskStatus::Unknown);
+/// Project copy of a template, falling back to the built-in one.
+fn read_template(path: &str, fallback: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|_| fallback.to_string())
+}
 
-        if next_task_name.is_empty() {
-            ui.separator(
-                &format!("Iteration {} of {}", loop_index, cfg.max_loops),
-                panel_width,
-            );
+/// Replace the first line starting with `prefix` by `heading`.
+fn replace_heading(content: &str, prefix: &str, heading: &str) -> String {
+    let mut replaced = false;
+    let mut out = String::with_capacity(content.len() + heading.len());
+    for line in content.lines() {
+        if !replaced && line.starts_with(prefix) {
+            out.push_str(heading);
+            replaced = true;
         } else {
-            ui.separator(
-                &format!(
-                    "Iteration {} {} {}",
-                    loop_index,
-                    ui.diamond(),
-                    next_task_name
-                ),
-                panel_width,
-            );
+            out.push_str(line);
         }
+        out.push('\n');
+    }
+    out
+}
 
-        append_run_iteration_header(
-            &session_path,
-            loop_index,
-            if next_task_name.is_empty() {
-                None
-            } else {
-                Some(next_task_name.as_str())
-            },
-        )?;
-
-        let iteration_start = Instant::now();
-        let remaining_before = count_remaining(&spec_dir);
-        let signature_before = status_signature(&spec_dir);
-
-        let before_sha: String;
-        let after_build_sha: String;
-        let mut build_cost = 0.0_f64;
-        let review_cost: f64;
-        let mut fix_total_cost = 0.0_f64;
-        let mut must_fix_count: u32;
-        let mut should_fix_count: u32;
-        let mut review_status: String;
-        let mut review_findings: String;
+pub fn cmd_task(args: &TaskArgs, ui: &Ui) -> Result<i32> {
+    let cfg = load_config(None)?;
+    let spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), args.spec.as_deref())?;
 
-        if resume_phase == "review"
-            || resume_phase == "fix"
-            || next_task_status == TaskStatus::InReview
-        {
-            ui.step_info(&format!(
-                "Skipping build (already completed, resuming at {})",
-                if next_task_status == TaskStatus::InReview {
-                    "review"
+    match &args.command {
+        TaskCommand::List => {
+            let tasks = list_task_files(&spec_dir);
+            if tasks.is_empty() {
+                ui.step_info(&format!("No tasks in {}", spec_dir.display()));
+                return Ok(EXIT_OK);
+            }
+            let done = count_status(&spec_dir, TaskStatus::Done);
+            ui.blank();
+            ui.line(&format!(
+                "  {}  {}",
+                ui.bold(&get_spec_name(&spec_dir)),
+                ui.dim(&ui.progress_bar(done, tasks.len(), 16, "done"))
+            ));
+            ui.blank();
+            for task in &tasks {
+                let status = get_task_status(task);
+                let depends = get_task_depends(task);
+                let unmet = unmet_dependencies(&spec_dir, task);
+                ui.emit(
+                    "task",
+                    json!({
+                        "file": task.display().to_string(),
+                        "name": get_task_name(task),
+                        "status": status.as_str(),
+                        "size": get_task_size(task),
+                        "depends": depends,
+                        "unmet": unmet,
+                    }),
+                );
+                let deps = if depends.is_empty() {
+                    String::new()
+                } else if unmet.is_empty() {
+                    format!("  after {}", join_numbers(&depends))
                 } else {
-                    &resume_phase
+                    format!("  waits on {}", join_numbers(&unmet))
+                };
+                ui.line(&format!(
+                    "  {:<12} {:<2} {}{}",
+                    status.as_str(),
+                    get_task_size(task),
+                    get_task_name(task),
+                    ui.dim(&deps)
+                ));
+            }
+            Ok(EXIT_OK)
+        }
+        TaskCommand::Show(show) => {
+            let task = find_task(&spec_dir, &show.task)?;
+            let unmet = unmet_dependencies(&spec_dir, &task);
+            ui.blank();
+            ui.line(&format!("  {}", ui.bold(&get_task_name(&task))));
+            ui.line(&format!("  {}", ui.dim(&task.display().to_string())));
+            ui.blank();
+            ui.line(&format!(
+                "  Status      {}",
+                get_task_status(&task).as_str()
+            ));
+            ui.line(&format!("  Size        {}", get_task_size(&task)));
+            ui.line(&format!(
+                "  Depends on  {}{}",
+                join_numbers(&get_task_depends(&task)),
+                if unmet.is_empty() {
+                    String::new()
+                } else {
+                    format!(" (unmet: {})", join_numbers(&unmet))
                 }
             ));
-            before_sha = if next_task_status == TaskStatus::InReview {
-                String::new()
-            } else {
-                resume_before_sha.clone()
-            };
-            after_build_sha = head_sha();
-            save_resume_state(
+            ui.blank();
+            let content = fs::read_to_string(&task)
+                .with_context(|| format!("failed to read {}", task.display()))?;
+            ui.emit(
+                "task",
+                json!({
+                    "file": task.display().to_string(),
+                    "name": get_task_name(&task),
+                    "status": get_task_status(&task).as_str(),
+                    "size": get_task_size(&task),
+                    "depends": get_task_depends(&task),
+                    "unmet": unmet,
+                    "content": content,
+                }),
+            );
+            ui.line(content.trim_end());
+            Ok(EXIT_OK)
+        }
+        TaskCommand::SetStatus(set) => {
+            let status = TaskStatus::from_str(&set.status);
+            if !TaskStatus::SETTABLE.contains(&status) {
+                bail!(
+                    "unknown status '{}' (expected one of: {})",
+                    set.status,
+                    TaskStatus::SETTABLE.map(TaskStatus::as_str).join(", ")
+                );
+            }
+            if status == TaskStatus::Done && set.reason.is_none() {
+                bail!("marking a task done by hand requires --reason");
+            }
+            let task = find_task(&spec_dir, &set.task)?;
+            let from = get_task_status(&task);
+            if status == TaskStatus::Done && !from.can_transition(status) {
+                bail!(
+                    "cannot move {} from {} to done; use `spec-loop task skip {} --reason ...` to mark it done without review",
+                    get_task_name(&task),
+                    from.as_str(),
+                    set.task
+                );
+            }
+            change_task_status(
+                ui,
                 &cfg,
                 &spec_dir,
-                loop_index,
-                &session_path,
-                if next_task_status == TaskStatus::InReview {
-                    "review"
-                } else {
-                    &resume_phase
+                &task,
+                status,
+                set.reason.as_deref(),
+                "set-status",
+            )
+        }
+        TaskCommand::Reset(reset) => {
+            let task = find_task(&spec_dir, &reset.task)?;
+            change_task_status(
+                ui,
+                &cfg,
+                &spec_dir,
+                &task,
+                TaskStatus::Pending,
+                reset.reason.as_deref(),
+                "reset",
+            )
+        }
+        TaskCommand::Skip(skip) => {
+            let task = find_task(&spec_dir, &skip.task)?;
+            change_task_status(
+                ui,
+                &cfg,
+                &spec_dir,
+                &task,
+                TaskStatus::Done,
+                Some(&skip.reason),
+                "skip",
+            )
+        }
+    }
+}
+
+pub fn cmd_rollback(args: &RollbackArgs, ui: &Ui) -> Result<i32> {
+    let cfg = load_config(None)?;
+    let mode = match &args.mode {
+        Some(mode) => mode.as_str(),
+        None if cfg.rollback_on_fail == "revert" => "revert",
+        None => "reset",
+    };
+    if !ROLLBACK_MODES.contains(&mode) {
+        bail!(
+            "unknown mode '{}' (expected one of: {})",
+            mode,
+            ROLLBACK_MODES.join(", ")
+        );
+    }
+    let status = TaskStatus::from_str(&args.status);
+    if !ROLLBACK_STATUSES.contains(&status) {
+        bail!(
+            "unknown status '{}' (expected pending or blocked)",
+            args.status
+        );
+    }
+
+    let spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), args.spec.as_deref())?;
+    let session_path = match &args.session {
+        Some(path) => PathBuf::from(path),
+        None => session_latest_for_spec(Path::new(&cfg.session_dir), &spec_dir)
+            .ok_or_else(|| anyhow!("no session found for {}", spec_dir.display()))?,
+    };
+    let data = read_session_json(&session_json_path(&session_path))?;
+    let iteration = data
+        .iterations
+        .iter()
+        .rev()
+        .find(|i| i.index == args.iteration)
+        .ok_or_else(|| {
+            anyhow!(
+                "iteration {} not found in {}",
+                args.iteration,
+                session_path.display()
+            )
+        })?;
+    let task = list_task_files(&spec_dir)
+        .into_iter()
+        .find(|f| get_task_name(f) == iteration.task)
+        .ok_or_else(|| {
+            anyhow!(
+                "task '{}' not found in {}",
+                iteration.task,
+                spec_dir.display()
+            )
+        })?;
+
+    rollback_iteration(
+        ui,
+        &cfg,
+        &spec_dir,
+        &session_path,
+        args.iteration,
+        (&iteration.before_sha, &iteration.commit),
+        mode,
+    )?;
+    if get_task_status(&task) != status {
+        change_task_status(
+            ui,
+            &cfg,
+            &spec_dir,
+            &task,
+            status,
+            args.reason.as_deref(),
+            "rollback",
+        )?;
+    }
+    Ok(EXIT_OK)
+}
+
+/// Undo an iteration's commits; the diff is kept as a patch in the session
+/// directory.
+fn rollback_iteration(
+    ui: &Ui,
+    cfg: &Config,
+    spec_dir: &Path,
+    session_path: &Path,
+    iteration: u32,
+    (before_sha, commit): (&str, &str),
+    mode: &str,
+) -> Result<usize> {
+    let patch = session_path.join(format!("rollback-iteration-{}.patch", iteration));
+    let count = rollback(
+        spec_dir,
+        Path::new(&cfg.session_dir),
+        before_sha,
+        commit,
+        mode,
+        &patch,
+    )?;
+    if count == 0 {
+        ui.step_info(&format!("Iteration {} made no commits", iteration));
+        return Ok(0);
+    }
+
+    ui.step_ok(&format!(
+        "Rolled back {} commit(s) of iteration {} ({}); diff saved to {}",
+        count,
+        iteration,
+        mode,
+        patch.display()
+    ));
+    append_run_note(
+        session_path,
+        &format!("Rollback (iteration {})", iteration),
+        &format!(
+            "- Mode: {}\n- Commits: {} ({}..{})\n- Patch: {}\n",
+            mode,
+            count,
+            &before_sha[..before_sha.len().min(7)],
+            &commit[..commit.len().min(7)],
+            patch.display()
+        ),
+    )?;
+    ui.emit(
+        "rollback",
+        json!({
+            "iteration": iteration,
+            "mode": mode,
+            "commits": count,
+            "before_sha": before_sha,
+            "patch": patch.display().to_string(),
+        }),
+    );
+    Ok(count)
+}
+
+/// Apply a manual status change after checking it against the lifecycle, and
+/// record it in progress.md.
+fn change_task_status(
+    ui: &Ui,
+    cfg: &Config,
+    spec_dir: &Path,
+    task: &Path,
+    to: TaskStatus,
+    reason: Option<&str>,
+    action: &str,
+) -> Result<i32> {
+    let from = get_task_status(task);
+    // Skipping is the one way to mark an open task done outside the
+    // lifecycle; it is recorded as an override.
+    let overridden = !from.can_transition(to)
+        && action == "skip"
+        && to == TaskStatus::Done
+        && from != TaskStatus::Done;
+    if from.can_transition(to) {
+        set_task_status(task, to)?;
+    } else if overridden {
+        write_task_status(task, to)?;
+    } else {
+        bail!(
+            "cannot move {} from {} to {}",
+            get_task_name(task),
+            from.as_str(),
+            to.as_str()
+        );
+    }
+
+    let mut entry = format!(
+        "### {} — {}\n\n- Status: {} → {} ({})\n",
+        now_human(),
+        get_task_name(task),
+        from.as_str(),
+        to.as_str(),
+        action
+    );
+    if overridden {
+        entry.push_str("- Override: lifecycle bypassed, marked done without review\n");
+    }
+    if let Some(reason) = reason {
+        entry.push_str(&format!("- Reason: {}\n", reason.trim()));
+    }
+    append_progress(spec_dir, &entry)?;
+    let session = session_latest_for_spec(Path::new(&cfg.session_dir), spec_dir);
+    let actor = if overridden { "user override" } else { "user" };
+    log_status_change(cfg, session.as_deref(), task, (from, to), actor, None)?;
+    if overridden && !cfg.task_history {
+        // Overrides are always kept in the task's history.
+        append_task_history(
+            task,
+            &format!(
+                "{} {} → {} (user override)",
+                now_human(),
+                from.as_str(),
+                to.as_str()
+            ),
+        )?;
+    }
+
+    ui.emit(
+        "task_status",
+        json!({
+            "file": task.display().to_string(),
+            "from": from.as_str(),
+            "to": to.as_str(),
+            "action": action,
+            "override": overridden,
+            "reason": reason,
+        }),
+    );
+    ui.step_ok(&format!(
+        "{}: {} {} {}",
+        get_task_name(task),
+        from.as_str(),
+        ui.arrow(),
+        to.as_str()
+    ));
+    Ok(EXIT_OK)
+}
+
+fn join_numbers(numbers: &[u32]) -> String {
+    if numbers.is_empty() {
+        return "none".to_string();
+    }
+    numbers
+        .iter()
+        .map(u32::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Where `plan --force` keeps the existing task files until the new ones pass
+/// validation, and where new ones that fail are left for reference.
+const PLAN_BACKUP_DIR: &str = "tasks.orig";
+const PLAN_REJECTED_DIR: &str = "tasks.rejected";
+
+pub fn cmd_plan(args: &PlanArgs, ui: &Ui) -> Result<i32> {
+    let cfg = load_config(None)?;
+    ui.print_header(SPECLOOP_VERSION);
+
+    if !command_exists(&cfg.claude_bin) {
+        bail!("'{}' not found in PATH", cfg.claude_bin);
+    }
+    let spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), args.spec.as_deref())?;
+    if !spec_dir.join("spec.md").is_file() {
+        bail!("{} has no spec.md", spec_dir.display());
+    }
+    warn_outdated_templates(ui);
+
+    let existing = list_task_files(&spec_dir);
+    if !existing.is_empty() && !args.force {
+        bail!(
+            "{} already has {} task file(s); pass --force to replace them",
+            spec_dir.display(),
+            existing.len()
+        );
+    }
+    let backup = spec_dir.join(PLAN_BACKUP_DIR);
+    if backup.exists() {
+        bail!(
+            "{} is left over from an interrupted plan; move its task files back or remove it",
+            backup.display()
+        );
+    }
+    let spec_md = fs::read_to_string(spec_dir.join("spec.md"))
+        .with_context(|| format!("failed to read {}/spec.md", spec_dir.display()))?;
+    move_files(&existing, &backup)?;
+    if !existing.is_empty() {
+        ui.step_warn(&format!(
+            "Moved {} existing task file(s) to {} until the new plan is validated",
+            existing.len(),
+            backup.display()
+        ));
+    }
+
+    let outcome = plan_tasks(args, ui, &cfg, &spec_dir);
+    if matches!(outcome, Ok(EXIT_OK)) {
+        for dir in [backup, spec_dir.join(PLAN_REJECTED_DIR)] {
+            if dir.exists() {
+                fs::remove_dir_all(&dir)
+                    .with_context(|| format!("failed to remove {}", dir.display()))?;
+            }
+        }
+        return outcome;
+    }
+
+    // Put the spec back as it was; keep whatever was generated aside.
+    let generated = list_task_files(&spec_dir);
+    if !generated.is_empty() {
+        let rejected = spec_dir.join(PLAN_REJECTED_DIR);
+        if rejected.exists() {
+            fs::remove_dir_all(&rejected)
+                .with_context(|| format!("failed to remove {}", rejected.display()))?;
+        }
+        move_files(&generated, &rejected)?;
+        ui.step_warn(&format!(
+            "Moved {} generated task file(s) to {}",
+            generated.len(),
+            rejected.display()
+        ));
+    }
+    let originals: Vec<PathBuf> = existing
+        .iter()
+        .filter_map(|f| f.file_name())
+        .map(|name| backup.join(name))
+        .collect();
+    move_files(&originals, &spec_dir.join("tasks"))?;
+    if backup.exists() {
+        fs::remove_dir(&backup)
+            .with_context(|| format!("failed to remove {}", backup.display()))?;
+    }
+    fs::write(spec_dir.join("spec.md"), spec_md)
+        .with_context(|| format!("failed to restore {}/spec.md", spec_dir.display()))?;
+    if !existing.is_empty() {
+        ui.step_info(&format!(
+            "Restored {} original task file(s)",
+            existing.len()
+        ));
+    }
+    outcome
+}
+
+/// Move `files` into `dir`, creating it.
+fn move_files(files: &[PathBuf], dir: &Path) -> Result<()> {
+    if files.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir).with_context(|| format!("failed to create {}", dir.display()))?;
+    for file in files {
+        let Some(name) = file.file_name() else {
+            continue;
+        };
+        fs::rename(file, dir.join(name))
+            .with_context(|| format!("failed to move {} to {}", file.display(), dir.display()))?;
+    }
+    Ok(())
+}
+
+/// Run the plan phase and check what it wrote; anything but `EXIT_OK` makes
+/// the caller restore the previous tasks.
+fn plan_tasks(args: &PlanArgs, ui: &Ui, cfg: &Config, spec_dir: &Path) -> Result<i32> {
+    fs::create_dir_all(spec_dir.join("tasks"))
+        .with_context(|| format!("failed to create {}/tasks", spec_dir.display()))?;
+
+    ui.phase("plan");
+    ui.emit("phase_start", json!({ "phase": "plan" }));
+    let prompt = render_prompt(
+        PromptKind::Plan,
+        &PromptContext {
+            spec_dir,
+            cfg,
+            task_file: None,
+            before_sha: None,
+            review_findings: "",
+            files_scope: "",
+            session_id: "",
+        },
+    )?;
+    let mut result = run_claude(
+        &prompt,
+        cfg,
+        PromptKind::Plan.access(),
+        false,
+        args.verbose,
+        ui,
+    )?;
+    let contract_ok = enforce_contract(
+        ui,
+        cfg,
+        None,
+        PromptKind::Plan,
+        &mut result,
+        (false, args.verbose),
+    )?;
+    if result.timed_out {
+        bail!(
+            "planning timed out after {} minutes (PHASE_TIMEOUT_MINUTES)",
+            cfg.phase_timeout_minutes
+        );
+    }
+    let plan_status = parse_kv(&result.output_text, "PLAN_STATUS").unwrap_or_default();
+    phase_end(
+        ui,
+        "plan",
+        if plan_status.is_empty() {
+            "unknown"
+        } else {
+            &plan_status
+        },
+        &result,
+    );
+
+    if has_tag(&result.output_text, "BLOCKED") || plan_status == "BLOCKED" {
+        ui.step_error("Planning is BLOCKED; see the agent output for what spec.md is missing");
+        return Ok(EXIT_BLOCKED);
+    }
+    if !contract_ok {
+        bail!("the plan output breaks the status contract");
+    }
+
+    let tasks = list_task_files(spec_dir);
+    let problems = validate_task_files(spec_dir);
+    if !problems.is_empty() {
+        for problem in &problems {
+            ui.step_error(problem);
+        }
+        bail!(
+            "generated tasks failed validation ({} problem(s)); see {}",
+            problems.len(),
+            spec_dir.join(PLAN_REJECTED_DIR).display()
+        );
+    }
+
+    for task in &tasks {
+        let depends = get_task_depends(task);
+        ui.step_ok(&format!(
+            "{}  {}  {}",
+            get_task_name(task),
+            ui.dim(&format!("[{}]", get_task_size(task))),
+            ui.dim(&if depends.is_empty() {
+                "no dependencies".to_string()
+            } else {
+                format!(
+                    "depends on {}",
+                    depends
+                        .iter()
+                        .map(u32::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })
+        ));
+    }
+    ui.blank();
+    ui.step_info(&format!(
+        "{} task(s) written to {}; review them, then run {}",
+        tasks.len(),
+        spec_dir.join("tasks").display(),
+        ui.bold("spec-loop run")
+    ));
+    Ok(EXIT_OK)
+}
+
+pub fn cmd_config(args: &ConfigArgs, ui: &Ui) -> Result<i32> {
+    match &args.command {
+        ConfigCommand::Show(show) => {
+            let mut cfg = load_layers(show.profile.as_deref())?;
+            if show.spec.is_some() || show.task.is_some() {
+                let spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), show.spec.as_deref())?;
+                let task_file = match &show.task {
+                    Some(task) => Some(find_task(&spec_dir, task)?),
+                    None => None,
+                };
+                cfg = scoped_config(&cfg, &spec_dir, task_file.as_deref())?;
+            }
+            for (flag, on) in [
+                ("--once", cfg.profile_flags.once),
+                ("--skip-review", cfg.profile_flags.skip_review),
+            ] {
+                if on {
+                    println!("# profile {} runs with {}", cfg.profile, flag);
+                }
+            }
+            let width = KEYS.iter().map(|k| k.path().len()).max().unwrap_or(0);
+            for key in KEYS {
+                let value = toml_value(key, &key.get(&cfg)).to_string();
+                if show.origin {
+                    println!(
+                        "{:<width$} = {}  # {}",
+                        key.path(),
+                        value,
+                        cfg.origin(key),
+                        width = width
+                    );
+                } else {
+                    println!("{:<width$} = {}", key.path(), value, width = width);
+                }
+            }
+            let quote = |v: &str| toml::Value::String(v.to_string()).to_string();
+            for target in &cfg.targets {
+                let value = format!(
+                    "{{ type = {}, verify_command = {}, test_command = {} }}",
+                    quote(&target.project_type),
+                    quote(&target.verify_command),
+                    quote(&target.test_command)
+                );
+                let name = format!("targets.{}", quote(&target.path));
+                if show.origin {
+                    println!("{} = {}  # {}", name, value, cfg.config_file);
+                } else {
+                    println!("{} = {}", name, value);
+                }
+            }
+            Ok(EXIT_OK)
+        }
+        ConfigCommand::Get(get) => {
+            let cfg = load_config(None)?;
+            println!("{}", lookup_key(&get.key)?.get(&cfg));
+            Ok(EXIT_OK)
+        }
+        ConfigCommand::Set(set) => {
+            let key = lookup_key(&set.key)?;
+            let path = if set.global {
+                user_config_path().context("cannot locate the user config directory")?
+            } else if !Path::new(CONFIG_FILE).exists() && Path::new(LEGACY_CONFIG_FILE).exists() {
+                bail!(
+                    "This project uses {}; run 'spec-loop config migrate' first",
+                    LEGACY_CONFIG_FILE
+                );
+            } else {
+                PathBuf::from(CONFIG_FILE)
+            };
+            let mut cfg = load_config(None)?;
+            key.apply(&mut cfg, &set.value)
+                .with_context(|| format!("invalid {}", key.path()))?;
+            validate_config(&cfg)?;
+            set_in_file(&path, key, &set.value)?;
+            ui.step_ok(&format!("Set {} in {}", key.path(), path.display()));
+            Ok(EXIT_OK)
+        }
+        ConfigCommand::Validate => {
+            let cfg = load_config(None)?;
+            warn_ignored_keys(ui, &cfg);
+            validate_config(&cfg)?;
+            ui.step_ok(&format!(
+                "Configuration is valid ({})",
+                if cfg.config_file.is_empty() {
+                    "defaults only"
+                } else {
+                    cfg.config_file.as_str()
+                }
+            ));
+            Ok(EXIT_OK)
+        }
+        ConfigCommand::Migrate(migrate) => {
+            let legacy = Path::new(LEGACY_CONFIG_FILE);
+            if !legacy.is_file() {
+                bail!("No {} to migrate", LEGACY_CONFIG_FILE);
+            }
+            if Path::new(CONFIG_FILE).exists() && !migrate.force {
+                bail!("{} already exists. Use --force to overwrite.", CONFIG_FILE);
+            }
+            let (content, unknown) = migrate_legacy_config(legacy)?;
+            fs::write(CONFIG_FILE, content)
+                .with_context(|| format!("failed writing {}", CONFIG_FILE))?;
+            ui.step_ok(&format!(
+                "Wrote {} from {}",
+                CONFIG_FILE, LEGACY_CONFIG_FILE
+            ));
+            for name in &unknown {
+                ui.step_warn(&format!("Left out unknown key {}", name));
+            }
+            ui.step_info(&format!(
+                "{} now takes precedence; remove {} once you have checked it",
+                CONFIG_FILE, LEGACY_CONFIG_FILE
+            ));
+            Ok(EXIT_OK)
+        }
+    }
+}
+
+pub fn cmd_prompt(args: &PromptArgs) -> Result<i32> {
+    match &args.command {
+        PromptCommand::Show(show) => {
+            let cfg = load_config(None)?;
+            let kind = PromptKind::from_str(&show.name)?;
+            let spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), show.spec.as_deref())?;
+            let task_file = show
+                .task
+                .as_ref()
+                .map(PathBuf::from)
+                .or_else(|| find_next_task(&spec_dir).or_else(|| find_open_task(&spec_dir)));
+            let cfg = scoped_config(&cfg, &spec_dir, task_file.as_deref())?;
+            let findings = match &show.findings {
+                Some(path) => {
+                    fs::read_to_string(path).with_context(|| format!("failed to read {}", path))?
+                }
+                None => "(review findings are inserted here)".to_string(),
+            };
+
+            if show.raw {
+                let (template, _) = load_template(kind)?;
+                print!("{}", template);
+                return Ok(EXIT_OK);
+            }
+
+            let scope_text = match (&task_file, &show.before_sha) {
+                (Some(task), Some(sha)) => check_scope(task, &spec_dir, sha)
+                    .map(|r| r.to_markdown())
+                    .unwrap_or_default(),
+                _ => String::new(),
+            };
+            let prompt = render_prompt(
+                kind,
+                &PromptContext {
+                    spec_dir: &spec_dir,
+                    cfg: &cfg,
+                    task_file: task_file.as_deref(),
+                    before_sha: show.before_sha.as_deref(),
+                    review_findings: &findings,
+                    files_scope: &scope_text,
+                    session_id: "",
+                },
+            )?;
+            print!("{}", prompt);
+            Ok(EXIT_OK)
+        }
+    }
+}
+
+pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
+    let cfg = load_config(Some(args))?;
+    let args = &RunArgs {
+        once: args.once || cfg.profile_flags.once,
+        skip_review: args.skip_review || cfg.profile_flags.skip_review,
+        ..args.clone()
+    };
+    preflight(&cfg)?;
+    warn_ignored_keys(ui, &cfg);
+    warn_outdated_templates(ui);
+    let policy = Policy::load(Path::new(&cfg.policy_file))?;
+    let panel_width = ui.panel_width(52, 72);
+
+    let session_dir = Path::new(&cfg.session_dir);
+    let mut cb = CircuitBreaker::load(session_dir)?;
+
+    let mut spec_dir = resolve_spec_dir(Path::new(&cfg.specs_dir), args.spec.as_deref())?;
+    let mut spec_name = get_spec_name(&spec_dir);
+
+    let loop_index: u32;
+    let mut resume_phase = String::new();
+    let mut resume_before_sha = String::new();
+    let session_path: PathBuf;
+    let mut mode = "new";
+
+    if args.resume {
+        if let Some(state) = load_resume_state(&cfg) {
+            spec_dir = PathBuf::from(&state.spec_dir);
+            spec_name = get_spec_name(&spec_dir);
+            session_path = PathBuf::from(&state.session_path);
+            loop_index = state.loop_index.max(1);
+            resume_phase = state.phase;
+            resume_before_sha = state.before_sha;
+            mode = "resume";
+            ui.step_info(&format!(
+                "Resuming session for {} at iteration {} (phase: {})",
+                spec_name, loop_index, resume_phase
+            ));
+        } else {
+            let continuation = session_continuation_for_spec(session_dir, &spec_dir);
+            if let Some(path) = continuation {
+                session_path = path;
+                loop_index = session_iterations_count(&session_path) + 1;
+                mode = "continue";
+                ui.step_info(&format!(
+                    "Continuing session {} at iteration {}",
+                    session_path
+                        .file_name()
+                        .map(|s| s.to_string_lossy().to_string())
+                        .unwrap_or_else(|| "unknown".to_string()),
+                    loop_index
+                ));
+            } else {
+                session_path = new_session_path(&cfg, &spec_name);
+                loop_index = 1;
+            }
+        }
+    } else {
+        let continuation = session_continuation_for_spec(session_dir, &spec_dir);
+        if let Some(path) = continuation {
+            session_path = path;
+            loop_index = session_iterations_count(&session_path) + 1;
+            mode = "continue";
+            ui.step_info(&format!(
+                "Continuing session {} at iteration {}",
+                session_path
+                    .file_name()
+                    .map(|s| s.to_string_lossy().to_string())
+                    .unwrap_or_else(|| "unknown".to_string()),
+                loop_index
+            ));
+        } else {
+            session_path = new_session_path(&cfg, &spec_name);
+            loop_index = 1;
+        }
+    }
+
+    let spec_cfg = scoped_config(&cfg, &spec_dir, None)?;
+    for task in list_task_files(&spec_dir) {
+        validate_config(&scoped_config(&spec_cfg, &spec_dir, Some(&task))?)?;
+    }
+    report_overrides(ui, &cfg, &spec_cfg);
+    let cfg = spec_cfg;
+
+    let start_branch = current_branch();
+    if !args.dry_run {
+        check_worktree(ui, &cfg, &spec_dir, &spec_name, None)?;
+    }
+
+    ensure_session_initialized(&session_path, &spec_dir, &spec_name, &cfg, SPECLOOP_VERSION)?;
+    let session_id = read_session_json(&session_json_path(&session_path))
+        .map(|data| data.session_id)
+        .unwrap_or_default();
+    if !args.dry_run && is_git_repo() {
+        record_spec_base(&cfg, &session_path, &spec_dir, &start_branch)?;
+    }
+    ui.emit(
+        "run_start",
+        json!({
+            "spec": spec_name,
+            "spec_dir": spec_dir.display().to_string(),
+            "session": session_path.display().to_string(),
+            "mode": mode,
+        }),
+    );
+
+    let total_tasks = count_total(&spec_dir);
+    let done_tasks = count_status(&spec_dir, TaskStatus::Done);
+    let pending_tasks = count_status(&spec_dir, TaskStatus::Pending);
+    let in_progress_tasks = count_status(&spec_dir, TaskStatus::InProgress);
+    let in_review_tasks = count_status(&spec_dir, TaskStatus::InReview);
+    let blocked_tasks = count_status(&spec_dir, TaskStatus::Blocked);
+    let remaining_tasks = count_remaining(&spec_dir);
+
+    ui.print_header(SPECLOOP_VERSION);
+    ui.box_header("Spec", panel_width);
+    ui.box_empty(panel_width);
+    ui.box_line(&format!("  {}", spec_name), panel_width);
+    ui.box_line(
+        &format!(
+            "  {} {} {} remaining",
+            ui.progress_bar(done_tasks, total_tasks, 16, "done"),
+            ui.diamond(),
+            remaining_tasks
+        ),
+        panel_width,
+    );
+    ui.box_line(
+        &format!(
+            "  {} pending {} {} in-progress",
+            pending_tasks,
+            ui.diamond(),
+            in_progress_tasks
+        ),
+        panel_width,
+    );
+    ui.box_line(
+        &format!(
+            "  {} in-review {} {} blocked",
+            in_review_tasks,
+            ui.diamond(),
+            blocked_tasks
+        ),
+        panel_width,
+    );
+    ui.box_line(&format!("  {}", current_branch()), panel_width);
+    if !cfg.profile.is_empty() {
+        ui.box_line(&format!("  Profile      {}", cfg.profile), panel_width);
+    }
+    if cfg.budget_usd > 0.0 {
+        ui.box_line(
+            &format!("  Cost budget  {} this run", format_cost(cfg.budget_usd)),
+            panel_width,
+        );
+    }
+    if cfg.max_tasks_per_run > 0 {
+        ui.box_line(
+            &format!("  Task budget  {} this run", cfg.max_tasks_per_run),
+            panel_width,
+        );
+    }
+    ui.box_empty(panel_width);
+    ui.box_footer(panel_width);
+
+    let total_cost = session_total_cost(&session_path);
+    let run_cost_base = total_cost;
+    let total_iterations_base = session_total_iterations(&session_path);
+    let started_epoch = session_started_epoch(&session_path);
+
+    append_run_invocation_header(
+        &session_path,
+        mode,
+        &cfg.profile,
+        args.once,
+        cfg.max_tasks_per_run,
+        cfg.max_loops,
+        args.skip_review,
+    )?;
+    let (allowed, review_allowed) = if cfg.permission_mode == "allowlist" {
+        (
+            allowed_tools(&cfg, Access::ReadWrite),
+            allowed_tools(&cfg, Access::ReadOnly),
+        )
+    } else {
+        (vec![], vec![])
+    };
+    record_permissions(&session_path, &cfg.permission_mode, allowed, review_allowed)?;
+
+    let mut run = RunSession {
+        ui,
+        cfg: &cfg,
+        path: &session_path,
+        started_epoch,
+        iterations_base: total_iterations_base,
+        iterations_completed: 0,
+        loop_index,
+        total_cost,
+        task_name: String::new(),
+        iteration_start: Instant::now(),
+    };
+
+    while run.loop_index <= cfg.max_loops {
+        if !cb.check(cfg.cb_cooldown_minutes, ui)? {
+            return run.finish("CIRCUIT_OPEN", EXIT_CIRCUIT_OPEN);
+        }
+
+        let remaining = count_remaining(&spec_dir);
+        if remaining == 0 {
+            return run.complete(&spec_dir, total_tasks);
+        }
+
+        if cfg.max_tasks_per_run > 0 && run.iterations_completed >= cfg.max_tasks_per_run {
+            ui.step_ok(&format!(
+                "Reached task budget ({}) for this run",
+                cfg.max_tasks_per_run
+            ));
+            return run.finish("TASK_LIMIT", EXIT_OK);
+        }
+
+        let run_cost = run.total_cost - run_cost_base;
+        if cfg.budget_usd > 0.0 && run_cost >= cfg.budget_usd {
+            ui.step_error(&format!(
+                "Budget exceeded ({} / {}). {} tasks remaining.",
+                format_cost(run_cost),
+                format_cost(cfg.budget_usd),
+                remaining
+            ));
+            return run.finish("BUDGET_EXCEEDED", EXIT_MAX_ITERATIONS);
+        }
+
+        if !args.dry_run && run.iterations_completed > 0 {
+            if let Err(err) = check_worktree(ui, &cfg, &spec_dir, &spec_name, Some(&session_path)) {
+                ui.step_error(&format!("{:#}", err));
+                run.end("UNSAFE_WORKTREE")?;
+                return Ok(EXIT_ERROR);
+            }
+        }
+
+        let next_task_file = find_next_task(&spec_dir).or_else(|| find_open_task(&spec_dir));
+        let next_task_name = next_task_file
+            .as_ref()
+            .map(|p| get_task_name(p))
+            .unwrap_or_default();
+        let next_task_status = next_task_file
+            .as_ref()
+            .map(|p| get_task_status(p))
+            .unwrap_or(TaskStatus::Unknown);
+
+        if next_task_name.is_empty() {
+            ui.separator(
+                &format!("Iteration {} of {}", run.loop_index, cfg.max_loops),
+                panel_width,
+            );
+        } else {
+            ui.separator(
+                &format!(
+                    "Iteration {} {} {}",
+                    run.loop_index,
+                    ui.diamond(),
+                    next_task_name
+                ),
+                panel_width,
+            );
+        }
+
+        append_run_iteration_header(
+            &session_path,
+            run.loop_index,
+            if next_task_name.is_empty() {
+                None
+            } else {
+                Some(next_task_name.as_str())
+            },
+        )?;
+        ui.emit(
+            "iteration_start",
+            json!({
+                "iteration": run.loop_index,
+                "task": next_task_name,
+                "task_file": next_task_file.as_ref().map(|p| p.display().to_string()),
+            }),
+        );
+
+        run.begin_iteration(&next_task_name);
+        let remaining_before = count_remaining(&spec_dir);
+        let signature_before = status_signature(&spec_dir);
+
+        let Some(task_file) = next_task_file else {
+            ui.step_error(&format!(
+                "No eligible task: {} tasks remain (blocked or waiting on dependencies)",
+                remaining
+            ));
+            run.log_iteration("blocked-no-pending", 0.0, (0, 0), (&head_sha(), ""))?;
+            return run.finish("BLOCKED", EXIT_BLOCKED);
+        };
+        let task_cfg = scoped_config(&cfg, &spec_dir, Some(&task_file))?;
+        report_overrides(ui, &cfg, &task_cfg);
+        let cfg = task_cfg;
+
+        let before_sha: String;
+        let after_build_sha: String;
+        let mut build_cost = 0.0_f64;
+        let mut fix_total_cost = 0.0_f64;
+        let mut policy_violations: Vec<Violation> = vec![];
+        let mut must_fix_count: u32;
+        let mut should_fix_count: u32;
+        let mut review_status: String;
+        let mut review_findings: String;
+
+        if resume_phase == "review"
+            || resume_phase == "fix"
+            || next_task_status == TaskStatus::InReview
+        {
+            ui.step_info(&format!(
+                "Skipping build (already completed, resuming at {})",
+                if next_task_status == TaskStatus::InReview {
+                    "review"
+                } else {
+                    &resume_phase
+                }
+            ));
+            before_sha = task_base(&session_path, &next_task_name)
+                .unwrap_or_else(|| resume_before_sha.clone());
+            after_build_sha = head_sha();
+            save_resume_state(
+                &cfg,
+                &spec_dir,
+                run.loop_index,
+                &session_path,
+                if next_task_status == TaskStatus::InReview {
+                    "review"
+                } else {
+                    &resume_phase
                 },
                 &before_sha,
             )?;
         } else {
-            save_resume_state(&cfg, &spec_dir, loop_index, &session_path, "build", "")?;
+            save_resume_state(&cfg, &spec_dir, run.loop_index, &session_path, "build", "")?;
 
+            if next_task_status == TaskStatus::Pending {
+                move_task(
+                    &cfg,
+                    &session_path,
+                    run.loop_index,
+                    &task_file,
+                    TaskStatus::InProgress,
+                )?;
+            }
+            let statuses_before_build = task_statuses(&spec_dir);
+            let spec_before_build = SpecSnapshot::take(&spec_dir)?;
             before_sha = head_sha();
+            record_task_base(&session_path, &next_task_name, &before_sha)?;
 
             ui.phase("build");
-            let prompt = build_prompt(&spec_dir, &cfg);
-            let result = run_claude(&prompt, &cfg, args.dry_run, args.verbose)?;
+            ui.emit("phase_start", json!({ "phase": "build" }));
+            let prompt = render_prompt(
+                PromptKind::Build,
+                &PromptContext {
+                    spec_dir: &spec_dir,
+                    cfg: &cfg,
+                    task_file: Some(&task_file),
+                    before_sha: Some(&before_sha),
+                    review_findings: "",
+                    files_scope: "",
+                    session_id: &session_id,
+                },
+            )?;
+            let mut result = run_claude(
+                &prompt,
+                &cfg,
+                PromptKind::Build.access(),
+                args.dry_run,
+                args.verbose,
+                ui,
+            )?;
             register_claude_session(&session_path, &result.claude_session_id)?;
+            let contract_ok = enforce_contract(
+                ui,
+                &cfg,
+                Some(&session_path),
+                PromptKind::Build,
+                &mut result,
+                (args.dry_run, args.verbose),
+            )?;
             let build_status = parse_kv(&result.output_text, "BUILD_STATUS").unwrap_or_default();
 
             build_cost = result.cost_usd;
-            total_cost += build_cost;
+            run.total_cost += build_cost;
 
             append_run_phase(
                 &session_path,
@@ -501,68 +1578,142 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
                 &prompt,
                 &result.claude_session_id,
             )?;
+            phase_end(
+                ui,
+                "build",
+                if build_status.is_empty() {
+                    "unknown"
+                } else {
+                    &build_status
+                },
+                &result,
+            );
+            if report_policy(ui, &session_path, "build", &result.policy_violations)? {
+                return run.finish("POLICY_VIOLATION", EXIT_ERROR);
+            }
+            policy_violations = result.policy_violations.clone();
 
-            after_build_sha = head_sha();
-
-            if has_tag(&result.output_text, "COMPLETE") {
-                ui.step_ok("All tasks complete");
-                iterations_completed += 1;
-                append_iteration_log(
+            if reject_wrong_task(
+                ui,
+                &cfg,
+                (&spec_dir, &session_path),
+                run.loop_index,
+                (&task_file, &statuses_before_build, &spec_before_build),
+                &before_sha,
+            )? {
+                move_task(
+                    &cfg,
                     &session_path,
-                    IterationLogInput {
-                        index: loop_index,
-                        task_name: &next_task_name,
-                        outcome: "complete",
-                        duration_seconds: iteration_start.elapsed().as_secs(),
-                        cost_usd: build_cost,
-                        must_fix_count: 0,
-                        should_fix_count: 0,
-                        commit_sha: &after_build_sha,
-                    },
+                    run.loop_index,
+                    &task_file,
+                    TaskStatus::Pending,
                 )?;
-                loop_complete(
+                run.iteration_failed(
+                    &mut cb,
+                    "wrong-task",
+                    build_cost,
+                    (&head_sha(), &before_sha),
+                )?;
+                continue;
+            }
+
+            if result.timed_out {
+                ui.step_error(&format!(
+                    "Build timed out after {} minutes; the task will be retried",
+                    cfg.phase_timeout_minutes
+                ));
+                reject_spec_commits(
+                    ui,
+                    &spec_dir,
                     &session_path,
-                    &spec_name,
-                    total_tasks,
-                    started_epoch,
-                    total_cost,
-                    total_iterations_base + iterations_completed,
+                    (&session_id, "build"),
+                    &before_sha,
+                )?;
+                enforce_spec_snapshot(ui, &session_path, "build", &spec_before_build, &task_file)?;
+                run.iteration_failed(&mut cb, "timeout", build_cost, (&head_sha(), &before_sha))?;
+                continue;
+            }
+
+            if contract_ok
+                && build_status == "COMPLETED_TASK"
+                && !enforce_commit(
                     ui,
+                    &cfg,
+                    &spec_dir,
+                    &session_path,
+                    (&session_id, "build"),
+                    &task_file,
+                )?
+            {
+                ui.step_error("Build left uncommitted changes; the task will be retried");
+                run.iteration_failed(
+                    &mut cb,
+                    "uncommitted",
+                    build_cost,
+                    (&head_sha(), &before_sha),
                 )?;
-                clear_resume_state(&cfg);
-                return Ok(EXIT_OK);
+                continue;
+            }
+            reject_spec_commits(
+                ui,
+                &spec_dir,
+                &session_path,
+                (&session_id, "build"),
+                &before_sha,
+            )?;
+            after_build_sha = head_sha();
+            if build_status == "COMPLETED_TASK" && after_build_sha == before_sha {
+                ui.step_warn("Build made no commits; the review will see no changes");
+            }
+            enforce_spec_snapshot(ui, &session_path, "build", &spec_before_build, &task_file)?;
+
+            revert_illegal_status_edits(
+                ui,
+                &cfg,
+                &session_path,
+                run.loop_index,
+                "build",
+                &statuses_before_build,
+                &task_file,
+                &[
+                    TaskStatus::InProgress,
+                    TaskStatus::InReview,
+                    TaskStatus::Blocked,
+                ],
+            )?;
+
+            if has_tag(&result.output_text, "COMPLETE") {
+                ui.step_ok("All tasks complete");
+                run.iterations_completed += 1;
+                run.log_iteration(
+                    "complete",
+                    build_cost,
+                    (0, 0),
+                    (&after_build_sha, &before_sha),
+                )?;
+                return run.complete(&spec_dir, total_tasks);
             }
 
             if has_tag(&result.output_text, "BLOCKED") {
                 ui.step_error("Build is BLOCKED");
-                append_iteration_log(
-                    &session_path,
-                    IterationLogInput {
-                        index: loop_index,
-                        task_name: &next_task_name,
-                        outcome: "blocked",
-                        duration_seconds: iteration_start.elapsed().as_secs(),
-                        cost_usd: build_cost,
-                        must_fix_count: 0,
-                        should_fix_count: 0,
-                        commit_sha: &after_build_sha,
-                    },
-                )?;
-                finalize_session(
-                    &session_path,
-                    started_epoch,
-                    total_cost,
-                    "BLOCKED",
-                    total_iterations_base + iterations_completed,
+                run.log_iteration(
+                    "blocked",
+                    build_cost,
+                    (0, 0),
+                    (&after_build_sha, &before_sha),
                 )?;
-                clear_resume_state(&cfg);
-                return Ok(EXIT_BLOCKED);
+                return run.finish("BLOCKED", EXIT_BLOCKED);
             }
 
-            if build_status.is_empty() {
-                bail!(
-                    "Build output missing BUILD_STATUS — model did not comply with prompt format"
-                );
+            if !contract_ok {
+                ui.step_error("Build status unknown; the task will be retried");
+                run.iteration_failed(
+                    &mut cb,
+                    "contract-violation",
+                    build_cost,
+                    (&after_build_sha, &before_sha),
+                )?;
+                continue;
             }
 
             match build_status.as_str() {
@@ -573,94 +1724,51 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
                             "No pending tasks, but {} tasks remain (likely blocked/in-review)",
                             still_remaining
                         ));
-                        append_iteration_log(
-                            &session_path,
-                            IterationLogInput {
-                                index: loop_index,
-                                task_name: &next_task_name,
-                                outcome: "blocked-no-pending",
-                                duration_seconds: iteration_start.elapsed().as_secs(),
-                                cost_usd: build_cost,
-                                must_fix_count: 0,
-                                should_fix_count: 0,
-                                commit_sha: &after_build_sha,
-                            },
-                        )?;
-                        finalize_session(
-                            &session_path,
-                            started_epoch,
-                            total_cost,
-                            "BLOCKED",
-                            total_iterations_base + iterations_completed,
+                        run.log_iteration(
+                            "blocked-no-pending",
+                            build_cost,
+                            (0, 0),
+                            (&after_build_sha, &before_sha),
                         )?;
-                        clear_resume_state(&cfg);
-                        return Ok(EXIT_BLOCKED);
+                        return run.finish("BLOCKED", EXIT_BLOCKED);
                     }
 
                     ui.step_ok("No pending tasks");
-                    iterations_completed += 1;
-                    append_iteration_log(
-                        &session_path,
-                        IterationLogInput {
-                            index: loop_index,
-                            task_name: &next_task_name,
-                            outcome: "no-pending",
-                            duration_seconds: iteration_start.elapsed().as_secs(),
-                            cost_usd: build_cost,
-                            must_fix_count: 0,
-                            should_fix_count: 0,
-                            commit_sha: &after_build_sha,
-                        },
-                    )?;
-                    loop_complete(
-                        &session_path,
-                        &spec_name,
-                        total_tasks,
-                        started_epoch,
-                        total_cost,
-                        total_iterations_base + iterations_completed,
-                        ui,
+                    run.iterations_completed += 1;
+                    run.log_iteration(
+                        "no-pending",
+                        build_cost,
+                        (0, 0),
+                        (&after_build_sha, &before_sha),
                     )?;
-                    clear_resume_state(&cfg);
-                    return Ok(EXIT_OK);
+                    return run.complete(&spec_dir, total_tasks);
                 }
                 "BLOCKED" => {
                     ui.step_error("Build reported BLOCKED");
-                    append_iteration_log(
-                        &session_path,
-                        IterationLogInput {
-                            index: loop_index,
-                            task_name: &next_task_name,
-                            outcome: "blocked",
-                            duration_seconds: iteration_start.elapsed().as_secs(),
-                            cost_usd: build_cost,
-                            must_fix_count: 0,
-                            should_fix_count: 0,
-                            commit_sha: &after_build_sha,
-                        },
+                    run.log_iteration(
+                        "blocked",
+                        build_cost,
+                        (0, 0),
+                        (&after_build_sha, &before_sha),
                     )?;
-                    finalize_session(
-                        &session_path,
-                        started_epoch,
-                        total_cost,
-                        "BLOCKED",
-                        total_iterations_base + iterations_completed,
-                    )?;
-                    clear_resume_state(&cfg);
-                    return Ok(EXIT_BLOCKED);
+                    return run.finish("BLOCKED", EXIT_BLOCKED);
                 }
                 "COMPLETED_TASK" => {
-                    if let Some(task_file) = &next_task_file {
-                        let _ = set_task_status(task_file, TaskStatus::InReview);
-                    }
+                    move_task(
+                        &cfg,
+                        &session_path,
+                        run.loop_index,
+                        &task_file,
+                        TaskStatus::InReview,
+                    )?;
                 }
-                other => bail!("Unexpected BUILD_STATUS: '{other}'"),
+                _ => unreachable!("BUILD_STATUS is checked by the build contract"),
             }
 
             save_resume_state(
                 &cfg,
                 &spec_dir,
-                loop_index,
+                run.loop_index,
                 &session_path,
                 "review",
                 &before_sha,
@@ -670,10 +1778,46 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
         resume_phase.clear();
         resume_before_sha.clear();
 
-        if args.skip_review {
-            if let Some(task_file) = &next_task_file {
-                let _ = set_task_status(task_file, TaskStatus::InReview);
+        let protected = policy.check_files(&changed_files_since(&before_sha));
+        if report_policy(ui, &session_path, "build", &protected)? {
+            return run.finish("POLICY_VIOLATION", EXIT_ERROR);
+        }
+        policy_violations.extend(protected);
+
+        let scope = check_scope(&task_file, &spec_dir, &before_sha);
+        if let Some(report) = scope.as_ref().filter(|r| !r.is_clean()) {
+            report_scope(ui, &session_path, report, &cfg.files_scope_policy)?;
+            if cfg.files_scope_policy == "block" && !report.undeclared.is_empty() {
+                ui.step_error("Build changed undeclared files (FILES_SCOPE_POLICY=block)");
+                move_task(
+                    &cfg,
+                    &session_path,
+                    run.loop_index,
+                    &task_file,
+                    TaskStatus::Blocked,
+                )?;
+                run.log_iteration(
+                    "scope-blocked",
+                    build_cost,
+                    (0, 0),
+                    (&after_build_sha, &before_sha),
+                )?;
+                return run.finish("BLOCKED", EXIT_BLOCKED);
             }
+        }
+        let scope_text = scope
+            .as_ref()
+            .map(ScopeReport::to_markdown)
+            .unwrap_or_default();
+
+        if args.skip_review {
+            move_task(
+                &cfg,
+                &session_path,
+                run.loop_index,
+                &task_file,
+                TaskStatus::InReview,
+            )?;
 
             let remaining = count_remaining(&spec_dir);
             ui.task_complete("Task ready for review", remaining);
@@ -683,88 +1827,120 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
                 ui,
             )?;
 
-            append_iteration_log(
-                &session_path,
-                IterationLogInput {
-                    index: loop_index,
-                    task_name: &next_task_name,
-                    outcome: "skip-review",
-                    duration_seconds: iteration_start.elapsed().as_secs(),
-                    cost_usd: build_cost,
-                    must_fix_count: 0,
-                    should_fix_count: 0,
-                    commit_sha: &after_build_sha,
-                },
+            run.log_iteration(
+                "skip-review",
+                build_cost,
+                (0, 0),
+                (&after_build_sha, &before_sha),
             )?;
 
-            iterations_completed += 1;
-            loop_index += 1;
+            run.next_iteration();
             continue;
         }
 
         ui.phase("review");
-        let review_prompt_text = review_prompt(
-            &spec_dir,
-            &cfg,
-            if before_sha.is_empty() {
-                None
-            } else {
-                Some(before_sha.as_str())
+        ui.emit("phase_start", json!({ "phase": "review" }));
+        let review_prompt_text = render_prompt(
+            PromptKind::Review,
+            &PromptContext {
+                spec_dir: &spec_dir,
+                cfg: &cfg,
+                task_file: Some(&task_file),
+                before_sha: Some(&before_sha),
+                review_findings: "",
+                files_scope: &scope_text,
+                session_id: &session_id,
             },
-        );
-        let review_result = run_claude(&review_prompt_text, &cfg, args.dry_run, args.verbose)?;
-        register_claude_session(&session_path, &review_result.claude_session_id)?;
-        review_cost = review_result.cost_usd;
-        total_cost += review_cost;
-
-        review_status = parse_kv(&review_result.output_text, "REVIEW_STATUS").unwrap_or_default();
-        must_fix_count = parse_kv(&review_result.output_text, "MUST_FIX_COUNT")
-            .and_then(|s| s.parse::<u32>().ok())
-            .unwrap_or(0);
-        should_fix_count = parse_kv(&review_result.output_text, "SHOULD_FIX_COUNT")
-            .and_then(|s| s.parse::<u32>().ok())
-            .unwrap_or(0);
-        review_findings = review_result.output_text.clone();
-
-        let review_phase_status = format!(
-            "{}; must_fix={}; should_fix={}",
-            if review_status.is_empty() {
-                "unknown"
-            } else {
-                &review_status
-            },
-            must_fix_count,
-            should_fix_count
-        );
-        append_run_phase(
+        )?;
+        let statuses_before_review = task_statuses(&spec_dir);
+        let spec_before_review = SpecSnapshot::take(&spec_dir)?;
+        let round = run_review_round(
+            ui,
+            &cfg,
+            args,
             &session_path,
-            "Review",
-            &review_phase_status,
-            review_result.cost_usd,
-            review_result.duration_ms,
-            &review_result.output_text,
             &review_prompt_text,
-            &review_result.claude_session_id,
+            "Review",
+            "review",
+        )?;
+        revert_illegal_status_edits(
+            ui,
+            &cfg,
+            &session_path,
+            run.loop_index,
+            "review",
+            &statuses_before_review,
+            &task_file,
+            &[TaskStatus::Blocked],
+        )?;
+        enforce_spec_snapshot(ui, &session_path, "review", &spec_before_review, &task_file)?;
+        if report_policy(ui, &session_path, "review", &round.violations)? {
+            return run.finish("POLICY_VIOLATION", EXIT_ERROR);
+        }
+        let review_ok = round.valid;
+        let review_cost = round.cost_usd;
+        run.total_cost += review_cost;
+        if round.timed_out {
+            ui.step_error(&format!(
+                "Review timed out after {} minutes; it will be rerun",
+                cfg.phase_timeout_minutes
+            ));
+            run.iteration_failed(
+                &mut cb,
+                "timeout",
+                build_cost + review_cost,
+                (&head_sha(), &before_sha),
+            )?;
+            continue;
+        }
+
+        let mut review = round.report;
+        if let Some(report) = &scope {
+            apply_scope_policy(&cfg.files_scope_policy, report, &mut review);
+        }
+        policy_violations.extend(round.violations);
+        apply_policy_violations(&policy_violations, &mut review);
+        record_review_round(
+            ui,
+            &session_path,
+            run.loop_index,
+            &next_task_name,
+            0,
+            &mut review,
+            &[],
         )?;
+        review_status = review.status.clone();
+        must_fix_count = review.must_fix_count;
+        should_fix_count = review.should_fix_count;
+        review_findings = findings_for_fix(&review, &round.output);
+        let mut open_findings = review.findings;
+
+        ui.emit(
+            "review_verdict",
+            json!({
+                "status": review_status,
+                "must_fix": must_fix_count,
+                "should_fix": should_fix_count,
+                "findings": open_findings,
+                "recheck": false,
+            }),
+        );
 
-        if has_tag(&review_result.output_text, "BLOCKED") {
+        if round.blocked {
             ui.step_error("Review is BLOCKED");
-            finalize_session(
-                &session_path,
-                started_epoch,
-                total_cost,
-                "BLOCKED",
-                total_iterations_base + iterations_completed,
-            )?;
-            clear_resume_state(&cfg);
-            return Ok(EXIT_BLOCKED);
+            return run.finish("BLOCKED", EXIT_BLOCKED);
         }
 
-        let needs_fix = review_status == "FAIL" || must_fix_count > 0;
+        // A review without a valid verdict never counts as a pass.
+        let needs_fix = !review_ok || review_status != "PASS" || must_fix_count > 0;
         if !needs_fix {
-            if let Some(task_file) = &next_task_file {
-                let _ = set_task_status(task_file, TaskStatus::Done);
-            }
+            move_task(
+                &cfg,
+                &session_path,
+                run.loop_index,
+                &task_file,
+                TaskStatus::Done,
+            )?;
 
             ui.step_ok(&format!(
                 "PASS  {}0 must-fix {} {} should-fix (non-blocking)",
@@ -781,22 +1957,14 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
                 ui,
             )?;
 
-            append_iteration_log(
-                &session_path,
-                IterationLogInput {
-                    index: loop_index,
-                    task_name: &next_task_name,
-                    outcome: "pass",
-                    duration_seconds: iteration_start.elapsed().as_secs(),
-                    cost_usd: build_cost + review_cost,
-                    must_fix_count,
-                    should_fix_count,
-                    commit_sha: &after_build_sha,
-                },
+            run.log_iteration(
+                "pass",
+                build_cost + review_cost,
+                (must_fix_count, should_fix_count),
+                (&after_build_sha, &before_sha),
             )?;
 
-            iterations_completed += 1;
-            loop_index += 1;
+            run.next_iteration();
             continue;
         }
 
@@ -810,7 +1978,7 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
         save_resume_state(
             &cfg,
             &spec_dir,
-            loop_index,
+            run.loop_index,
             &session_path,
             "fix",
             &before_sha,
@@ -823,97 +1991,214 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
                 "fix (attempt {}/{})",
                 fix_try, cfg.max_review_fix_loops
             ));
-            let fix_prompt_text = fix_prompt(&spec_dir, &cfg, &review_findings);
-            let fix_result = run_claude(&fix_prompt_text, &cfg, args.dry_run, args.verbose)?;
+            ui.emit("phase_start", json!({ "phase": "fix", "attempt": fix_try }));
+            let fix_prompt_text = render_prompt(
+                PromptKind::Fix,
+                &PromptContext {
+                    spec_dir: &spec_dir,
+                    cfg: &cfg,
+                    task_file: Some(&task_file),
+                    before_sha: Some(&before_sha),
+                    review_findings: &review_findings,
+                    files_scope: "",
+                    session_id: &session_id,
+                },
+            )?;
+            let statuses_before_fix = task_statuses(&spec_dir);
+            let spec_before_fix = SpecSnapshot::take(&spec_dir)?;
+            let head_before_fix = head_sha();
+            let mut fix_result = run_claude(
+                &fix_prompt_text,
+                &cfg,
+                PromptKind::Fix.access(),
+                args.dry_run,
+                args.verbose,
+                ui,
+            )?;
             register_claude_session(&session_path, &fix_result.claude_session_id)?;
-            total_cost += fix_result.cost_usd;
+            enforce_contract(
+                ui,
+                &cfg,
+                Some(&session_path),
+                PromptKind::Fix,
+                &mut fix_result,
+                (args.dry_run, args.verbose),
+            )?;
+            let fix_status = parse_kv(&fix_result.output_text, "BUILD_STATUS")
+                .unwrap_or_else(|| "unknown".to_string());
+            run.total_cost += fix_result.cost_usd;
             fix_total_cost += fix_result.cost_usd;
 
             append_run_phase(
                 &session_path,
                 &format!("Fix (attempt {})", fix_try),
-                "applied",
+                &fix_status,
                 fix_result.cost_usd,
                 fix_result.duration_ms,
                 &fix_result.output_text,
                 &fix_prompt_text,
                 &fix_result.claude_session_id,
             )?;
+            phase_end(ui, "fix", &fix_status, &fix_result);
+            if report_policy(ui, &session_path, "fix", &fix_result.policy_violations)? {
+                return run.finish("POLICY_VIOLATION", EXIT_ERROR);
+            }
+
+            let fix_committed = fix_status != "FIXES_APPLIED"
+                || enforce_commit(
+                    ui,
+                    &cfg,
+                    &spec_dir,
+                    &session_path,
+                    (&session_id, "fix"),
+                    &task_file,
+                )?;
+            reject_spec_commits(
+                ui,
+                &spec_dir,
+                &session_path,
+                (&session_id, "fix"),
+                &head_before_fix,
+            )?;
+            revert_illegal_status_edits(
+                ui,
+                &cfg,
+                &session_path,
+                run.loop_index,
+                "fix",
+                &statuses_before_fix,
+                &task_file,
+                &[TaskStatus::Blocked],
+            )?;
+            enforce_spec_snapshot(ui, &session_path, "fix", &spec_before_fix, &task_file)?;
+            let protected = policy.check_files(&changed_files_since(&before_sha));
+            if report_policy(ui, &session_path, "fix", &protected)? {
+                return run.finish("POLICY_VIOLATION", EXIT_ERROR);
+            }
+            policy_violations = fix_result.policy_violations.clone();
+            policy_violations.extend(protected);
 
+            if fix_result.timed_out {
+                ui.step_warn(&format!(
+                    "Fix attempt {} timed out after {} minutes; skipping its recheck",
+                    fix_try, cfg.phase_timeout_minutes
+                ));
+                fix_try += 1;
+                continue;
+            }
+            if !fix_committed {
+                ui.step_warn(&format!(
+                    "Fix attempt {} left uncommitted changes; skipping its recheck",
+                    fix_try
+                ));
+                fix_try += 1;
+                continue;
+            }
             if has_tag(&fix_result.output_text, "BLOCKED") {
                 ui.step_error("Fix build is BLOCKED");
-                finalize_session(
-                    &session_path,
-                    started_epoch,
-                    total_cost,
-                    "BLOCKED",
-                    total_iterations_base + iterations_completed,
-                )?;
-                clear_resume_state(&cfg);
-                return Ok(EXIT_BLOCKED);
+                return run.finish("BLOCKED", EXIT_BLOCKED);
             }
 
             ui.phase("review (recheck)");
-            let recheck_prompt_text = review_prompt(
-                &spec_dir,
-                &cfg,
-                if before_sha.is_empty() {
-                    None
-                } else {
-                    Some(before_sha.as_str())
-                },
+            ui.emit(
+                "phase_start",
+                json!({ "phase": "recheck", "attempt": fix_try }),
             );
-            let recheck_result =
-                run_claude(&recheck_prompt_text, &cfg, args.dry_run, args.verbose)?;
-            register_claude_session(&session_path, &recheck_result.claude_session_id)?;
-            total_cost += recheck_result.cost_usd;
-            fix_total_cost += recheck_result.cost_usd;
-
-            review_status =
-                parse_kv(&recheck_result.output_text, "REVIEW_STATUS").unwrap_or_default();
-            must_fix_count = parse_kv(&recheck_result.output_text, "MUST_FIX_COUNT")
-                .and_then(|s| s.parse::<u32>().ok())
-                .unwrap_or(0);
-            should_fix_count = parse_kv(&recheck_result.output_text, "SHOULD_FIX_COUNT")
-                .and_then(|s| s.parse::<u32>().ok())
-                .unwrap_or(0);
-            review_findings = recheck_result.output_text.clone();
-
-            let recheck_phase_status = format!(
-                "{}; must_fix={}; should_fix={}",
-                if review_status.is_empty() {
-                    "unknown"
-                } else {
-                    &review_status
+            let recheck_scope = check_scope(&task_file, &spec_dir, &before_sha);
+            if let Some(report) = recheck_scope.as_ref().filter(|r| !r.is_clean()) {
+                report_scope(ui, &session_path, report, &cfg.files_scope_policy)?;
+            }
+            let recheck_scope_text = recheck_scope
+                .as_ref()
+                .map(ScopeReport::to_markdown)
+                .unwrap_or_default();
+            let recheck_prompt_text = render_prompt(
+                PromptKind::Review,
+                &PromptContext {
+                    spec_dir: &spec_dir,
+                    cfg: &cfg,
+                    task_file: Some(&task_file),
+                    before_sha: Some(&before_sha),
+                    review_findings: &review_findings,
+                    files_scope: &recheck_scope_text,
+                    session_id: &session_id,
                 },
-                must_fix_count,
-                should_fix_count
-            );
-            append_run_phase(
+            )?;
+            let statuses_before_recheck = task_statuses(&spec_dir);
+            let spec_before_recheck = SpecSnapshot::take(&spec_dir)?;
+            let recheck_round = run_review_round(
+                ui,
+                &cfg,
+                args,
                 &session_path,
-                &format!("Review (recheck {})", fix_try),
-                &recheck_phase_status,
-                recheck_result.cost_usd,
-                recheck_result.duration_ms,
-                &recheck_result.output_text,
                 &recheck_prompt_text,
-                &recheck_result.claude_session_id,
+                &format!("Review (recheck {})", fix_try),
+                "recheck",
+            )?;
+            revert_illegal_status_edits(
+                ui,
+                &cfg,
+                &session_path,
+                run.loop_index,
+                "recheck",
+                &statuses_before_recheck,
+                &task_file,
+                &[TaskStatus::Blocked],
+            )?;
+            enforce_spec_snapshot(
+                ui,
+                &session_path,
+                "recheck",
+                &spec_before_recheck,
+                &task_file,
+            )?;
+            if report_policy(ui, &session_path, "recheck", &recheck_round.violations)? {
+                return run.finish("POLICY_VIOLATION", EXIT_ERROR);
+            }
+            let recheck_ok = recheck_round.valid;
+            run.total_cost += recheck_round.cost_usd;
+            fix_total_cost += recheck_round.cost_usd;
+
+            let mut recheck = recheck_round.report;
+            if let Some(report) = &recheck_scope {
+                apply_scope_policy(&cfg.files_scope_policy, report, &mut recheck);
+            }
+            policy_violations.extend(recheck_round.violations);
+            apply_policy_violations(&policy_violations, &mut recheck);
+            record_review_round(
+                ui,
+                &session_path,
+                run.loop_index,
+                &next_task_name,
+                fix_try,
+                &mut recheck,
+                &open_findings,
             )?;
+            review_status = recheck.status.clone();
+            must_fix_count = recheck.must_fix_count;
+            should_fix_count = recheck.should_fix_count;
+            review_findings = findings_for_fix(&recheck, &recheck_round.output);
+            let resolved = recheck.resolved;
+            open_findings = recheck.findings;
+
+            ui.emit(
+                "review_verdict",
+                json!({
+                    "status": review_status,
+                    "must_fix": must_fix_count,
+                    "should_fix": should_fix_count,
+                    "findings": open_findings,
+                    "resolved": resolved,
+                    "recheck": true,
+                }),
+            );
 
-            if has_tag(&recheck_result.output_text, "BLOCKED") {
+            if recheck_round.blocked {
                 ui.step_error("Review recheck is BLOCKED");
-                finalize_session(
-                    &session_path,
-                    started_epoch,
-                    total_cost,
-                    "BLOCKED",
-                    total_iterations_base + iterations_completed,
-                )?;
-                clear_resume_state(&cfg);
-                return Ok(EXIT_BLOCKED);
+                return run.finish("BLOCKED", EXIT_BLOCKED);
             }
 
-            if review_status != "FAIL" && must_fix_count == 0 {
+            if recheck_ok && review_status == "PASS" && must_fix_count == 0 {
                 ui.step_ok(&format!("PASS after fix attempt {}", fix_try));
                 fix_passed = true;
                 break;
@@ -929,39 +2214,64 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
         }
 
         if !fix_passed {
-            ui.step_warn(&format!(
-                "Review still failing after {} fix attempts; leaving task in-review and continuing",
-                cfg.max_review_fix_loops
-            ));
-            if let Some(task_file) = &next_task_file {
-                let _ = set_task_status(task_file, TaskStatus::InReview);
+            let failed_head = head_sha();
+            let mut outcome = "review-failed";
+            if cfg.rollback_on_fail != "off" {
+                ui.step_warn(&format!(
+                    "Review still failing after {} fix attempts; rolling back ({})",
+                    cfg.max_review_fix_loops, cfg.rollback_on_fail
+                ));
+                match rollback_iteration(
+                    ui,
+                    &cfg,
+                    &spec_dir,
+                    &session_path,
+                    run.loop_index,
+                    (&before_sha, &failed_head),
+                    &cfg.rollback_on_fail,
+                ) {
+                    Ok(_) => outcome = "rolled-back",
+                    Err(err) => ui.step_warn(&format!("Rollback skipped: {:#}", err)),
+                }
+            } else {
+                ui.step_warn(&format!(
+                    "Review still failing after {} fix attempts; leaving task in-review and continuing",
+                    cfg.max_review_fix_loops
+                ));
             }
+            move_task(
+                &cfg,
+                &session_path,
+                run.loop_index,
+                &task_file,
+                if outcome == "rolled-back" {
+                    TaskStatus::from_str(&cfg.rollback_task_status)
+                } else {
+                    TaskStatus::InReview
+                },
+            )?;
             cb.record(
                 has_spec_progress(&spec_dir, remaining_before, &signature_before),
                 cfg.cb_no_progress_threshold,
                 ui,
             )?;
-            append_iteration_log(
-                &session_path,
-                IterationLogInput {
-                    index: loop_index,
-                    task_name: &next_task_name,
-                    outcome: "review-failed",
-                    duration_seconds: iteration_start.elapsed().as_secs(),
-                    cost_usd: build_cost + review_cost + fix_total_cost,
-                    must_fix_count,
-                    should_fix_count,
-                    commit_sha: &head_sha(),
-                },
+            run.log_iteration(
+                outcome,
+                build_cost + review_cost + fix_total_cost,
+                (must_fix_count, should_fix_count),
+                (&failed_head, &before_sha),
             )?;
-            iterations_completed += 1;
-            loop_index += 1;
+            run.next_iteration();
             continue;
         }
 
-        if let Some(task_file) = &next_task_file {
-            let _ = set_task_status(task_file, TaskStatus::Done);
-        }
+        move_task(
+            &cfg,
+            &session_path,
+            run.loop_index,
+            &task_file,
+            TaskStatus::Done,
+        )?;
 
         let remaining = count_remaining(&spec_dir);
         ui.task_complete("Task done", remaining);
@@ -972,22 +2282,14 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
         )?;
 
         let after_fix_sha = head_sha();
-        append_iteration_log(
-            &session_path,
-            IterationLogInput {
-                index: loop_index,
-                task_name: &next_task_name,
-                outcome: "pass-after-fix",
-                duration_seconds: iteration_start.elapsed().as_secs(),
-                cost_usd: build_cost + review_cost + fix_total_cost,
-                must_fix_count,
-                should_fix_count,
-                commit_sha: &after_fix_sha,
-            },
+        run.log_iteration(
+            "pass-after-fix",
+            build_cost + review_cost + fix_total_cost,
+            (must_fix_count, should_fix_count),
+            (&after_fix_sha, &before_sha),
         )?;
 
-        iterations_completed += 1;
-        loop_index += 1;
+        run.next_iteration();
     }
 
     if args.once {
@@ -996,24 +2298,10 @@ pub fn cmd_run(args: &RunArgs, ui: &Ui) -> Result<i32> {
             "Single cycle completed (--once). {} tasks may remain.",
             remaining
         ));
-        finalize_session(
-            &session_path,
-            started_epoch,
-            total_cost,
-            "ONCE",
-            total_iterations_base + iterations_completed,
-        )?;
-        clear_resume_state(&cfg);
-        return Ok(EXIT_OK);
+        return run.finish("ONCE", EXIT_OK);
     }
 
-    finalize_session(
-        &session_path,
-        started_epoch,
-        total_cost,
-        "MAX_ITERATIONS",
-        total_iterations_base + iterations_completed,
-    )?;
+    run.end("MAX_ITERATIONS")?;
     clear_resume_state(&cfg);
     ui.step_error(&format!(
         "Reached max loops ({}) with pending tasks still present",
@@ -1027,73 +2315,562 @@ fn preflight(cfg: &Config) -> Result<()> {
         bail!("Required command not found: {}", cfg.claude_bin);
     }
 
-    if !Path::new(&cfg.speclooprc).exists() {
-        bail!("No .speclooprc found. Run 'spec-loop init' first.");
+    if cfg.config_file.is_empty() {
+        bail!("No {} found. Run 'spec-loop init' first.", CONFIG_FILE);
+    }
+
+    validate_config(cfg)?;
+
+    fs::create_dir_all(&cfg.session_dir)
+        .with_context(|| format!("failed to create {}", cfg.session_dir))?;
+
+    if !Path::new(&cfg.specs_dir).is_dir() {
+        bail!(
+            "Specs directory not found: {}\n  Run 'spec-loop init' or create it manually.",
+            cfg.specs_dir
+        );
+    }
+
+    Ok(())
+}
+
+fn warn_ignored_keys(ui: &Ui, cfg: &Config) {
+    for name in &cfg.ignored_keys {
+        ui.step_warn(&format!(
+            "{}: ignoring unknown key {}",
+            cfg.config_file, name
+        ));
+    }
+}
+
+/// Print each setting a spec or task overrides.
+fn report_overrides(ui: &Ui, base: &Config, scoped: &Config) {
+    for key in KEYS.iter().filter(|k| scoped.origin(k) != base.origin(k)) {
+        ui.step_info(&format!(
+            "Override {} = {} ({})",
+            key.path(),
+            toml_value(key, &key.get(scoped)),
+            scoped.origin(key)
+        ));
+    }
+}
+
+/// Checks on setting values that loading cannot make, shared by `run` and
+/// `config validate`.
+fn validate_config(cfg: &Config) -> Result<()> {
+    if cfg.max_loops == 0 {
+        bail!("MAX_LOOPS must be a positive integer (got: 0)");
+    }
+    if cfg.max_review_fix_loops == 0 {
+        bail!("MAX_REVIEW_FIX_LOOPS must be a positive integer (got: 0)");
+    }
+    if cfg.review_strategy == "consensus" {
+        if cfg.review_count == 0 {
+            bail!("REVIEW_COUNT must be a positive integer (got: 0)");
+        }
+        if cfg.effective_review_quorum() > cfg.review_count {
+            bail!(
+                "REVIEW_QUORUM ({}) cannot exceed REVIEW_COUNT ({})",
+                cfg.review_quorum,
+                cfg.review_count
+            );
+        }
+    }
+
+    for (i, target) in cfg.targets.iter().enumerate() {
+        let path = Path::new(&target.path);
+        if target.path.is_empty() || path.is_absolute() || target.path.split('/').any(|p| p == "..")
+        {
+            bail!(
+                "targets: path must be a directory inside the project (got: \"{}\")",
+                target.path
+            );
+        }
+        if !path.is_dir() {
+            bail!("targets: {} is not a directory", target.path);
+        }
+        if cfg.targets[..i].iter().any(|t| t.path == target.path) {
+            bail!("targets: {} is listed twice", target.path);
+        }
+    }
+
+    commit_subject(cfg, Path::new(&cfg.specs_dir), None, "build")
+        .context("COMMIT_TEMPLATE is invalid")?;
+
+    Policy::load(Path::new(&cfg.policy_file))?;
+    Ok(())
+}
+
+fn loop_complete(
+    session_path: &Path,
+    spec_dir: &Path,
+    total_tasks: usize,
+    started_epoch: i64,
+    total_cost: f64,
+    iterations: u32,
+    ui: &Ui,
+) -> Result<()> {
+    let end_epoch = chrono::Utc::now().timestamp();
+    let duration_s = (end_epoch - started_epoch).max(0) as u64;
+    let panel_width = ui.panel_width(52, 72);
+
+    ui.box_header("Complete", panel_width);
+    ui.box_empty(panel_width);
+    ui.box_line("  All tasks done", panel_width);
+    ui.box_empty(panel_width);
+    let pr = write_pr_description(spec_dir, session_path)?;
+
+    ui.box_line(
+        &format!("  Spec        {}", get_spec_name(spec_dir)),
+        panel_width,
+    );
+    ui.box_line(
+        &format!("  Tasks       {} completed", total_tasks),
+        panel_width,
+    );
+    ui.box_line(&format!("  Iterations  {}", iterations), panel_width);
+    ui.box_line(
+        &format!("  Duration    {}", format_duration(duration_s)),
+        panel_width,
+    );
+    ui.box_line(
+        &format!("  Cost        {}", format_cost(total_cost)),
+        panel_width,
+    );
+    ui.box_empty(panel_width);
+    ui.box_footer(panel_width);
+    if let Some(path) = &pr {
+        ui.step_info(&format!("PR description: {}", path.display()));
+    }
+
+    end_session(
+        ui,
+        session_path,
+        started_epoch,
+        total_cost,
+        "COMPLETE",
+        iterations,
+    )
+}
+
+/// Handle changes a build or fix phase left uncommitted according to
+/// `COMMIT_POLICY`: commit them with the configured message and session
+/// trailer, or report them and return false so the phase fails.
+fn enforce_commit(
+    ui: &Ui,
+    cfg: &Config,
+    spec_dir: &Path,
+    session_path: &Path,
+    (session_id, phase): (&str, &str),
+    task_file: &Path,
+) -> Result<bool> {
+    if cfg.commit_policy == "off" || !is_git_repo() {
+        return Ok(true);
+    }
+    let pending = unrelated_changes(spec_dir, Path::new(&cfg.session_dir));
+    if pending.is_empty() {
+        return Ok(true);
+    }
+    let listing: String = pending.iter().map(|p| format!("- {}\n", p)).collect();
+    if cfg.commit_policy == "fail" {
+        append_run_note(
+            session_path,
+            &format!("Uncommitted changes after {}", phase),
+            &listing,
+        )?;
+        ui.step_error(&format!(
+            "{} left {} uncommitted change(s) (COMMIT_POLICY=fail)",
+            phase,
+            pending.len()
+        ));
+        return Ok(false);
+    }
+
+    let subject = commit_subject(cfg, spec_dir, Some(task_file), phase)?;
+    let sha = commit_paths(&pending, &subject, session_id)?;
+    ui.step_warn(&format!(
+        "{} left {} uncommitted change(s); committed as {}",
+        phase,
+        pending.len(),
+        &sha[..sha.len().min(7)]
+    ));
+    append_run_note(
+        session_path,
+        &format!("Harness commit after {}", phase),
+        &format!("{} {}\n\n{}", &sha[..sha.len().min(7)], subject, listing),
+    )?;
+    Ok(true)
+}
+
+/// Revert spec directory edits `phase` was not allowed to make and report them.
+fn enforce_spec_snapshot(
+    ui: &Ui,
+    session_path: &Path,
+    phase: &str,
+    snapshot: &SpecSnapshot,
+    task_file: &Path,
+) -> Result<()> {
+    let reverted = snapshot.enforce(Some(task_file))?;
+    if reverted.is_empty() {
+        return Ok(());
+    }
+    for item in &reverted {
+        ui.step_warn(&format!("{}: {}; reverted", phase, item));
+    }
+    let body: String = reverted.iter().map(|r| format!("- {}\n", r)).collect();
+    append_run_note(session_path, &format!("Spec guard ({})", phase), &body)?;
+    ui.emit(
+        "spec_guard",
+        json!({ "phase": phase, "reverted": reverted }),
+    );
+    Ok(())
+}
+
+/// Back spec file changes out of the commits `phase` made since `base`; the
+/// agent must leave spec files unstaged.
+fn reject_spec_commits(
+    ui: &Ui,
+    spec_dir: &Path,
+    session_path: &Path,
+    (session_id, phase): (&str, &str),
+    base: &str,
+) -> Result<()> {
+    if base.is_empty() || !is_git_repo() {
+        return Ok(());
+    }
+    let committed = committed_spec_files(spec_dir, base);
+    if committed.is_empty() {
+        return Ok(());
+    }
+    uncommit_spec_files(
+        spec_dir,
+        base,
+        &format!("Revert spec file changes committed during {}", phase),
+        session_id,
+    )?;
+    ui.step_warn(&format!(
+        "{} committed {} spec file(s); backed them out of the branch",
+        phase,
+        committed.len()
+    ));
+    let body: String = committed.iter().map(|f| format!("- {}\n", f)).collect();
+    append_run_note(
+        session_path,
+        &format!("Spec files committed during {}", phase),
+        &body,
+    )
+}
+
+/// Detached HEAD or a protected branch, with the policy that applies.
+fn branch_problem<'a>(cfg: &'a Config, branch: &str) -> Option<(&'static str, &'a str)> {
+    if branch.is_empty() {
+        Some(("HEAD is detached", &cfg.git_detached_policy))
+    } else if cfg.is_protected_branch(branch) {
+        Some(("on a protected branch", &cfg.git_protected_policy))
+    } else {
+        None
+    }
+}
+
+/// Remember the branch and the commit the spec's work started from: the
+/// fork point when spec-loop just switched branches, otherwise the base an
+/// earlier session recorded, otherwise HEAD.
+fn record_spec_base(
+    cfg: &Config,
+    session_path: &Path,
+    spec_dir: &Path,
+    start_branch: &str,
+) -> Result<()> {
+    let data = read_session_json(&session_json_path(session_path))?;
+    if !data.base_sha.is_empty() {
+        return Ok(());
+    }
+    let branch = current_branch();
+    let base = if !start_branch.is_empty() && start_branch != branch {
+        git(&["merge-base", "HEAD", start_branch]).unwrap_or_else(|_| head_sha())
+    } else {
+        session_base_for_spec(Path::new(&cfg.session_dir), spec_dir, &branch)
+            .unwrap_or_else(head_sha)
+    };
+    record_session_base(session_path, &branch, &base)
+}
+
+/// Write `pr-description.md` to the session directory from the spec, the
+/// session's iterations and reviews, and the commits since the base.
+fn write_pr_description(spec_dir: &Path, session_path: &Path) -> Result<Option<PathBuf>> {
+    let data = read_session_json(&session_json_path(session_path))?;
+    if data.base_sha.is_empty() {
+        return Ok(None);
+    }
+    let log = git(&[
+        "log",
+        "--reverse",
+        "--format=%h %s",
+        &format!("{}..HEAD", data.base_sha),
+    ])
+    .unwrap_or_default();
+    let commits: Vec<String> = log.lines().map(ToOwned::to_owned).collect();
+    let path = session_path.join("pr-description.md");
+    fs::write(&path, pr_description(spec_dir, &data, &commits))
+        .with_context(|| format!("failed to write {}", path.display()))?;
+    Ok(Some(path))
+}
+
+/// Check git state before letting the agent work: merges or rebases in
+/// progress, detached HEAD, protected branches and changes spec-loop did not
+/// make. Each follows its `GIT_*_POLICY`; refusals are returned as an error.
+/// Branch warnings are shown only in the preflight call (`session_path` is
+/// `None`); later calls catch what the previous iteration left behind.
+fn check_worktree(
+    ui: &Ui,
+    cfg: &Config,
+    spec_dir: &Path,
+    spec_name: &str,
+    session_path: Option<&Path>,
+) -> Result<()> {
+    if !is_git_repo() {
+        return Ok(());
+    }
+    let preflight = session_path.is_none();
+    let mut refusals = vec![];
+    let mut notes = vec![];
+
+    if let Some(op) = operation_in_progress() {
+        let msg = format!("a {} is in progress", op);
+        if cfg.git_in_progress_policy == "refuse" {
+            refusals.push(format!("{} (finish or abort it)", msg));
+        } else {
+            ui.step_warn(&format!("Git: {}", msg));
+            notes.push(msg);
+        }
+    }
+
+    let session_dir = Path::new(&cfg.session_dir);
+    let dirty = unrelated_changes(spec_dir, session_dir);
+    if !dirty.is_empty() {
+        let msg = format!(
+            "{} uncommitted change(s): {}",
+            dirty.len(),
+            dirty.join(", ")
+        );
+        match cfg.git_dirty_policy.as_str() {
+            "refuse" => refusals.push(format!("{} (commit or stash them)", msg)),
+            "stash" if refusals.is_empty() => {
+                stash_paths(
+                    &dirty,
+                    &format!("spec-loop: before {} ({})", spec_name, now_human()),
+                )?;
+                ui.step_warn(&format!(
+                    "Git: stashed {} uncommitted change(s); restore them with `git stash pop`",
+                    dirty.len()
+                ));
+                notes.push(format!("stashed {}", msg));
+            }
+            _ => {
+                ui.step_warn(&format!("Git: {}", msg));
+                notes.push(msg);
+            }
+        }
+    }
+
+    let branch = current_branch();
+    let own_branch = spec_branch(spec_name);
+    if cfg.branch_strategy == "per-spec" {
+        if branch != own_branch && refusals.is_empty() {
+            let created = switch_branch(&own_branch)?;
+            let msg = format!(
+                "{} branch {}",
+                if created { "created" } else { "switched to" },
+                own_branch
+            );
+            if preflight {
+                ui.step_ok(&format!("Git: {}", msg));
+            } else {
+                ui.step_warn(&format!("Git: left {}; {}", own_branch, msg));
+                notes.push(msg);
+            }
+        }
+    } else if let Some((problem, policy)) = branch_problem(cfg, &branch) {
+        let msg = if branch.is_empty() {
+            problem.to_string()
+        } else {
+            format!("{} ({})", problem, branch)
+        };
+        match policy {
+            "refuse" => refusals.push(msg),
+            "branch" if refusals.is_empty() => {
+                let created = switch_branch(&own_branch)?;
+                ui.step_ok(&format!(
+                    "Git: {}; {} branch {}",
+                    msg,
+                    if created { "created" } else { "switched to" },
+                    own_branch
+                ));
+                notes.push(format!("{}; switched to {}", msg, own_branch));
+            }
+            _ if preflight => {
+                ui.step_warn(&format!("Git: {}", msg));
+                notes.push(msg);
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(session_path) = session_path {
+        if !notes.is_empty() || !refusals.is_empty() {
+            let body: String = notes
+                .iter()
+                .chain(&refusals)
+                .map(|n| format!("- {}\n", n))
+                .collect();
+            append_run_note(session_path, "Working tree", &body)?;
+        }
+    }
+    if !refusals.is_empty() {
+        bail!("unsafe working tree: {}", refusals.join("; "));
+    }
+    Ok(())
+}
+
+/// The session `cmd_run` is driving: its totals and the current iteration.
+struct RunSession<'a> {
+    ui: &'a Ui,
+    cfg: &'a Config,
+    path: &'a Path,
+    started_epoch: i64,
+    /// Iterations recorded by earlier runs of this session.
+    iterations_base: u32,
+    iterations_completed: u32,
+    loop_index: u32,
+    total_cost: f64,
+    task_name: String,
+    iteration_start: Instant,
+}
+
+impl RunSession<'_> {
+    fn iterations(&self) -> u32 {
+        self.iterations_base + self.iterations_completed
+    }
+
+    fn begin_iteration(&mut self, task_name: &str) {
+        self.task_name = task_name.to_string();
+        self.iteration_start = Instant::now();
+    }
+
+    fn next_iteration(&mut self) {
+        self.iterations_completed += 1;
+        self.loop_index += 1;
     }
 
-    if cfg.max_loops == 0 {
-        bail!("MAX_LOOPS must be a positive integer (got: 0)");
+    fn log_iteration(
+        &self,
+        outcome: &str,
+        cost_usd: f64,
+        (must_fix_count, should_fix_count): (u32, u32),
+        (commit_sha, before_sha): (&str, &str),
+    ) -> Result<()> {
+        append_iteration_log(
+            self.path,
+            IterationLogInput {
+                index: self.loop_index,
+                task_name: &self.task_name,
+                outcome,
+                duration_seconds: self.iteration_start.elapsed().as_secs(),
+                cost_usd,
+                must_fix_count,
+                should_fix_count,
+                commit_sha,
+                before_sha,
+            },
+        )
     }
-    if cfg.max_review_fix_loops == 0 {
-        bail!("MAX_REVIEW_FIX_LOOPS must be a positive integer (got: 0)");
+
+    /// Record an iteration that made no progress and move on to the next.
+    fn iteration_failed(
+        &mut self,
+        cb: &mut CircuitBreaker,
+        outcome: &str,
+        cost_usd: f64,
+        shas: (&str, &str),
+    ) -> Result<()> {
+        cb.record(false, self.cfg.cb_no_progress_threshold, self.ui)?;
+        self.log_iteration(outcome, cost_usd, (0, 0), shas)?;
+        self.next_iteration();
+        Ok(())
     }
 
-    fs::create_dir_all(&cfg.session_dir)
-        .with_context(|| format!("failed to create {}", cfg.session_dir))?;
+    fn end(&self, exit_reason: &str) -> Result<()> {
+        end_session(
+            self.ui,
+            self.path,
+            self.started_epoch,
+            self.total_cost,
+            exit_reason,
+            self.iterations(),
+        )
+    }
 
-    if !Path::new(&cfg.specs_dir).is_dir() {
-        bail!(
-            "Specs directory not found: {}\n  Run 'spec-loop init' or create it manually.",
-            cfg.specs_dir
-        );
+    /// End the session and drop the resume state; returns `code`.
+    fn finish(&self, exit_reason: &str, code: i32) -> Result<i32> {
+        self.end(exit_reason)?;
+        clear_resume_state(self.cfg);
+        Ok(code)
     }
 
-    Ok(())
+    /// Every task is done.
+    fn complete(&self, spec_dir: &Path, total_tasks: usize) -> Result<i32> {
+        loop_complete(
+            self.path,
+            spec_dir,
+            total_tasks,
+            self.started_epoch,
+            self.total_cost,
+            self.iterations(),
+            self.ui,
+        )?;
+        clear_resume_state(self.cfg);
+        Ok(EXIT_OK)
+    }
 }
 
-fn loop_complete(
+fn end_session(
+    ui: &Ui,
     session_path: &Path,
-    spec_name: &str,
-    total_tasks: usize,
     started_epoch: i64,
     total_cost: f64,
+    exit_reason: &str,
     iterations: u32,
-    ui: &Ui,
 ) -> Result<()> {
-    let end_epoch = chrono::Utc::now().timestamp();
-    let duration_s = (end_epoch - started_epoch).max(0) as u64;
-    let panel_width = ui.panel_width(52, 72);
-
-    ui.box_header("Complete", panel_width);
-    ui.box_empty(panel_width);
-    ui.box_line("  All tasks done", panel_width);
-    ui.box_empty(panel_width);
-    ui.box_line(&format!("  Spec        {}", spec_name), panel_width);
-    ui.box_line(
-        &format!("  Tasks       {} completed", total_tasks),
-        panel_width,
-    );
-    ui.box_line(&format!("  Iterations  {}", iterations), panel_width);
-    ui.box_line(
-        &format!("  Duration    {}", format_duration(duration_s)),
-        panel_width,
-    );
-    ui.box_line(
-        &format!("  Cost        {}", format_cost(total_cost)),
-        panel_width,
+    ui.emit(
+        "exit",
+        json!({
+            "reason": exit_reason,
+            "iterations": iterations,
+            "cost_usd": total_cost,
+        }),
     );
-    ui.box_empty(panel_width);
-    ui.box_footer(panel_width);
-
     finalize_session(
         session_path,
         started_epoch,
         total_cost,
-        "COMPLETE",
+        exit_reason,
         iterations,
     )
 }
 
+fn phase_end(ui: &Ui, phase: &str, status: &str, result: &ClaudeResult) {
+    ui.emit(
+        "phase_end",
+        json!({
+            "phase": phase,
+            "status": status,
+            "cost_usd": result.cost_usd,
+            "duration_ms": result.duration_ms,
+            "claude_session_id": result.claude_session_id,
+        }),
+    );
+}
+
 fn new_session_path(cfg: &Config, spec_name: &str) -> PathBuf {
     let session_id = format!("{}_{}", now_stamp(), slugify(spec_name));
     Path::new(&cfg.session_dir).join(session_id)
@@ -1115,20 +2892,84 @@ fn prompt_with_default(label: &str, default: &str) -> Result<String> {
     }
 }
 
-fn write_speclooprc(project_type: &str, verify_cmd: &str, test_cmd: &str) -> Result<()> {
-    let content = format!(
-        "# spec-loop configuration\n# Generated by spec-loop init\n\n# Project\nPROJECT_TYPE=\"{}\"\nVERIFY_COMMAND=\"{}\"\nTEST_COMMAND=\"{}\"\n\n# Claude Code\n# CLAUDE_MODEL=\"\"\n\n# Loop limits\n# MAX_LOOPS={}\n# MAX_REVIEW_FIX_LOOPS={}\n# MAX_TASKS_PER_RUN=0\n\n# Safety\n# CB_NO_PROGRESS_THRESHOLD=3\n# CB_COOLDOWN_MINUTES=30\n\n# Paths\n# SPECS_DIR=\".agents/specs\"\n# SESSION_DIR=\".spec-loop/sessions\"\n",
-        project_type,
-        verify_cmd,
-        test_cmd,
-        DEFAULT_MAX_LOOPS,
-        DEFAULT_MAX_REVIEW_FIX_LOOPS,
+/// Commented-out defaults written below `[project]` by `spec-loop init`.
+const CONFIG_TEMPLATE_REST: &str = r#"
+[agent]
+# bin = "claude"
+# model = ""
+# extra_args = ""
+# permission_mode = "bypass"  # bypass | allowlist
+# allowed_tools = []  # extra tools for allowlist mode
+
+[phases]
+# files_scope_policy = "warn"  # warn | must-fix | block
+# contract_retries = 2
+# phase_timeout_minutes = 0  # kill an agent phase after this long; 0 = no limit
+# review_strategy = "single"  # single | consensus
+# review_count = 3
+# review_quorum = 2
+# review_models = []  # cycled across reviewers
+# commit_policy = "commit"  # off | commit | fail (for changes the agent left uncommitted)
+# commit_template = "feat({{spec}}): task {{task_number}} {{task_title}}"
+# rollback_on_fail = "off"  # off | reset | revert
+# rollback_task_status = "pending"  # pending | blocked
+# task_history = false  # also log status changes in each task file
+
+[limits]
+# max_loops = 25
+# max_review_fix_loops = 3
+# max_tasks_per_run = 0
+# budget_usd = 0  # stop a run once it has cost this much; 0 = no limit
+
+[safety]
+# cb_no_progress_threshold = 3
+# cb_cooldown_minutes = 30
+# policy_file = ".agents/policy.toml"  # protected paths and forbidden commands
+# branch_strategy = "current"  # current | per-spec
+# git_dirty_policy = "warn"  # warn | refuse | stash
+# git_detached_policy = "warn"  # warn | refuse | branch
+# git_protected_policy = "warn"  # warn | refuse | branch
+# git_protected_branches = ["main", "master"]
+# git_in_progress_policy = "refuse"  # warn | refuse
+
+[paths]
+# specs_dir = ".agents/specs"
+# session_dir = ".spec-loop/sessions"
+"#;
+
+fn write_config_file(
+    project_type: &str,
+    verify_cmd: &str,
+    test_cmd: &str,
+    targets: &[Target],
+) -> Result<()> {
+    let quote = |v: &str| toml::Value::String(v.to_string()).to_string();
+    let mut content = format!(
+        "# spec-loop configuration\n# Generated by spec-loop init\n\n[project]\ntype = {}\nverify_command = {}\ntest_command = {}\n{}",
+        quote(project_type),
+        quote(verify_cmd),
+        quote(test_cmd),
+        CONFIG_TEMPLATE_REST,
     );
-    fs::write(".speclooprc", content).context("failed writing .speclooprc")
+    if !targets.is_empty() {
+        content.push_str("\n# Subprojects; a task gets the commands for the files it declares.\n");
+    }
+    for target in targets {
+        content.push_str(&format!(
+            "\n[[targets]]\npath = {}\ntype = {}\nverify_command = {}\ntest_command = {}\n",
+            quote(&target.path),
+            quote(&target.project_type),
+            quote(&target.verify_command),
+            quote(&target.test_command),
+        ));
+    }
+    fs::write(CONFIG_FILE, content).with_context(|| format!("failed writing {}", CONFIG_FILE))
 }
 
 fn install_templates() -> Result<()> {
     fs::create_dir_all(".agents/templates").context("failed creating .agents/templates")?;
+    fs::create_dir_all(PROMPT_TEMPLATES_DIR)
+        .with_context(|| format!("failed creating {PROMPT_TEMPLATES_DIR}"))?;
 
     let templates = [
         (
@@ -1151,9 +2992,67 @@ fn install_templates() -> Result<()> {
         }
     }
 
+    for kind in PromptKind::ALL {
+        let path = kind.override_path();
+        if !path.exists() {
+            fs::write(&path, kind.stamped_template())
+                .with_context(|| format!("failed writing {}", path.display()))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Replace the installed prompt templates with the built-in ones. Edited
+/// copies older than this binary are kept next to them as `<name>.md.bak`;
+/// edited copies that are current are left alone.
+fn update_templates(ui: &Ui) -> Result<()> {
+    fs::create_dir_all(PROMPT_TEMPLATES_DIR)
+        .with_context(|| format!("failed creating {PROMPT_TEMPLATES_DIR}"))?;
+    for (kind, state) in installed_templates() {
+        let path = kind.override_path();
+        let installed = fs::read_to_string(&path).unwrap_or_default();
+        if installed == kind.stamped_template() {
+            ui.step_info(&format!("{} is up to date", path.display()));
+            continue;
+        }
+        if state == TemplateState::Current && load_template(kind)?.0 != kind.default_template() {
+            ui.step_info(&format!(
+                "{} is customized and current; left as is",
+                path.display()
+            ));
+            continue;
+        }
+        if state == TemplateState::OutdatedCustomized {
+            let backup = path.with_extension("md.bak");
+            fs::write(&backup, &installed)
+                .with_context(|| format!("failed writing {}", backup.display()))?;
+            ui.step_warn(&format!(
+                "{} was customized; your copy is in {}",
+                path.display(),
+                backup.display()
+            ));
+        }
+        fs::write(&path, kind.stamped_template())
+            .with_context(|| format!("failed writing {}", path.display()))?;
+        ui.step_ok(&format!("Updated {}", path.display()));
+    }
     Ok(())
 }
 
+/// Warn about edited prompt templates written for an older spec-loop.
+fn warn_outdated_templates(ui: &Ui) {
+    for (kind, state) in installed_templates() {
+        if state == TemplateState::OutdatedCustomized {
+            ui.step_warn(&format!(
+                "{} is customized but predates this spec-loop's {} prompt; run 'spec-loop init --update-templates' and merge your changes back",
+                kind.override_path().display(),
+                kind.name()
+            ));
+        }
+    }
+}
+
 fn default_agents_md() -> &'static str {
     "# AGENTS.md\n\nProject conventions and architecture rules for AI agents.\n\n## Project Overview\n\n<!-- Describe what this project does, its architecture, and key technologies. -->\n\n## Conventions\n\n<!-- Add your project's coding conventions:\n- Naming: files, functions, variables\n- Structure: where things live, how modules are organized\n- Patterns: common patterns to follow\n- Anti-patterns: things to avoid\n-->\n\n## Review Checklist\n\n<!-- Add project-specific review criteria:\n- Architecture rules\n- Type safety requirements\n- Test coverage expectations\n- Security considerations\n-->\n"
 }
@@ -1208,6 +3107,729 @@ fn recent_commits() -> Option<Vec<String>> {
     }
 }
 
+fn report_scope(ui: &Ui, session_path: &Path, report: &ScopeReport, policy: &str) -> Result<()> {
+    ui.step_warn(&format!(
+        "Files scope: {} undeclared, {} untouched (policy: {})",
+        report.undeclared.len(),
+        report.untouched.len(),
+        policy
+    ));
+    append_run_note(session_path, "Files scope", &report.to_markdown())
+}
+
+/// Under the must-fix and block policies, undeclared files fail the review
+/// and are handed to the fix phase as must-fix findings.
+fn apply_scope_policy(policy: &str, report: &ScopeReport, review: &mut ReviewReport) {
+    if policy == "warn" || report.undeclared.is_empty() {
+        return;
+    }
+    review.status = "FAIL".to_string();
+    for (i, file) in report.undeclared.iter().enumerate() {
+        review.findings.push(ReviewFinding {
+            id: format!("S{}", i + 1),
+            severity: "must".to_string(),
+            file: file.clone(),
+            line: None,
+            description: "Changed but not declared in the task's Files section; revert it or move it out of this task".to_string(),
+            reviewers: vec![],
+        });
+    }
+    let must = review
+        .findings
+        .iter()
+        .filter(|f| f.severity == "must")
+        .count() as u32;
+    review.must_fix_count = review.must_fix_count.max(must);
+}
+
+/// Turn policy violations into must-fix findings that fail the review.
+fn apply_policy_violations(violations: &[Violation], review: &mut ReviewReport) {
+    if violations.is_empty() {
+        return;
+    }
+    review.status = "FAIL".to_string();
+    for (i, v) in violations.iter().enumerate() {
+        let reason = if v.reason.is_empty() {
+            String::new()
+        } else {
+            format!(" ({})", v.reason)
+        };
+        let (file, description) = if v.kind == "path" {
+            (
+                v.subject.clone(),
+                format!("Protected path changed{}; revert this change", reason),
+            )
+        } else {
+            (
+                String::new(),
+                format!(
+                    "Ran forbidden command `{}`{}; undo its effects and do not run it again",
+                    v.subject, reason
+                ),
+            )
+        };
+        review.findings.push(ReviewFinding {
+            id: format!("P{}", i + 1),
+            severity: "must".to_string(),
+            file,
+            line: None,
+            description,
+            reviewers: vec![],
+        });
+    }
+    let must = review
+        .findings
+        .iter()
+        .filter(|f| f.severity == "must")
+        .count() as u32;
+    review.must_fix_count = review.must_fix_count.max(must);
+}
+
+/// Log `phase`'s policy violations; true when one is critical and the run
+/// must stop.
+fn report_policy(
+    ui: &Ui,
+    session_path: &Path,
+    phase: &str,
+    violations: &[Violation],
+) -> Result<bool> {
+    if violations.is_empty() {
+        return Ok(false);
+    }
+    for v in violations {
+        ui.step_warn(&format!("{} {}", phase, v.describe()));
+    }
+    let body: String = violations
+        .iter()
+        .map(|v| format!("- {}\n", v.describe()))
+        .collect();
+    append_run_note(
+        session_path,
+        &format!("Policy violations ({})", phase),
+        &body,
+    )?;
+    ui.emit(
+        "policy_violation",
+        json!({
+            "phase": phase,
+            "violations": violations.iter().map(Violation::describe).collect::<Vec<_>>(),
+        }),
+    );
+    let critical = violations.iter().any(|v| v.critical);
+    if critical {
+        ui.step_error("Critical policy violation; stopping the run");
+    }
+    Ok(critical)
+}
+
+/// A review round: one reviewer, or several merged by quorum when
+/// `REVIEW_STRATEGY=consensus`.
+struct ReviewRound {
+    report: ReviewReport,
+    /// False when the verdict could not be read; such a round never passes.
+    valid: bool,
+    /// Raw review output, handed to the fix phase when no findings parse.
+    output: String,
+    cost_usd: f64,
+    blocked: bool,
+    violations: Vec<Violation>,
+    /// A reviewer hit the phase timeout and the round has no valid verdict.
+    timed_out: bool,
+}
+
+fn run_review_round(
+    ui: &Ui,
+    cfg: &Config,
+    args: &RunArgs,
+    session_path: &Path,
+    prompt: &str,
+    title: &str,
+    phase: &str,
+) -> Result<ReviewRound> {
+    if cfg.review_strategy != "consensus" {
+        let (result, valid) = run_reviewer(ui, cfg, args, session_path, prompt, title, phase)?;
+        return Ok(ReviewRound {
+            report: parse_review(&result.output_text),
+            valid,
+            blocked: has_tag(&result.output_text, "BLOCKED"),
+            cost_usd: result.cost_usd,
+            timed_out: result.timed_out,
+            output: result.output_text,
+            violations: result.policy_violations,
+        });
+    }
+
+    let quorum = cfg.effective_review_quorum();
+    let mut reports = vec![];
+    let mut summary = String::new();
+    let mut output = String::new();
+    let mut cost_usd = 0.0;
+    let mut blocked = false;
+    let mut violations = vec![];
+    let mut valid_count = 0;
+    let mut timed_out = false;
+    for i in 0..cfg.review_count as usize {
+        let mut reviewer_cfg = cfg.clone();
+        reviewer_cfg.claude_model = cfg.reviewer_model(i);
+        let model = if reviewer_cfg.claude_model.is_empty() {
+            "default".to_string()
+        } else {
+            reviewer_cfg.claude_model.clone()
+        };
+        ui.step_info(&format!(
+            "Reviewer {}/{} ({})",
+            i + 1,
+            cfg.review_count,
+            model
+        ));
+        let (result, valid) = run_reviewer(
+            ui,
+            &reviewer_cfg,
+            args,
+            session_path,
+            prompt,
+            &format!("{} - reviewer {} ({})", title, i + 1, model),
+            phase,
+        )?;
+        let report = parse_review(&result.output_text);
+        let passed = valid && report.status == "PASS" && report.must_fix_count == 0;
+        valid_count += u32::from(valid);
+        summary.push_str(&format!(
+            "- Reviewer {} ({}): {}; must_fix={}; should_fix={}\n",
+            i + 1,
+            model,
+            if valid {
+                report.status.as_str()
+            } else if result.timed_out {
+                "timed out"
+            } else {
+                "invalid"
+            },
+            report.must_fix_count,
+            report.should_fix_count
+        ));
+        output.push_str(&format!(
+            "## Reviewer {} ({})\n\n{}\n\n",
+            i + 1,
+            model,
+            result.output_text.trim_end()
+        ));
+        cost_usd += result.cost_usd;
+        blocked |= has_tag(&result.output_text, "BLOCKED");
+        timed_out |= result.timed_out;
+        violations.extend(result.policy_violations);
+        reports.push((report, passed));
+    }
+
+    let passes = reports.iter().filter(|(_, passed)| *passed).count();
+    let report = merge_consensus(&reports, quorum);
+    summary.push_str(&format!(
+        "\n{}/{} passed (quorum {}): {}\n",
+        passes,
+        reports.len(),
+        quorum,
+        report.status
+    ));
+    if passes > 0 && passes < reports.len() {
+        ui.step_warn(&format!(
+            "Reviewers disagree: {}/{} passed (quorum {})",
+            passes,
+            reports.len(),
+            quorum
+        ));
+    }
+    let dissent: String = report
+        .findings
+        .iter()
+        .filter(|f| f.reviewers.len() < reports.len())
+        .map(|f| {
+            let others: Vec<u32> = (1..=reports.len() as u32)
+                .filter(|r| !f.reviewers.contains(r))
+                .collect();
+            format!(
+                "- {} ({}) {} — {}; raised by reviewer {}, not by reviewer {}\n",
+                f.id,
+                f.severity,
+                f.location(),
+                f.description,
+                join_numbers(&f.reviewers),
+                join_numbers(&others)
+            )
+        })
+        .collect();
+    if !dissent.is_empty() {
+        summary.push_str(&format!(
+            "\nFindings not raised by every reviewer:\n\n{}",
+            dissent
+        ));
+    }
+    append_run_note(session_path, &format!("{} consensus", title), &summary)?;
+    ui.emit(
+        "review_consensus",
+        json!({
+            "phase": phase,
+            "passes": passes,
+            "reviewers": reports.len(),
+            "quorum": quorum,
+            "status": report.status,
+        }),
+    );
+
+    Ok(ReviewRound {
+        report,
+        // Too few readable verdicts to reach the quorum.
+        valid: valid_count >= quorum,
+        output,
+        cost_usd,
+        blocked,
+        violations,
+        timed_out: timed_out && valid_count < quorum,
+    })
+}
+
+/// Run one reviewer, enforce its output contract and log the phase.
+fn run_reviewer(
+    ui: &Ui,
+    cfg: &Config,
+    args: &RunArgs,
+    session_path: &Path,
+    prompt: &str,
+    title: &str,
+    phase: &str,
+) -> Result<(ClaudeResult, bool)> {
+    let mut result = run_claude(
+        prompt,
+        cfg,
+        PromptKind::Review.access(),
+        args.dry_run,
+        args.verbose,
+        ui,
+    )?;
+    register_claude_session(session_path, &result.claude_session_id)?;
+    let valid = enforce_contract(
+        ui,
+        cfg,
+        Some(session_path),
+        PromptKind::Review,
+        &mut result,
+        (args.dry_run, args.verbose),
+    )?;
+
+    let status = parse_kv(&result.output_text, "REVIEW_STATUS").unwrap_or_default();
+    let phase_status = format!(
+        "{}; must_fix={}; should_fix={}",
+        if status.is_empty() {
+            "unknown"
+        } else {
+            &status
+        },
+        parse_kv(&result.output_text, "MUST_FIX_COUNT").unwrap_or_else(|| "?".to_string()),
+        parse_kv(&result.output_text, "SHOULD_FIX_COUNT").unwrap_or_else(|| "?".to_string())
+    );
+    append_run_phase(
+        session_path,
+        title,
+        &phase_status,
+        result.cost_usd,
+        result.duration_ms,
+        &result.output_text,
+        prompt,
+        &result.claude_session_id,
+    )?;
+    phase_end(ui, phase, &phase_status, &result);
+    let valid = valid && !result.timed_out;
+    Ok((result, valid))
+}
+
+/// Check a phase's status block and, while it breaks the contract, ask the
+/// agent to restate it, up to `CONTRACT_RETRIES` times. Follow-up output and
+/// cost are folded into `result`. Returns whether the output now complies.
+fn enforce_contract(
+    ui: &Ui,
+    cfg: &Config,
+    session_path: Option<&Path>,
+    kind: PromptKind,
+    result: &mut ClaudeResult,
+    (dry_run, verbose): (bool, bool),
+) -> Result<bool> {
+    let contract = Contract::for_kind(kind);
+    let mut problems = contract.validate(&result.output_text);
+    let mut attempt = 0;
+    while !problems.is_empty() {
+        // A stopped agent cannot restate; the caller reports the timeout.
+        if result.timed_out {
+            return Ok(false);
+        }
+        if attempt >= cfg.contract_retries
+            || result.claude_session_id.is_empty()
+            || dry_run
+            || result.policy_violations.iter().any(|v| v.critical)
+        {
+            ui.step_error(&format!(
+                "{} output breaks the status contract: {}",
+                kind.name(),
+                problems.join("; ")
+            ));
+            if let Some(session_path) = session_path {
+                append_run_note(
+                    session_path,
+                    &format!("Contract violation ({})", kind.name()),
+                    &format!(
+                        "After {} restatement request(s):\n\n{}",
+                        attempt,
+                        problems
+                            .iter()
+                            .map(|p| format!("- {}\n", p))
+                            .collect::<String>()
+                    ),
+                )?;
+            }
+            return Ok(false);
+        }
+
+        attempt += 1;
+        ui.step_warn(&format!(
+            "{}; asking for the status block again ({}/{})",
+            problems.join("; "),
+            attempt,
+            cfg.contract_retries
+        ));
+        ui.emit(
+            "contract_retry",
+            json!({ "phase": kind.name(), "attempt": attempt, "problems": problems }),
+        );
+        let followup = resume_claude(
+            &result.claude_session_id,
+            &contract.restate_prompt(&problems),
+            cfg,
+            kind.access(),
+            verbose,
+            ui,
+        )?;
+        if let Some(session_path) = session_path {
+            register_claude_session(session_path, &followup.claude_session_id)?;
+        }
+        result.output_text = contract.merge(&result.output_text, &followup.output_text);
+        result.cost_usd += followup.cost_usd;
+        result.policy_violations.extend(followup.policy_violations);
+        result.duration_ms += followup.duration_ms;
+        result.timed_out |= followup.timed_out;
+        problems = contract.validate(&result.output_text);
+    }
+    Ok(true)
+}
+
+/// Text handed to the fix phase, and to the recheck as the previous findings.
+fn findings_for_fix(review: &ReviewReport, output: &str) -> String {
+    if review.findings.is_empty() {
+        // Nothing structured to work from; hand over the full review instead.
+        return output.to_string();
+    }
+    findings_checklist(&review.findings)
+}
+
+/// Surface format problems, work out which of the previous findings the
+/// recheck resolved, and record the round in session.json.
+fn record_review_round(
+    ui: &Ui,
+    session_path: &Path,
+    iteration: u32,
+    task_name: &str,
+    round: u32,
+    review: &mut ReviewReport,
+    previous: &[ReviewFinding],
+) -> Result<()> {
+    if !review.errors.is_empty() {
+        ui.step_warn(&format!(
+            "Review output has {} format problem(s); see run.md",
+            review.errors.len()
+        ));
+        append_run_note(
+            session_path,
+            "Review format problems",
+            &review
+                .errors
+                .iter()
+                .map(|e| format!("- {}\n", e))
+                .collect::<String>(),
+        )?;
+    }
+
+    if round > 0 {
+        review.resolved = resolved_ids(previous, review);
+        let open = previous.len() - review.resolved.len();
+        if !previous.is_empty() {
+            ui.step_info(&format!(
+                "Resolved {}/{} previous findings{}",
+                review.resolved.len(),
+                previous.len(),
+                if open > 0 {
+                    format!(" ({} still open)", open)
+                } else {
+                    String::new()
+                }
+            ));
+        }
+    }
+
+    record_review(
+        session_path,
+        SessionReview {
+            iteration,
+            task: task_name.to_string(),
+            round,
+            status: review.status.clone(),
+            must_fix_count: review.must_fix_count,
+            should_fix_count: review.should_fix_count,
+            findings: review.findings.clone(),
+            resolved: review.resolved.clone(),
+            format_errors: review.errors.clone(),
+            timestamp: now_iso(),
+        },
+    )
+}
+
+/// Detect a build that worked on another task than `task_file`: it changed
+/// other tasks' statuses, or only files other open tasks declare. Its work
+/// is stashed and its commits are reset to `before_sha` before anything is
+/// committed; spec edits and the other tasks' statuses are reverted.
+fn reject_wrong_task(
+    ui: &Ui,
+    cfg: &Config,
+    (spec_dir, session_path): (&Path, &Path),
+    iteration: u32,
+    (task_file, statuses_before, spec_before): (&Path, &[(PathBuf, TaskStatus)], &SpecSnapshot),
+    before_sha: &str,
+) -> Result<bool> {
+    let foreign = foreign_status_changes(statuses_before, task_file);
+    let dirty = unrelated_changes(spec_dir, Path::new(&cfg.session_dir));
+    let mut changed = if before_sha.is_empty() {
+        vec![]
+    } else {
+        changed_files_since(before_sha)
+    };
+    changed.extend(dirty.iter().cloned());
+    let open: Vec<PathBuf> = statuses_before
+        .iter()
+        .filter(|(_, status)| *status != TaskStatus::Done)
+        .map(|(path, _)| path.clone())
+        .collect();
+    let claimed = claimed_by_other_tasks(task_file, &open, spec_dir, &changed);
+    if foreign.is_empty() && claimed.is_empty() {
+        return Ok(false);
+    }
+
+    let mut names: Vec<String> = foreign
+        .iter()
+        .map(|(path, _)| path.clone())
+        .chain(claimed)
+        .map(|path| get_task_name(&path))
+        .collect();
+    names.sort();
+    names.dedup();
+    ui.step_error(&format!(
+        "Build worked on other tasks ({}) instead of {}; rolling it back",
+        names.join(", "),
+        get_task_name(task_file)
+    ));
+
+    if !dirty.is_empty() {
+        let message = format!("spec-loop: wrong task in iteration {}", iteration);
+        stash_paths(&dirty, &message)?;
+        ui.step_info(&format!(
+            "Uncommitted build changes stashed as \"{}\"",
+            message
+        ));
+    }
+    if !before_sha.is_empty() {
+        let head = head_sha();
+        if let Err(e) = rollback_iteration(
+            ui,
+            cfg,
+            spec_dir,
+            session_path,
+            iteration,
+            (before_sha, &head),
+            "reset",
+        ) {
+            ui.step_warn(&format!("Could not roll back the build's commits: {}", e));
+        }
+    }
+    enforce_spec_snapshot(ui, session_path, "build", spec_before, task_file)?;
+
+    for (path, status) in &foreign {
+        let now = get_task_status(path);
+        if now == *status {
+            continue;
+        }
+        log_status_change(
+            cfg,
+            Some(session_path),
+            path,
+            (*status, now),
+            "agent",
+            Some(iteration),
+        )?;
+        write_task_status(path, *status)?;
+        log_status_change(
+            cfg,
+            Some(session_path),
+            path,
+            (now, *status),
+            "harness",
+            Some(iteration),
+        )?;
+    }
+    Ok(true)
+}
+
+/// Tasks other than `selected` whose status differs from the snapshot, paired
+/// with the status they had before.
+fn foreign_status_changes(
+    before: &[(PathBuf, TaskStatus)],
+    selected: &Path,
+) -> Vec<(PathBuf, TaskStatus)> {
+    before
+        .iter()
+        .filter(|(path, status)| path != selected && get_task_status(path) != *status)
+        .cloned()
+        .collect()
+}
+
+/// Record a status change in the session history and, with `TASK_HISTORY`,
+/// in the task file.
+fn log_status_change(
+    cfg: &Config,
+    session_path: Option<&Path>,
+    task_file: &Path,
+    (from, to): (TaskStatus, TaskStatus),
+    actor: &str,
+    iteration: Option<u32>,
+) -> Result<()> {
+    if let Some(session_path) = session_path {
+        record_status_change(
+            session_path,
+            SessionStatusChange {
+                task: get_task_name(task_file),
+                from: from.as_str().to_string(),
+                to: to.as_str().to_string(),
+                actor: actor.to_string(),
+                iteration,
+                timestamp: now_iso(),
+            },
+        )?;
+    }
+    if cfg.task_history {
+        let mut entry = format!(
+            "{} {} → {} ({}",
+            now_human(),
+            from.as_str(),
+            to.as_str(),
+            actor
+        );
+        if let Some(i) = iteration {
+            entry.push_str(&format!(", iteration {}", i));
+        }
+        entry.push(')');
+        append_task_history(task_file, &entry)?;
+    }
+    Ok(())
+}
+
+/// Harness status change for the current task, checked and logged.
+fn move_task(
+    cfg: &Config,
+    session_path: &Path,
+    iteration: u32,
+    task_file: &Path,
+    to: TaskStatus,
+) -> Result<()> {
+    let from = get_task_status(task_file);
+    if from == to {
+        return Ok(());
+    }
+    set_task_status(task_file, to)?;
+    log_status_change(
+        cfg,
+        Some(session_path),
+        task_file,
+        (from, to),
+        "harness",
+        Some(iteration),
+    )
+}
+
+/// Restore task statuses the agent changed during `phase` against the
+/// lifecycle. Only the current task may change, to one of `allowed`, and
+/// only along a legal transition.
+#[allow(clippy::too_many_arguments)]
+fn revert_illegal_status_edits(
+    ui: &Ui,
+    cfg: &Config,
+    session_path: &Path,
+    iteration: u32,
+    phase: &str,
+    before: &[(PathBuf, TaskStatus)],
+    task_file: &Path,
+    allowed: &[TaskStatus],
+) -> Result<()> {
+    for (path, old) in before {
+        let now = get_task_status(path);
+        if now == *old {
+            continue;
+        }
+        log_status_change(
+            cfg,
+            Some(session_path),
+            path,
+            (*old, now),
+            "agent",
+            Some(iteration),
+        )?;
+        if path == task_file && allowed.contains(&now) && old.can_transition(now) {
+            continue;
+        }
+        let name = get_task_name(path);
+        ui.step_warn(&format!(
+            "{} set {} to {} (from {}); reverting",
+            phase,
+            name,
+            now.as_str(),
+            old.as_str()
+        ));
+        write_task_status(path, *old)?;
+        log_status_change(
+            cfg,
+            Some(session_path),
+            path,
+            (now, *old),
+            "harness",
+            Some(iteration),
+        )?;
+        append_run_note(
+            session_path,
+            "Illegal status edit",
+            &format!(
+                "- Phase: {}\n- Task: {}\n- Change: {} → {} (reverted)\n",
+                phase,
+                name,
+                old.as_str(),
+                now.as_str()
+            ),
+        )?;
+        ui.emit(
+            "status_reverted",
+            json!({
+                "phase": phase,
+                "task": name,
+                "from": old.as_str(),
+                "to": now.as_str(),
+            }),
+        );
+    }
+    Ok(())
+}
+
 fn has_spec_progress(spec_dir: &Path, remaining_before: usize, signature_before: &str) -> bool {
     let remaining_after = count_remaining(spec_dir);
     if remaining_after < remaining_before {
@@ -1215,3 +3837,82 @@ fn has_spec_progress(spec_dir: &Path, remaining_before: usize, signature_before:
     }
     status_signature(spec_dir) != signature_before
 }
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::cli::OutputMode;
+
+    const BUILD_ALLOWED: [TaskStatus; 3] = [
+        TaskStatus::InProgress,
+        TaskStatus::InReview,
+        TaskStatus::Blocked,
+    ];
+
+    /// A spec directory with the selected task and another one, a session
+    /// directory, and both tasks' paths.
+    fn spec(name: &str, own: TaskStatus, other: TaskStatus) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("spec-loop-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tasks")).unwrap();
+        fs::create_dir_all(dir.join("session")).unwrap();
+        let task = |n: u32, status: TaskStatus| {
+            let path = dir.join(format!("tasks/0{}-task.md", n));
+            let text = format!("# Task {}: T\n\n> Status: {}\n", n, status.as_str());
+            fs::write(&path, text).unwrap();
+            path
+        };
+        let own = task(1, own);
+        let other = task(2, other);
+        (dir, own, other)
+    }
+
+    #[test]
+    fn illegal_status_edits_are_reverted() {
+        use TaskStatus::*;
+        // (before, after, kept) for the selected task during a build.
+        let cases = [
+            (InProgress, InReview, true),
+            (InProgress, Blocked, true),
+            (InProgress, Done, false),
+            (InProgress, Pending, false),
+            (Pending, InReview, false),
+            (Unknown, InReview, true),
+            (Unknown, Done, false),
+        ];
+        let ui = Ui::new(OutputMode::Plain);
+        let cfg = Config::default();
+        for (i, (before, after, kept)) in cases.into_iter().enumerate() {
+            let (dir, own, other) = spec(&format!("lifecycle-{}", i), before, Pending);
+            let statuses = vec![(own.clone(), before), (other.clone(), Pending)];
+            write_task_status(&own, after).unwrap();
+            write_task_status(&other, InProgress).unwrap();
+
+            revert_illegal_status_edits(
+                &ui,
+                &cfg,
+                &dir.join("session"),
+                1,
+                "build",
+                &statuses,
+                &own,
+                &BUILD_ALLOWED,
+            )
+            .unwrap();
+
+            let expected = if kept { after } else { before };
+            assert_eq!(
+                get_task_status(&own),
+                expected,
+                "{:?} -> {:?}",
+                before,
+                after
+            );
+            // Another task's status is never the build's to change.
+            assert_eq!(get_task_status(&other), Pending);
+            fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+}
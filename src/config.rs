@@ -1,11 +1,21 @@
 use std::collections::HashMap;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Deserialize;
+use walkdir::WalkDir;
 
+use crate::claude::PERMISSION_MODES;
 use crate::cli::RunArgs;
+use crate::review::REVIEW_STRATEGIES;
+use crate::rollback::ROLLBACK_POLICIES;
+use crate::scope::SCOPE_POLICIES;
+use crate::util::shell_quote;
+use crate::worktree::{
+    BRANCH_POLICIES, BRANCH_STRATEGIES, COMMIT_POLICIES, DIRTY_TREE_POLICIES, IN_PROGRESS_POLICIES,
+};
 
 pub const DEFAULT_MAX_LOOPS: u32 = 25;
 pub const DEFAULT_MAX_REVIEW_FIX_LOOPS: u32 = 3;
@@ -15,10 +25,32 @@ pub const DEFAULT_SPECS_DIR: &str = ".agents/specs";
 pub const DEFAULT_SESSION_DIR: &str = ".spec-loop/sessions";
 pub const DEFAULT_CB_NO_PROGRESS_THRESHOLD: u32 = 3;
 pub const DEFAULT_CB_COOLDOWN_MINUTES: u32 = 30;
+pub const DEFAULT_FILES_SCOPE_POLICY: &str = "warn";
+pub const DEFAULT_CONTRACT_RETRIES: u32 = 2;
+pub const DEFAULT_REVIEW_STRATEGY: &str = "single";
+pub const DEFAULT_REVIEW_COUNT: u32 = 3;
+pub const DEFAULT_ROLLBACK_ON_FAIL: &str = "off";
+pub const DEFAULT_ROLLBACK_TASK_STATUS: &str = "pending";
+pub const DEFAULT_COMMIT_POLICY: &str = "commit";
+pub const DEFAULT_COMMIT_TEMPLATE: &str = "feat({{spec}}): task {{task_number}} {{task_title}}";
+pub const DEFAULT_BRANCH_STRATEGY: &str = "current";
+pub const DEFAULT_GIT_DIRTY_POLICY: &str = "warn";
+pub const DEFAULT_GIT_DETACHED_POLICY: &str = "warn";
+pub const DEFAULT_GIT_PROTECTED_POLICY: &str = "warn";
+pub const DEFAULT_GIT_PROTECTED_BRANCHES: &str = "main,master";
+pub const DEFAULT_POLICY_FILE: &str = ".agents/policy.toml";
+pub const DEFAULT_PERMISSION_MODE: &str = "bypass";
+pub const DEFAULT_GIT_IN_PROGRESS_POLICY: &str = "refuse";
 
 #[derive(Debug, Clone)]
 pub struct Config {
-    pub speclooprc: String,
+    /// Project config file that was loaded, if any.
+    pub config_file: String,
+    /// Unknown names in the legacy `.speclooprc` that was loaded; they are
+    /// ignored.
+    pub ignored_keys: Vec<String>,
+    /// Where each setting that is not a default came from, by legacy name.
+    pub origins: HashMap<&'static str, String>,
     pub project_type: String,
     pub verify_command: String,
     pub test_command: String,
@@ -32,12 +64,160 @@ pub struct Config {
     pub max_tasks_per_run: u32,
     pub cb_no_progress_threshold: u32,
     pub cb_cooldown_minutes: u32,
+    pub files_scope_policy: String,
+    pub contract_retries: u32,
+    /// Kill an agent phase that runs longer than this; 0 means no limit.
+    pub phase_timeout_minutes: u32,
+    pub review_strategy: String,
+    pub review_count: u32,
+    /// Passing reviews needed under consensus; 0 means a simple majority.
+    pub review_quorum: u32,
+    pub review_models: String,
+    /// Also append each status change to a `> History:` block in the task file.
+    pub task_history: bool,
+    /// What to do with a task's commits when review never passes.
+    pub rollback_on_fail: String,
+    pub rollback_task_status: String,
+    /// What to do with changes a build or fix phase left uncommitted.
+    pub commit_policy: String,
+    pub commit_template: String,
+    /// `per-spec` keeps each spec's work on its own `spec/<slug>` branch.
+    pub branch_strategy: String,
+    pub git_dirty_policy: String,
+    pub git_detached_policy: String,
+    pub git_protected_policy: String,
+    pub git_protected_branches: String,
+    pub git_in_progress_policy: String,
+    /// Protected paths and forbidden commands checked after each phase.
+    pub policy_file: String,
+    /// `bypass` or `allowlist`; see `claude::allowed_tools`.
+    pub permission_mode: String,
+    /// Extra tools for `allowlist` mode, comma-separated.
+    pub allowed_tools: String,
+    /// Stop the run once it has cost this many USD; 0 means no limit.
+    pub budget_usd: f64,
+    /// Profile selected with `run --profile`, if any.
+    pub profile: String,
+    /// `run` flags the selected profile turns on.
+    pub profile_flags: RunFlags,
+    /// Subprojects with their own verify and test commands.
+    pub targets: Vec<Target>,
+}
+
+/// A `[[targets]]` entry: a subproject whose verify and test commands apply
+/// to the files under `path`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    pub path: String,
+    #[serde(default, rename = "type")]
+    pub project_type: String,
+    #[serde(default)]
+    pub verify_command: String,
+    #[serde(default)]
+    pub test_command: String,
+}
+
+impl Target {
+    /// Whether `file` (relative to the project root) is under this target.
+    pub fn contains(&self, file: &str) -> bool {
+        let path = self.path.trim_end_matches('/');
+        file.trim_start_matches("./")
+            .strip_prefix(path)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// `command` run from the target's directory.
+    pub fn in_dir(&self, command: &str) -> String {
+        format!("(cd {} && {})", shell_quote(&self.path), command)
+    }
+}
+
+impl Config {
+    /// Passing reviews required for a consensus review to pass.
+    pub fn effective_review_quorum(&self) -> u32 {
+        if self.review_quorum == 0 {
+            self.review_count / 2 + 1
+        } else {
+            self.review_quorum
+        }
+    }
+
+    pub fn origin(&self, key: &Key) -> &str {
+        self.origins
+            .get(key.legacy)
+            .map(String::as_str)
+            .unwrap_or("default")
+    }
+
+    /// Verify and test commands for a change to `files`: those of each
+    /// file's closest target, and the project's own for files outside every
+    /// target. With no files, every target's commands are included.
+    pub fn commands_for(&self, files: &[String]) -> (String, String) {
+        let mut root = files.is_empty();
+        let mut selected = vec![files.is_empty(); self.targets.len()];
+        for file in files {
+            let closest = self
+                .targets
+                .iter()
+                .enumerate()
+                .filter(|(_, t)| t.contains(file))
+                .max_by_key(|(_, t)| t.path.len());
+            match closest {
+                Some((i, _)) => selected[i] = true,
+                None => root = true,
+            }
+        }
+
+        let join = |root_cmd: &str, target_cmd: fn(&Target) -> &str| {
+            let mut parts = vec![];
+            if root && !root_cmd.is_empty() {
+                parts.push(root_cmd.to_string());
+            }
+            for (target, _) in self.targets.iter().zip(&selected).filter(|(_, s)| **s) {
+                let cmd = target_cmd(target);
+                if !cmd.is_empty() {
+                    parts.push(target.in_dir(cmd));
+                }
+            }
+            parts.join(" && ")
+        };
+        (
+            join(&self.verify_command, |t| &t.verify_command),
+            join(&self.test_command, |t| &t.test_command),
+        )
+    }
+
+    pub fn is_protected_branch(&self, branch: &str) -> bool {
+        self.git_protected_branches
+            .split(',')
+            .map(str::trim)
+            .any(|b| !b.is_empty() && b == branch)
+    }
+
+    /// Model for the `index`th consensus reviewer, cycling through
+    /// `REVIEW_MODELS` and falling back to `CLAUDE_MODEL`.
+    pub fn reviewer_model(&self, index: usize) -> String {
+        let models: Vec<&str> = self
+            .review_models
+            .split(',')
+            .map(str::trim)
+            .filter(|m| !m.is_empty())
+            .collect();
+        if models.is_empty() {
+            self.claude_model.clone()
+        } else {
+            models[index % models.len()].to_string()
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
-            speclooprc: ".speclooprc".to_string(),
+            config_file: String::new(),
+            ignored_keys: vec![],
+            origins: HashMap::new(),
             project_type: String::new(),
             verify_command: String::new(),
             test_command: String::new(),
@@ -51,10 +231,425 @@ impl Default for Config {
             max_tasks_per_run: DEFAULT_MAX_TASKS_PER_RUN,
             cb_no_progress_threshold: DEFAULT_CB_NO_PROGRESS_THRESHOLD,
             cb_cooldown_minutes: DEFAULT_CB_COOLDOWN_MINUTES,
+            files_scope_policy: DEFAULT_FILES_SCOPE_POLICY.to_string(),
+            contract_retries: DEFAULT_CONTRACT_RETRIES,
+            phase_timeout_minutes: 0,
+            review_strategy: DEFAULT_REVIEW_STRATEGY.to_string(),
+            review_count: DEFAULT_REVIEW_COUNT,
+            review_quorum: 0,
+            review_models: String::new(),
+            task_history: false,
+            rollback_on_fail: DEFAULT_ROLLBACK_ON_FAIL.to_string(),
+            rollback_task_status: DEFAULT_ROLLBACK_TASK_STATUS.to_string(),
+            commit_policy: DEFAULT_COMMIT_POLICY.to_string(),
+            commit_template: DEFAULT_COMMIT_TEMPLATE.to_string(),
+            branch_strategy: DEFAULT_BRANCH_STRATEGY.to_string(),
+            git_dirty_policy: DEFAULT_GIT_DIRTY_POLICY.to_string(),
+            git_detached_policy: DEFAULT_GIT_DETACHED_POLICY.to_string(),
+            git_protected_policy: DEFAULT_GIT_PROTECTED_POLICY.to_string(),
+            git_protected_branches: DEFAULT_GIT_PROTECTED_BRANCHES.to_string(),
+            git_in_progress_policy: DEFAULT_GIT_IN_PROGRESS_POLICY.to_string(),
+            policy_file: DEFAULT_POLICY_FILE.to_string(),
+            permission_mode: DEFAULT_PERMISSION_MODE.to_string(),
+            allowed_tools: String::new(),
+            budget_usd: 0.0,
+            profile: String::new(),
+            profile_flags: RunFlags::default(),
+            targets: vec![],
         }
     }
 }
 
+/// Value type of a setting, checked when a layer is applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Str,
+    U32,
+    Bool,
+    /// Non-negative number; an integer or float in TOML.
+    F64,
+    /// Comma-separated string; an array of strings in TOML.
+    List,
+    /// One of a fixed set of strings.
+    Choice(&'static [&'static str]),
+}
+
+/// One setting: its legacy `.speclooprc` name (also the `SPECLOOP_` env
+/// suffix), its place in `spec-loop.toml`, and how it is applied.
+pub struct Key {
+    pub legacy: &'static str,
+    pub section: &'static str,
+    pub name: &'static str,
+    pub kind: Kind,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &str) -> Result<()>,
+}
+
+impl Key {
+    pub fn path(&self) -> String {
+        format!("{}.{}", self.section, self.name)
+    }
+
+    pub fn get(&self, cfg: &Config) -> String {
+        (self.get)(cfg)
+    }
+
+    pub fn apply(&self, cfg: &mut Config, value: &str) -> Result<()> {
+        (self.set)(cfg, value)
+    }
+
+    /// Check `value` the way loading would, without applying it.
+    pub fn validate(&self, value: &str) -> Result<()> {
+        self.apply(&mut Config::default(), value)
+    }
+}
+
+pub const CONFIG_FILE: &str = "spec-loop.toml";
+pub const LEGACY_CONFIG_FILE: &str = ".speclooprc";
+
+/// Statuses `ROLLBACK_TASK_STATUS` may name.
+const ROLLBACK_TASK_STATUSES: [&str; 2] = ["pending", "blocked"];
+
+/// `spec-loop.toml` sections in file order. `notifications` is reserved.
+pub const SECTIONS: [&str; 7] = [
+    "project",
+    "agent",
+    "phases",
+    "limits",
+    "safety",
+    "paths",
+    "notifications",
+];
+
+macro_rules! key {
+    ($legacy:literal, $section:literal, $name:literal, Str, $field:ident) => {
+        Key {
+            legacy: $legacy,
+            section: $section,
+            name: $name,
+            kind: Kind::Str,
+            get: |c| c.$field.clone(),
+            set: |c, v| {
+                c.$field = v.to_string();
+                Ok(())
+            },
+        }
+    };
+    ($legacy:literal, $section:literal, $name:literal, List, $field:ident) => {
+        Key {
+            legacy: $legacy,
+            section: $section,
+            name: $name,
+            kind: Kind::List,
+            get: |c| c.$field.clone(),
+            set: |c, v| {
+                c.$field = v.to_string();
+                Ok(())
+            },
+        }
+    };
+    ($legacy:literal, $section:literal, $name:literal, Choice($choices:expr), $field:ident) => {
+        Key {
+            legacy: $legacy,
+            section: $section,
+            name: $name,
+            kind: Kind::Choice(&$choices),
+            get: |c| c.$field.clone(),
+            set: |c, v| {
+                c.$field = parse_choice(v, &$choices)?;
+                Ok(())
+            },
+        }
+    };
+    ($legacy:literal, $section:literal, $name:literal, U32, $field:ident) => {
+        Key {
+            legacy: $legacy,
+            section: $section,
+            name: $name,
+            kind: Kind::U32,
+            get: |c| c.$field.to_string(),
+            set: |c, v| {
+                c.$field = parse_u32(v)?;
+                Ok(())
+            },
+        }
+    };
+    ($legacy:literal, $section:literal, $name:literal, F64, $field:ident) => {
+        Key {
+            legacy: $legacy,
+            section: $section,
+            name: $name,
+            kind: Kind::F64,
+            get: |c| c.$field.to_string(),
+            set: |c, v| {
+                c.$field = parse_f64(v)?;
+                Ok(())
+            },
+        }
+    };
+    ($legacy:literal, $section:literal, $name:literal, Bool, $field:ident) => {
+        Key {
+            legacy: $legacy,
+            section: $section,
+            name: $name,
+            kind: Kind::Bool,
+            get: |c| c.$field.to_string(),
+            set: |c, v| {
+                c.$field = parse_bool(v)?;
+                Ok(())
+            },
+        }
+    };
+}
+
+pub const KEYS: &[Key] = &[
+    key!("PROJECT_TYPE", "project", "type", Str, project_type),
+    key!(
+        "VERIFY_COMMAND",
+        "project",
+        "verify_command",
+        Str,
+        verify_command
+    ),
+    key!("TEST_COMMAND", "project", "test_command", Str, test_command),
+    key!("CLAUDE_BIN", "agent", "bin", Str, claude_bin),
+    key!("CLAUDE_MODEL", "agent", "model", Str, claude_model),
+    key!(
+        "CLAUDE_EXTRA_ARGS",
+        "agent",
+        "extra_args",
+        Str,
+        claude_extra_args
+    ),
+    key!(
+        "PERMISSION_MODE",
+        "agent",
+        "permission_mode",
+        Choice(PERMISSION_MODES),
+        permission_mode
+    ),
+    key!(
+        "ALLOWED_TOOLS",
+        "agent",
+        "allowed_tools",
+        List,
+        allowed_tools
+    ),
+    key!(
+        "FILES_SCOPE_POLICY",
+        "phases",
+        "files_scope_policy",
+        Choice(SCOPE_POLICIES),
+        files_scope_policy
+    ),
+    key!(
+        "CONTRACT_RETRIES",
+        "phases",
+        "contract_retries",
+        U32,
+        contract_retries
+    ),
+    key!(
+        "PHASE_TIMEOUT_MINUTES",
+        "phases",
+        "phase_timeout_minutes",
+        U32,
+        phase_timeout_minutes
+    ),
+    key!(
+        "REVIEW_STRATEGY",
+        "phases",
+        "review_strategy",
+        Choice(REVIEW_STRATEGIES),
+        review_strategy
+    ),
+    key!("REVIEW_COUNT", "phases", "review_count", U32, review_count),
+    key!(
+        "REVIEW_QUORUM",
+        "phases",
+        "review_quorum",
+        U32,
+        review_quorum
+    ),
+    key!(
+        "REVIEW_MODELS",
+        "phases",
+        "review_models",
+        List,
+        review_models
+    ),
+    key!(
+        "COMMIT_POLICY",
+        "phases",
+        "commit_policy",
+        Choice(COMMIT_POLICIES),
+        commit_policy
+    ),
+    key!(
+        "COMMIT_TEMPLATE",
+        "phases",
+        "commit_template",
+        Str,
+        commit_template
+    ),
+    key!(
+        "ROLLBACK_ON_FAIL",
+        "phases",
+        "rollback_on_fail",
+        Choice(ROLLBACK_POLICIES),
+        rollback_on_fail
+    ),
+    key!(
+        "ROLLBACK_TASK_STATUS",
+        "phases",
+        "rollback_task_status",
+        Choice(ROLLBACK_TASK_STATUSES),
+        rollback_task_status
+    ),
+    key!("TASK_HISTORY", "phases", "task_history", Bool, task_history),
+    key!("MAX_LOOPS", "limits", "max_loops", U32, max_loops),
+    key!(
+        "MAX_REVIEW_FIX_LOOPS",
+        "limits",
+        "max_review_fix_loops",
+        U32,
+        max_review_fix_loops
+    ),
+    key!(
+        "MAX_TASKS_PER_RUN",
+        "limits",
+        "max_tasks_per_run",
+        U32,
+        max_tasks_per_run
+    ),
+    key!("BUDGET_USD", "limits", "budget_usd", F64, budget_usd),
+    key!(
+        "CB_NO_PROGRESS_THRESHOLD",
+        "safety",
+        "cb_no_progress_threshold",
+        U32,
+        cb_no_progress_threshold
+    ),
+    key!(
+        "CB_COOLDOWN_MINUTES",
+        "safety",
+        "cb_cooldown_minutes",
+        U32,
+        cb_cooldown_minutes
+    ),
+    key!("POLICY_FILE", "safety", "policy_file", Str, policy_file),
+    key!(
+        "BRANCH_STRATEGY",
+        "safety",
+        "branch_strategy",
+        Choice(BRANCH_STRATEGIES),
+        branch_strategy
+    ),
+    key!(
+        "GIT_DIRTY_POLICY",
+        "safety",
+        "git_dirty_policy",
+        Choice(DIRTY_TREE_POLICIES),
+        git_dirty_policy
+    ),
+    key!(
+        "GIT_DETACHED_POLICY",
+        "safety",
+        "git_detached_policy",
+        Choice(BRANCH_POLICIES),
+        git_detached_policy
+    ),
+    key!(
+        "GIT_PROTECTED_POLICY",
+        "safety",
+        "git_protected_policy",
+        Choice(BRANCH_POLICIES),
+        git_protected_policy
+    ),
+    key!(
+        "GIT_PROTECTED_BRANCHES",
+        "safety",
+        "git_protected_branches",
+        List,
+        git_protected_branches
+    ),
+    key!(
+        "GIT_IN_PROGRESS_POLICY",
+        "safety",
+        "git_in_progress_policy",
+        Choice(IN_PROGRESS_POLICIES),
+        git_in_progress_policy
+    ),
+    key!("SPECS_DIR", "paths", "specs_dir", Str, specs_dir),
+    key!("SESSION_DIR", "paths", "session_dir", Str, session_dir),
+];
+
+/// Settings a spec or task may override, by `section.name`.
+pub const SCOPED_KEYS: [&str; 7] = [
+    "project.verify_command",
+    "project.test_command",
+    "agent.model",
+    "phases.review_models",
+    "phases.contract_retries",
+    "phases.phase_timeout_minutes",
+    "limits.max_review_fix_loops",
+];
+
+pub fn find_key(section: &str, name: &str) -> Option<&'static Key> {
+    KEYS.iter().find(|k| k.section == section && k.name == name)
+}
+
+pub fn find_legacy_key(legacy: &str) -> Option<&'static Key> {
+    KEYS.iter().find(|k| k.legacy == legacy)
+}
+
+/// Look a key up by `section.name` or by its legacy name.
+pub fn lookup_key(name: &str) -> Result<&'static Key> {
+    name.split_once('.')
+        .and_then(|(section, key)| find_key(section, key))
+        .or_else(|| find_legacy_key(&name.to_uppercase()))
+        .ok_or_else(|| anyhow!("unknown key {} (see 'spec-loop config show')", name))
+}
+
+fn parse_u32(v: &str) -> Result<u32> {
+    v.trim()
+        .parse()
+        .map_err(|_| anyhow!("expected a non-negative integer, got \"{}\"", v))
+}
+
+fn parse_f64(v: &str) -> Result<f64> {
+    v.trim()
+        .parse()
+        .ok()
+        .filter(|n: &f64| n.is_finite() && *n >= 0.0)
+        .ok_or_else(|| anyhow!("expected a non-negative number, got \"{}\"", v))
+}
+
+fn parse_choice(v: &str, choices: &[&str]) -> Result<String> {
+    let v = v.trim();
+    if !choices.contains(&v) {
+        bail!("expected one of {}, got \"{}\"", choices.join(", "), v);
+    }
+    Ok(v.to_string())
+}
+
+fn parse_bool(v: &str) -> Result<bool> {
+    match v.trim().to_lowercase().as_str() {
+        "true" | "1" | "yes" => Ok(true),
+        "false" | "0" | "no" => Ok(false),
+        _ => bail!("expected true or false, got \"{}\"", v),
+    }
+}
+
+/// Global settings shared by every project.
+pub fn user_config_path() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(base.join("spec-loop").join("config.toml"))
+}
+
 pub fn parse_speclooprc(path: &Path) -> Result<HashMap<String, String>> {
     let mut out = HashMap::new();
     if !path.exists() {
@@ -63,129 +658,521 @@ pub fn parse_speclooprc(path: &Path) -> Result<HashMap<String, String>> {
 
     let content =
         fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
-    for raw_line in content.lines() {
+    for (number, raw_line) in content.lines().enumerate() {
         let line = raw_line.trim();
         if line.is_empty() || line.starts_with('#') {
             continue;
         }
-        if let Some((k, v)) = line.split_once('=') {
-            let key = k.trim().to_string();
-            let mut val = v.trim().to_string();
-            if (val.starts_with('"') && val.ends_with('"'))
-                || (val.starts_with('\'') && val.ends_with('\''))
-            {
-                val = val[1..val.len().saturating_sub(1)].to_string();
-            }
-            out.insert(key, val);
+        let Some((k, v)) = line.split_once('=').filter(|(k, _)| !k.trim().is_empty()) else {
+            bail!(
+                "{}:{}: expected KEY=value, got '{}'",
+                path.display(),
+                number + 1,
+                line
+            );
+        };
+        let key = k.trim().to_string();
+        let mut val = v.trim().to_string();
+        if (val.starts_with('"') && val.ends_with('"'))
+            || (val.starts_with('\'') && val.ends_with('\''))
+        {
+            val = val[1..val.len().saturating_sub(1)].to_string();
         }
+        out.insert(key, val);
     }
 
     Ok(out)
 }
 
-pub fn load_config(run_args: Option<&RunArgs>) -> Result<Config> {
-    let mut cfg = Config::default();
-    let rc = parse_speclooprc(Path::new(&cfg.speclooprc))?;
+/// Settings read from one config source.
+pub type Layer = Vec<(&'static Key, String)>;
+
+/// `run` flags a profile can turn on.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunFlags {
+    pub once: bool,
+    pub skip_review: bool,
+}
+
+/// A `[profile.<name>]` block: setting overrides plus `run` flags.
+#[derive(Default)]
+pub struct Profile {
+    pub settings: Vec<(&'static Key, String)>,
+    pub flags: RunFlags,
+}
 
-    apply_map(&mut cfg, &rc);
+/// A parsed `spec-loop.toml`-style file.
+#[derive(Default)]
+pub struct ConfigFile {
+    pub settings: Vec<(&'static Key, String)>,
+    pub profiles: Vec<(String, Profile)>,
+    pub targets: Vec<Target>,
+}
+
+/// Settings and profiles from a `spec-loop.toml`-style file, checked
+/// against [`KEYS`].
+pub fn parse_config_toml(path: &Path) -> Result<ConfigFile> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let table: toml::Table =
+        toml::from_str(&content).with_context(|| format!("failed to parse {}", path.display()))?;
 
-    // env overrides
-    if let Ok(v) = env::var("SPECLOOP_MAX_LOOPS") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.max_loops = n;
+    let mut out = ConfigFile::default();
+    for (section, body) in &table {
+        if section == "targets" {
+            out.targets = body
+                .clone()
+                .try_into()
+                .with_context(|| format!("{}: invalid [[targets]]", path.display()))?;
+            continue;
         }
-    }
-    if let Ok(v) = env::var("SPECLOOP_MAX_REVIEW_FIX_LOOPS") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.max_review_fix_loops = n;
+        if section != "profile" {
+            out.settings.extend(parse_section(path, "", section, body)?);
+            continue;
+        }
+        let Some(profiles) = body.as_table() else {
+            bail!("{}: profile must be a table", path.display());
+        };
+        for (name, body) in profiles {
+            let Some(body) = body.as_table() else {
+                bail!("{}: profile.{} must be a table", path.display(), name);
+            };
+            let prefix = format!("profile.{}.", name);
+            let mut profile = Profile::default();
+            for (entry, value) in body {
+                let flag = match entry.as_str() {
+                    "once" => &mut profile.flags.once,
+                    "skip_review" => &mut profile.flags.skip_review,
+                    _ => {
+                        profile
+                            .settings
+                            .extend(parse_section(path, &prefix, entry, value)?);
+                        continue;
+                    }
+                };
+                *flag = value.as_bool().ok_or_else(|| {
+                    anyhow!(
+                        "{}: invalid {}{}: expected a boolean, got {}",
+                        path.display(),
+                        prefix,
+                        entry,
+                        value.type_str()
+                    )
+                })?;
+            }
+            out.profiles.push((name.clone(), profile));
         }
     }
-    if let Ok(v) = env::var("SPECLOOP_MAX_TASKS_PER_RUN") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.max_tasks_per_run = n;
+    Ok(out)
+}
+
+fn parse_section(
+    path: &Path,
+    prefix: &str,
+    section: &str,
+    body: &toml::Value,
+) -> Result<Vec<(&'static Key, String)>> {
+    if !SECTIONS.contains(&section) {
+        bail!(
+            "{}: unknown section [{}{}] (expected one of {})",
+            path.display(),
+            prefix,
+            section,
+            SECTIONS.join(", ")
+        );
+    }
+    let Some(body) = body.as_table() else {
+        bail!("{}: {}{} must be a table", path.display(), prefix, section);
+    };
+    let mut out = vec![];
+    for (name, value) in body {
+        let key = find_key(section, name).ok_or_else(|| {
+            anyhow!(
+                "{}: unknown key {}{}.{}",
+                path.display(),
+                prefix,
+                section,
+                name
+            )
+        })?;
+        let text = toml_value_text(key, value)
+            .with_context(|| format!("{}: invalid {}{}", path.display(), prefix, key.path()))?;
+        out.push((key, text));
+    }
+    Ok(out)
+}
+
+/// Settings from the `> Config:` block of a spec or task file: the
+/// `> section.name = value` lines that follow it (or share its line).
+pub fn parse_config_block(path: &Path) -> Result<Vec<(&'static Key, String)>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let mut lines = content.lines();
+    let mut block = vec![];
+    while let Some(line) = lines.next() {
+        let Some(first) = line.trim().strip_prefix("> Config:") else {
+            continue;
+        };
+        block.push(first.trim());
+        for line in lines.by_ref() {
+            match line.trim().strip_prefix('>') {
+                Some(entry) if entry.contains('=') => block.push(entry.trim()),
+                _ => break,
+            }
         }
+        break;
+    }
+
+    let table: toml::Table = toml::from_str(&block.join("\n"))
+        .with_context(|| format!("{}: failed to parse the Config block", path.display()))?;
+    let mut out = vec![];
+    for (section, body) in &table {
+        out.extend(parse_section(path, "", section, body)?);
+    }
+    Ok(out)
+}
+
+fn toml_value_text(key: &Key, value: &toml::Value) -> Result<String> {
+    match (key.kind, value) {
+        (Kind::Str | Kind::List | Kind::Choice(_), toml::Value::String(s)) => Ok(s.clone()),
+        (Kind::U32, toml::Value::Integer(n)) => Ok(n.to_string()),
+        (Kind::Bool, toml::Value::Boolean(b)) => Ok(b.to_string()),
+        (Kind::F64, toml::Value::Float(n)) => Ok(n.to_string()),
+        (Kind::F64, toml::Value::Integer(n)) => Ok(n.to_string()),
+        (Kind::List, toml::Value::Array(items)) => items
+            .iter()
+            .map(|v| {
+                v.as_str()
+                    .map(str::to_string)
+                    .ok_or_else(|| anyhow!("expected an array of strings"))
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(|items| items.join(",")),
+        (kind, other) => bail!("expected {}, got {}", kind_name(kind), other.type_str()),
+    }
+}
+
+fn kind_name(kind: Kind) -> &'static str {
+    match kind {
+        Kind::Str => "a string",
+        Kind::U32 => "a non-negative integer",
+        Kind::Bool => "a boolean",
+        Kind::F64 => "a non-negative number",
+        Kind::List => "a string or an array of strings",
+        Kind::Choice(_) => "a string",
     }
-    if let Ok(v) = env::var("SPECLOOP_SPECS_DIR") {
-        cfg.specs_dir = v;
+}
+
+/// Settings from a legacy `.speclooprc`, checked against [`KEYS`], and the
+/// unknown names. Those are left over from older versions, so callers warn
+/// about them instead of failing.
+pub fn parse_legacy_config(path: &Path) -> Result<(Layer, Vec<String>)> {
+    let mut out = vec![];
+    let mut unknown = vec![];
+    let mut settings: Vec<_> = parse_speclooprc(path)?.into_iter().collect();
+    settings.sort();
+    for (name, value) in settings {
+        match find_legacy_key(&name) {
+            Some(key) => out.push((key, value)),
+            None => unknown.push(name),
+        }
     }
-    if let Ok(v) = env::var("SPECLOOP_SESSION_DIR") {
-        cfg.session_dir = v;
+    Ok((out, unknown))
+}
+
+fn apply_layer(cfg: &mut Config, layer: &[(&'static Key, String)], source: &str) -> Result<()> {
+    for (key, value) in layer {
+        (key.set)(cfg, value).with_context(|| format!("{}: invalid {}", source, key.path()))?;
+        cfg.origins.insert(key.legacy, source.to_string());
     }
+    Ok(())
+}
+
+/// Defaults, then the user-global file, then `spec-loop.toml` (or the
+/// legacy `.speclooprc`), then the `run --profile`, then `SPECLOOP_<KEY>`
+/// env, then CLI flags.
+pub fn load_config(run_args: Option<&RunArgs>) -> Result<Config> {
+    let profile = run_args.and_then(|a| a.profile.as_deref());
+    let mut cfg = load_layers(profile)?;
 
     // CLI overrides
     if let Some(args) = run_args {
         if let Some(v) = args.max_loops {
             cfg.max_loops = v;
+            cfg.origins.insert("MAX_LOOPS", "--max-loops".to_string());
         }
         if let Some(v) = args.max_review_fix_loops {
             cfg.max_review_fix_loops = v;
+            cfg.origins
+                .insert("MAX_REVIEW_FIX_LOOPS", "--max-review-fix-loops".to_string());
         }
         if let Some(v) = args.max_tasks {
             cfg.max_tasks_per_run = v;
+            cfg.origins
+                .insert("MAX_TASKS_PER_RUN", "--max-tasks".to_string());
+        }
+        if let Some(v) = args.budget {
+            cfg.budget_usd = parse_f64(&v.to_string()).context("invalid --budget")?;
+            cfg.origins.insert("BUDGET_USD", "--budget".to_string());
         }
-        if args.once {
+        let source = if args.once {
+            Some("--once".to_string())
+        } else if cfg.profile_flags.once {
+            Some(format!("profile {}", cfg.profile))
+        } else {
+            None
+        };
+        if let Some(source) = source {
             cfg.max_loops = 1;
             cfg.max_tasks_per_run = 1;
+            cfg.origins.insert("MAX_LOOPS", source.clone());
+            cfg.origins.insert("MAX_TASKS_PER_RUN", source);
         }
     }
 
     Ok(cfg)
 }
 
-fn apply_map(cfg: &mut Config, map: &HashMap<String, String>) {
-    if let Some(v) = map.get("PROJECT_TYPE") {
-        cfg.project_type = v.clone();
-    }
-    if let Some(v) = map.get("VERIFY_COMMAND") {
-        cfg.verify_command = v.clone();
+/// `cfg` with the overrides for `spec_dir` and, if given, `task_file`: the
+/// spec's own `spec-loop.toml`, then the `> Config:` block of `spec.md`,
+/// then the task's. Settings that came from env or flags are kept.
+pub fn scoped_config(cfg: &Config, spec_dir: &Path, task_file: Option<&Path>) -> Result<Config> {
+    let mut layers = vec![];
+    let spec_file = spec_dir.join(CONFIG_FILE);
+    if spec_file.is_file() {
+        let file = parse_config_toml(&spec_file)?;
+        if !file.profiles.is_empty() || !file.targets.is_empty() {
+            bail!(
+                "{}: profiles and targets belong in the project's {}",
+                spec_file.display(),
+                CONFIG_FILE
+            );
+        }
+        layers.push((spec_file, file.settings));
     }
-    if let Some(v) = map.get("TEST_COMMAND") {
-        cfg.test_command = v.clone();
+    for path in [
+        Some(spec_dir.join("spec.md")),
+        task_file.map(Path::to_path_buf),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|p| p.is_file())
+    {
+        let layer = parse_config_block(&path)?;
+        layers.push((path, layer));
     }
-    if let Some(v) = map.get("CLAUDE_BIN") {
-        cfg.claude_bin = v.clone();
+
+    let mut out = cfg.clone();
+    for (path, layer) in layers {
+        if let Some((key, _)) = layer
+            .iter()
+            .find(|(k, _)| !SCOPED_KEYS.contains(&k.path().as_str()))
+        {
+            bail!(
+                "{}: {} cannot be set per spec or task (allowed: {})",
+                path.display(),
+                key.path(),
+                SCOPED_KEYS.join(", ")
+            );
+        }
+        let layer: Vec<_> = layer
+            .into_iter()
+            .filter(|(k, _)| {
+                let origin = cfg.origin(k);
+                !origin.starts_with("env ") && !origin.starts_with("--")
+            })
+            .collect();
+        apply_layer(&mut out, &layer, &path.display().to_string())?;
     }
-    if let Some(v) = map.get("CLAUDE_MODEL") {
-        cfg.claude_model = v.clone();
+    Ok(out)
+}
+
+/// Every layer except CLI flags, with `profile` applied above the files.
+pub fn load_layers(profile: Option<&str>) -> Result<Config> {
+    let mut cfg = Config::default();
+    for var in ["CLAUDE_BIN", "CLAUDE_EXTRA_ARGS"] {
+        if env::var_os(var).is_some() {
+            cfg.origins.insert(var, format!("env {}", var));
+        }
     }
-    if let Some(v) = map.get("CLAUDE_EXTRA_ARGS") {
-        cfg.claude_extra_args = v.clone();
+
+    let mut profiles: Vec<(String, Profile)> = vec![];
+    if let Some(path) = user_config_path().filter(|p| p.is_file()) {
+        let file = parse_config_toml(&path)?;
+        if !file.targets.is_empty() {
+            bail!(
+                "{}: targets belong in the project's {}",
+                path.display(),
+                CONFIG_FILE
+            );
+        }
+        apply_layer(&mut cfg, &file.settings, &path.display().to_string())?;
+        profiles.extend(file.profiles);
     }
-    if let Some(v) = map.get("SPECS_DIR") {
-        cfg.specs_dir = v.clone();
+
+    if Path::new(CONFIG_FILE).is_file() {
+        let file = parse_config_toml(Path::new(CONFIG_FILE))?;
+        apply_layer(&mut cfg, &file.settings, CONFIG_FILE)?;
+        profiles.extend(file.profiles);
+        cfg.targets = file.targets;
+        cfg.config_file = CONFIG_FILE.to_string();
+    } else if Path::new(LEGACY_CONFIG_FILE).is_file() {
+        let (layer, unknown) = parse_legacy_config(Path::new(LEGACY_CONFIG_FILE))?;
+        apply_layer(&mut cfg, &layer, LEGACY_CONFIG_FILE)?;
+        cfg.config_file = LEGACY_CONFIG_FILE.to_string();
+        cfg.ignored_keys = unknown;
     }
-    if let Some(v) = map.get("SESSION_DIR") {
-        cfg.session_dir = v.clone();
+
+    if let Some(name) = profile {
+        // The project file is read last, so its profile wins a name clash.
+        let Some((_, selected)) = profiles.iter().rev().find(|(n, _)| n == name) else {
+            let mut names: Vec<&str> = profiles.iter().map(|(n, _)| n.as_str()).collect();
+            names.sort_unstable();
+            names.dedup();
+            if names.is_empty() {
+                bail!(
+                    "unknown profile {} (no [profile.<name>] blocks are defined)",
+                    name
+                );
+            }
+            bail!("unknown profile {} (defined: {})", name, names.join(", "));
+        };
+        apply_layer(&mut cfg, &selected.settings, &format!("profile {}", name))?;
+        cfg.profile = name.to_string();
+        cfg.profile_flags = selected.flags;
     }
-    if let Some(v) = map.get("MAX_LOOPS") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.max_loops = n;
+
+    for key in KEYS {
+        let var = format!("SPECLOOP_{}", key.legacy);
+        if let Ok(v) = env::var(&var) {
+            (key.set)(&mut cfg, &v).with_context(|| format!("invalid {}", var))?;
+            cfg.origins.insert(key.legacy, format!("env {}", var));
         }
     }
-    if let Some(v) = map.get("MAX_REVIEW_FIX_LOOPS") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.max_review_fix_loops = n;
+
+    Ok(cfg)
+}
+
+/// `spec-loop.toml` text for the settings of a legacy `.speclooprc`, and the
+/// unknown names left out of it.
+pub fn migrate_legacy_config(path: &Path) -> Result<(String, Vec<String>)> {
+    let (layer, unknown) = parse_legacy_config(path)?;
+    let mut out = format!(
+        "# spec-loop configuration\n# Migrated from {}\n",
+        path.display()
+    );
+    for section in SECTIONS {
+        let entries: Vec<String> = KEYS
+            .iter()
+            .filter(|k| k.section == section)
+            .filter_map(|k| {
+                layer
+                    .iter()
+                    .find(|(l, _)| l.legacy == k.legacy)
+                    .map(|(_, v)| (k, v))
+            })
+            .map(|(k, v)| -> Result<String> {
+                let mut probe = Config::default();
+                (k.set)(&mut probe, v)
+                    .with_context(|| format!("{}: invalid {}", path.display(), k.legacy))?;
+                Ok(format!("{} = {}", k.name, toml_value(k, v)))
+            })
+            .collect::<Result<_>>()?;
+        if !entries.is_empty() {
+            out.push_str(&format!("\n[{}]\n{}\n", section, entries.join("\n")));
         }
     }
-    if let Some(v) = map.get("MAX_TASKS_PER_RUN") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.max_tasks_per_run = n;
+    Ok((out, unknown))
+}
+
+/// Set `key` in a `spec-loop.toml`-style file, creating the file or section
+/// if needed. Comments and layout elsewhere in the file are kept.
+pub fn set_in_file(path: &Path, key: &Key, value: &str) -> Result<()> {
+    key.validate(value)
+        .with_context(|| format!("invalid {}", key.path()))?;
+    let content = if path.exists() {
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?
+    } else {
+        String::new()
+    };
+    let mut doc: toml_edit::DocumentMut = content
+        .parse()
+        .with_context(|| format!("failed to parse {}", path.display()))?;
+    let section = doc
+        .entry(key.section)
+        .or_insert_with(toml_edit::table)
+        .as_table_mut()
+        .ok_or_else(|| anyhow!("{}: {} must be a table", path.display(), key.section))?;
+    let literal = toml_value(key, value).to_string();
+    let parsed: toml_edit::Value = literal
+        .parse()
+        .with_context(|| format!("failed to encode {}", key.path()))?;
+    match section
+        .get_mut(key.name)
+        .and_then(toml_edit::Item::as_value_mut)
+    {
+        Some(existing) => {
+            let decor = existing.decor().clone();
+            *existing = parsed;
+            *existing.decor_mut() = decor;
         }
-    }
-    if let Some(v) = map.get("CB_NO_PROGRESS_THRESHOLD") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.cb_no_progress_threshold = n;
+        None => {
+            section.insert(key.name, toml_edit::value(parsed));
         }
     }
-    if let Some(v) = map.get("CB_COOLDOWN_MINUTES") {
-        if let Ok(n) = v.parse::<u32>() {
-            cfg.cb_cooldown_minutes = n;
-        }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::write(path, doc.to_string()).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// A validated setting as a TOML literal.
+pub fn toml_value(key: &Key, value: &str) -> toml::Value {
+    match key.kind {
+        Kind::U32 => toml::Value::Integer(parse_u32(value).map(i64::from).unwrap_or_default()),
+        Kind::Bool => toml::Value::Boolean(parse_bool(value).unwrap_or_default()),
+        Kind::F64 => toml::Value::Float(parse_f64(value).unwrap_or_default()),
+        Kind::List => toml::Value::Array(
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| toml::Value::String(v.to_string()))
+                .collect(),
+        ),
+        Kind::Str | Kind::Choice(_) => toml::Value::String(value.to_string()),
     }
 }
 
-pub fn detect_project_type(cwd: &Path) -> String {
-    let has = |name: &str| cwd.join(name).exists();
+/// Directories never searched for subprojects.
+const DETECT_SKIP_DIRS: [&str; 12] = [
+    "node_modules",
+    "target",
+    "vendor",
+    "dist",
+    "build",
+    "out",
+    "_build",
+    "deps",
+    "venv",
+    "__pycache__",
+    "bin",
+    "obj",
+];
+
+/// Project type of `dir` from its marker files, or `generic`.
+pub fn detect_project_type(dir: &Path) -> String {
+    let has = |name: &str| dir.join(name).exists();
+    let has_ext = |exts: &[&str]| {
+        fs::read_dir(dir).is_ok_and(|entries| {
+            entries.flatten().any(|e| {
+                e.path()
+                    .extension()
+                    .is_some_and(|ext| exts.iter().any(|x| ext == *x))
+            })
+        })
+    };
     if has("tsconfig.json") {
         "typescript".to_string()
     } else if has("package.json") {
@@ -198,38 +1185,294 @@ pub fn detect_project_type(cwd: &Path) -> String {
         "go".to_string()
     } else if has("Gemfile") {
         "ruby".to_string()
-    } else if has("build.gradle") || has("build.gradle.kts") {
+    } else if has("build.gradle") || has("build.gradle.kts") || has("pom.xml") {
         "java".to_string()
     } else if has("Package.swift") {
         "swift".to_string()
+    } else if has("mix.exs") {
+        "elixir".to_string()
+    } else if has_ext(&["sln", "csproj", "fsproj"]) {
+        "dotnet".to_string()
+    } else if has("justfile") || has("Justfile") {
+        "just".to_string()
+    } else if has("Makefile") {
+        "make".to_string()
     } else {
         "generic".to_string()
     }
 }
 
-pub fn detect_verify_command(project_type: &str) -> String {
+/// Package manager for a JavaScript project, from its lock files.
+fn node_package_manager(dir: &Path) -> &'static str {
+    if dir.join("pnpm-lock.yaml").exists() || dir.join("pnpm-workspace.yaml").exists() {
+        "pnpm"
+    } else if dir.join("yarn.lock").exists() {
+        "yarn"
+    } else {
+        "npm"
+    }
+}
+
+/// Prefix that runs a command in a Python project's environment.
+fn python_runner(dir: &Path) -> &'static str {
+    let pyproject = fs::read_to_string(dir.join("pyproject.toml")).unwrap_or_default();
+    if dir.join("uv.lock").exists() {
+        "uv run "
+    } else if dir.join("poetry.lock").exists() || pyproject.contains("[tool.poetry]") {
+        "poetry run "
+    } else {
+        ""
+    }
+}
+
+/// The first of `names` that the Makefile or justfile in `dir` defines.
+fn task_runner_target(dir: &Path, file: &str, names: &[&str]) -> Option<String> {
+    let content = fs::read_to_string(dir.join(file))
+        .or_else(|_| fs::read_to_string(dir.join(file.to_lowercase())))
+        .unwrap_or_default();
+    names
+        .iter()
+        .find(|name| {
+            content.lines().any(|line| {
+                line.strip_prefix(**name)
+                    .is_some_and(|rest| rest.trim_start().starts_with(':'))
+            })
+        })
+        .map(|name| name.to_string())
+}
+
+pub fn detect_verify_command(project_type: &str, dir: &Path) -> String {
     match project_type {
-        "typescript" => "npm run lint && npm run typecheck".to_string(),
-        "javascript" => "npm run lint".to_string(),
-        "python" => "python -m py_compile".to_string(),
+        "typescript" => {
+            let pm = node_package_manager(dir);
+            format!("{pm} run lint && {pm} run typecheck")
+        }
+        "javascript" => format!("{} run lint", node_package_manager(dir)),
+        "python" => format!("{}python -m py_compile", python_runner(dir)),
         "rust" => "cargo clippy && cargo check".to_string(),
         "go" => "go vet ./...".to_string(),
         "ruby" => "bundle exec rubocop".to_string(),
+        "java" if dir.join("pom.xml").exists() => "mvn -q verify -DskipTests".to_string(),
         "java" => "./gradlew check".to_string(),
         "swift" => "swift build".to_string(),
+        "elixir" => "mix compile --warnings-as-errors && mix format --check-formatted".to_string(),
+        "dotnet" => "dotnet build".to_string(),
+        "make" => task_runner_target(dir, "Makefile", &["lint", "check", "verify"])
+            .map(|t| format!("make {}", t))
+            .unwrap_or_default(),
+        "just" => task_runner_target(dir, "Justfile", &["lint", "check", "verify"])
+            .map(|t| format!("just {}", t))
+            .unwrap_or_default(),
         _ => String::new(),
     }
 }
 
-pub fn detect_test_command(project_type: &str) -> String {
+pub fn detect_test_command(project_type: &str, dir: &Path) -> String {
     match project_type {
-        "typescript" | "javascript" => "npm test".to_string(),
-        "python" => "python -m pytest".to_string(),
+        "typescript" | "javascript" => format!("{} test", node_package_manager(dir)),
+        "python" => format!("{}python -m pytest", python_runner(dir)),
         "rust" => "cargo test".to_string(),
         "go" => "go test ./...".to_string(),
         "ruby" => "bundle exec rspec".to_string(),
+        "java" if dir.join("pom.xml").exists() => "mvn -q test".to_string(),
         "java" => "./gradlew test".to_string(),
         "swift" => "swift test".to_string(),
+        "elixir" => "mix test".to_string(),
+        "dotnet" => "dotnet test".to_string(),
+        "make" => task_runner_target(dir, "Makefile", &["test"])
+            .map(|t| format!("make {}", t))
+            .unwrap_or_default(),
+        "just" => task_runner_target(dir, "Justfile", &["test"])
+            .map(|t| format!("just {}", t))
+            .unwrap_or_default(),
         _ => String::new(),
     }
 }
+
+/// Project types that share tooling, so one nested in the other is treated
+/// as its workspace member.
+fn ecosystem(project_type: &str) -> &str {
+    match project_type {
+        "typescript" | "javascript" => "node",
+        other => other,
+    }
+}
+
+/// Subprojects up to three levels below `root` that need their own
+/// commands. A project nested in the root or in another subproject of the
+/// same ecosystem is taken to be its workspace member and is skipped.
+pub fn detect_targets(root: &Path) -> Vec<Target> {
+    let root_type = detect_project_type(root);
+    let mut found: Vec<(PathBuf, String)> = vec![];
+    let walker = WalkDir::new(root)
+        .min_depth(1)
+        .max_depth(3)
+        .sort_by_file_name()
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_string_lossy();
+            e.file_type().is_dir()
+                && !name.starts_with('.')
+                && !DETECT_SKIP_DIRS.contains(&name.as_ref())
+        });
+    for entry in walker.flatten() {
+        let project_type = detect_project_type(entry.path());
+        if matches!(project_type.as_str(), "generic" | "make" | "just") {
+            continue;
+        }
+        let Ok(rel) = entry.path().strip_prefix(root) else {
+            continue;
+        };
+        let covered = ecosystem(&project_type) == ecosystem(&root_type)
+            || found
+                .iter()
+                .any(|(p, t)| rel.starts_with(p) && ecosystem(t) == ecosystem(&project_type));
+        if !covered {
+            found.push((rel.to_path_buf(), project_type));
+        }
+    }
+
+    found
+        .into_iter()
+        .map(|(rel, project_type)| {
+            let dir = root.join(&rel);
+            Target {
+                path: rel.to_string_lossy().replace('\\', "/"),
+                verify_command: detect_verify_command(&project_type, &dir),
+                test_command: detect_test_command(&project_type, &dir),
+                project_type,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An empty scratch directory for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("spec-loop-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn layer(settings: &[(&str, &str)]) -> Vec<(&'static Key, String)> {
+        settings
+            .iter()
+            .map(|(path, value)| (lookup_key(path).unwrap(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn later_layers_win() {
+        let mut cfg = Config::default();
+        apply_layer(
+            &mut cfg,
+            &layer(&[("limits.max_loops", "10"), ("agent.model", "a")]),
+            "user",
+        )
+        .unwrap();
+        apply_layer(&mut cfg, &layer(&[("limits.max_loops", "5")]), CONFIG_FILE).unwrap();
+        apply_layer(&mut cfg, &layer(&[("agent.model", "b")]), "profile fast").unwrap();
+
+        assert_eq!(cfg.max_loops, 5);
+        assert_eq!(cfg.origin(lookup_key("MAX_LOOPS").unwrap()), CONFIG_FILE);
+        assert_eq!(cfg.claude_model, "b");
+        assert_eq!(
+            cfg.origin(lookup_key("agent.model").unwrap()),
+            "profile fast"
+        );
+        assert_eq!(cfg.origin(lookup_key("agent.bin").unwrap()), "default");
+    }
+
+    #[test]
+    fn enum_keys_are_checked_when_loaded() {
+        let dir = scratch("enum-keys");
+        let path = dir.join(CONFIG_FILE);
+        fs::write(&path, "[safety]\ngit_dirty_policy = \"stash\"\n").unwrap();
+        let file = parse_config_toml(&path).unwrap();
+        let mut cfg = Config::default();
+        apply_layer(&mut cfg, &file.settings, CONFIG_FILE).unwrap();
+        assert_eq!(cfg.git_dirty_policy, "stash");
+
+        fs::write(&path, "[phases]\nreview_strategy = \"maybe\"\n").unwrap();
+        let file = parse_config_toml(&path).unwrap();
+        let err = apply_layer(&mut cfg, &file.settings, CONFIG_FILE).unwrap_err();
+        assert!(format!("{:#}", err).contains("invalid phases.review_strategy"));
+        assert!(lookup_key("ROLLBACK_TASK_STATUS")
+            .unwrap()
+            .validate("done")
+            .is_err());
+    }
+
+    #[test]
+    fn legacy_unknown_keys_are_ignored() {
+        let dir = scratch("legacy");
+        let path = dir.join(LEGACY_CONFIG_FILE);
+        fs::write(&path, "MAX_LOOPS=4\nOLD_SETTING=1\n").unwrap();
+        let (settings, unknown) = parse_legacy_config(&path).unwrap();
+        assert_eq!(settings.len(), 1);
+        assert_eq!(settings[0].0.legacy, "MAX_LOOPS");
+        assert_eq!(unknown, ["OLD_SETTING"]);
+    }
+
+    #[test]
+    fn legacy_lines_without_a_value_are_errors() {
+        let dir = scratch("legacy-malformed");
+        let path = dir.join(LEGACY_CONFIG_FILE);
+        fs::write(&path, "# comment\nMAX_LOOPS=4\nMAX_TASKS_PER_RUN 2\n").unwrap();
+        let err = parse_legacy_config(&path).err().unwrap().to_string();
+        assert!(
+            err.ends_with(":3: expected KEY=value, got 'MAX_TASKS_PER_RUN 2'"),
+            "{}",
+            err
+        );
+        fs::write(&path, "=4\n").unwrap();
+        assert!(parse_legacy_config(&path).is_err());
+    }
+
+    #[test]
+    fn config_block_ends_at_the_first_other_line() {
+        let dir = scratch("config-block");
+        let path = dir.join("spec.md");
+        fs::write(
+            &path,
+            "# Spec\n\n> Config: agent.model = \"a\"\n> limits.max_review_fix_loops = 2\n\n> phases.contract_retries = 9\n",
+        )
+        .unwrap();
+        let settings = parse_config_block(&path).unwrap();
+        let paths: Vec<String> = settings.iter().map(|(k, _)| k.path()).collect();
+        assert_eq!(paths, ["agent.model", "limits.max_review_fix_loops"]);
+    }
+
+    #[test]
+    fn task_overrides_spec_and_flags_are_kept() {
+        let dir = scratch("scoped");
+        fs::write(
+            dir.join("spec.md"),
+            "# Spec\n\n> Config:\n> agent.model = \"spec\"\n> limits.max_review_fix_loops = 5\n",
+        )
+        .unwrap();
+        let task = dir.join("01-task.md");
+        fs::write(&task, "# Task 1\n\n> Config: agent.model = \"task\"\n").unwrap();
+
+        let mut cfg = Config::default();
+        cfg.origins
+            .insert("MAX_REVIEW_FIX_LOOPS", "--max-review-fix-loops".to_string());
+        let spec = scoped_config(&cfg, &dir, None).unwrap();
+        assert_eq!(spec.claude_model, "spec");
+        assert_eq!(spec.max_review_fix_loops, DEFAULT_MAX_REVIEW_FIX_LOOPS);
+
+        let scoped = scoped_config(&cfg, &dir, Some(&task)).unwrap();
+        assert_eq!(scoped.claude_model, "task");
+        assert_eq!(
+            scoped.origin(lookup_key("agent.model").unwrap()),
+            task.display().to_string()
+        );
+
+        fs::write(&task, "# Task 1\n\n> Config: limits.max_loops = 3\n").unwrap();
+        assert!(scoped_config(&cfg, &dir, Some(&task)).is_err());
+    }
+}
@@ -0,0 +1,216 @@
+use crate::prompts::PromptKind;
+
+/// The status block a phase must end its response with.
+pub struct Contract {
+    pub status_key: &'static str,
+    pub allowed: &'static [&'static str],
+    pub counts: &'static [&'static str],
+    /// Key of the line that must be the last line of the response.
+    pub last_key: &'static str,
+}
+
+impl Contract {
+    pub fn for_kind(kind: PromptKind) -> Self {
+        match kind {
+            PromptKind::Build => Self {
+                status_key: "BUILD_STATUS",
+                allowed: &["COMPLETED_TASK", "BLOCKED", "NO_PENDING_TASKS"],
+                counts: &[],
+                last_key: "BUILD_STATUS",
+            },
+            PromptKind::Review => Self {
+                status_key: "REVIEW_STATUS",
+                allowed: &["PASS", "FAIL"],
+                counts: &["MUST_FIX_COUNT", "SHOULD_FIX_COUNT", "SUGGESTION_COUNT"],
+                last_key: "SUGGESTION_COUNT",
+            },
+            PromptKind::Fix => Self {
+                status_key: "BUILD_STATUS",
+                allowed: &["FIXES_APPLIED", "BLOCKED"],
+                counts: &[],
+                last_key: "BUILD_STATUS",
+            },
+            PromptKind::Plan => Self {
+                status_key: "PLAN_STATUS",
+                allowed: &["TASKS_WRITTEN", "BLOCKED"],
+                counts: &[],
+                last_key: "PLAN_STATUS",
+            },
+        }
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &'static str> + '_ {
+        std::iter::once(self.status_key).chain(self.counts.iter().copied())
+    }
+
+    /// Problems with `output`'s status block; empty when it complies.
+    pub fn validate(&self, output: &str) -> Vec<String> {
+        let mut problems = vec![];
+
+        match status_lines(output, self.status_key).as_slice() {
+            [] => problems.push(format!("{} line is missing", self.status_key)),
+            [value] => {
+                if !self.allowed.contains(&value.as_str()) {
+                    problems.push(format!(
+                        "{} is '{}' (expected one of: {})",
+                        self.status_key,
+                        value,
+                        self.allowed.join(", ")
+                    ));
+                }
+            }
+            many => problems.push(format!(
+                "{} appears {} times (expected exactly once)",
+                self.status_key,
+                many.len()
+            )),
+        }
+
+        for key in self.counts {
+            match status_lines(output, key).as_slice() {
+                [] => problems.push(format!("{} line is missing", key)),
+                [value] => {
+                    if value.parse::<u32>().is_err() {
+                        problems.push(format!("{} is '{}' (expected an integer)", key, value));
+                    }
+                }
+                many => problems.push(format!(
+                    "{} appears {} times (expected exactly once)",
+                    key,
+                    many.len()
+                )),
+            }
+        }
+
+        let last = output
+            .lines()
+            .map(str::trim_end)
+            .rfind(|l| !l.is_empty())
+            .unwrap_or_default();
+        if !last.starts_with(&format!("{}:", self.last_key)) {
+            problems.push(format!("the last line must be the {} line", self.last_key));
+        }
+
+        problems
+    }
+
+    /// Follow-up prompt asking the agent to repeat only its status block.
+    pub fn restate_prompt(&self, problems: &[String]) -> String {
+        let mut prompt =
+            String::from("Your previous response did not follow the required output contract:\n\n");
+        for p in problems {
+            prompt.push_str(&format!("- {}\n", p));
+        }
+        prompt.push_str(
+            "\nDo not redo or change any work. Please restate only the final status block, \
+             as plain text lines with no code fences, backticks or other text:\n\n",
+        );
+        prompt.push_str(&format!(
+            "{}: <{}>\n",
+            self.status_key,
+            self.allowed.join("|")
+        ));
+        for key in self.counts {
+            prompt.push_str(&format!("{}: <integer>\n", key));
+        }
+        prompt
+    }
+
+    /// Replace the status lines in `original` with those restated in
+    /// `restated`, keeping the rest of the original response (e.g. review
+    /// findings) and any status lines the restatement left out.
+    pub fn merge(&self, original: &str, restated: &str) -> String {
+        let key_of = |line: &str| {
+            let line = line.trim().trim_matches('`');
+            self.keys().find(|k| line.starts_with(&format!("{}:", k)))
+        };
+        let mut block: Vec<&str> = restated
+            .lines()
+            .map(|l| l.trim().trim_matches('`').trim())
+            .filter(|l| key_of(l).is_some())
+            .collect();
+        let restated_keys: Vec<&str> = block.iter().filter_map(|l| key_of(l)).collect();
+        let mut out: String = original
+            .lines()
+            .filter(|l| key_of(l).is_none_or(|k| !restated_keys.contains(&k)))
+            .map(|l| format!("{}\n", l))
+            .collect();
+        // Keep the last-line key last, whatever order the agent restated in.
+        block.sort_by_key(|l| l.starts_with(&format!("{}:", self.last_key)));
+        for line in block {
+            out.push_str(line);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Values of lines that start with `KEY:` (the strict form the contract asks
+/// for). Indented lines, e.g. in quoted text, do not count.
+fn status_lines(output: &str, key: &str) -> Vec<String> {
+    let prefix = format!("{}:", key);
+    output
+        .lines()
+        .filter_map(|l| l.trim_end().strip_prefix(&prefix))
+        .map(|v| v.trim().to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review() -> Contract {
+        Contract::for_kind(PromptKind::Review)
+    }
+
+    #[test]
+    fn accepts_a_complete_block() {
+        let out = "Looks good.\n\nREVIEW_STATUS: PASS\nMUST_FIX_COUNT: 0\nSHOULD_FIX_COUNT: 1\nSUGGESTION_COUNT: 2\n";
+        assert!(review().validate(out).is_empty());
+    }
+
+    #[test]
+    fn ignores_indented_status_lines() {
+        let out = "    BUILD_STATUS: COMPLETED_TASK\n";
+        let problems = Contract::for_kind(PromptKind::Build).validate(out);
+        assert!(problems.contains(&"BUILD_STATUS line is missing".to_string()));
+        assert!(problems.contains(&"the last line must be the BUILD_STATUS line".to_string()));
+    }
+
+    #[test]
+    fn allows_trailing_whitespace() {
+        let out = "BUILD_STATUS: COMPLETED_TASK  \r\n";
+        assert!(Contract::for_kind(PromptKind::Build)
+            .validate(out)
+            .is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicated_status_lines() {
+        let out = "BUILD_STATUS: BLOCKED\nBUILD_STATUS: COMPLETED_TASK\n";
+        let problems = Contract::for_kind(PromptKind::Build).validate(out);
+        assert_eq!(
+            problems,
+            vec!["BUILD_STATUS appears 2 times (expected exactly once)".to_string()]
+        );
+    }
+
+    #[test]
+    fn rejects_bad_values_and_order() {
+        let out =
+            "REVIEW_STATUS: MAYBE\nMUST_FIX_COUNT: x\nSUGGESTION_COUNT: 0\nSHOULD_FIX_COUNT: 0\n";
+        let problems = review().validate(out);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+    }
+
+    #[test]
+    fn merge_replaces_restated_lines_and_keeps_findings() {
+        let original = "FINDING: F1\nREVIEW_STATUS: FAIL\nMUST_FIX_COUNT: 1\n";
+        let restated = "```\nSUGGESTION_COUNT: 0\nREVIEW_STATUS: FAIL\nSHOULD_FIX_COUNT: 0\n```";
+        let merged = review().merge(original, restated);
+        assert!(merged.starts_with("FINDING: F1\nMUST_FIX_COUNT: 1\n"));
+        assert!(merged.trim_end().ends_with("SUGGESTION_COUNT: 0"));
+        assert!(review().validate(&merged).is_empty(), "{}", merged);
+    }
+}
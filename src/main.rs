@@ -6,24 +6,39 @@ mod cli;
 mod commands;
 mod config;
 mod constants;
+mod contract;
+mod policy;
+mod pr;
 mod prompts;
+mod review;
+mod rollback;
+mod scope;
 mod session;
 mod spec;
+mod spec_guard;
+mod template;
 mod ui;
 mod util;
+mod worktree;
 
 use cli::{Cli, Command};
 use constants::{EXIT_ERROR, EXIT_OK, SPECLOOP_VERSION};
 use ui::Ui;
 
 fn main() {
-    let ui = Ui::new();
     let cli = Cli::parse();
+    let ui = Ui::new(cli.output);
 
     let result = match cli.command {
         Some(Command::Init(args)) => commands::cmd_init(&args, &ui),
         Some(Command::Run(args)) => commands::cmd_run(&args, &ui),
         Some(Command::Status) => commands::cmd_status(&ui),
+        Some(Command::New(args)) => commands::cmd_new(&args, &ui),
+        Some(Command::Plan(args)) => commands::cmd_plan(&args, &ui),
+        Some(Command::Task(args)) => commands::cmd_task(&args, &ui),
+        Some(Command::Prompt(args)) => commands::cmd_prompt(&args),
+        Some(Command::Rollback(args)) => commands::cmd_rollback(&args, &ui),
+        Some(Command::Config(args)) => commands::cmd_config(&args, &ui),
         Some(Command::Version) => {
             println!("spec-loop v{}", SPECLOOP_VERSION);
             Ok(EXIT_OK)
@@ -39,7 +54,7 @@ fn main() {
     match result {
         Ok(code) => std::process::exit(code),
         Err(err) => {
-            ui.step_error(&err.to_string());
+            ui.step_error(&format!("{:#}", err));
             std::process::exit(EXIT_ERROR);
         }
     }
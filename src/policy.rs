@@ -0,0 +1,164 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde::Deserialize;
+
+/// Protected paths and forbidden commands from `.agents/policy.toml`.
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    paths: Vec<(Regex, Rule)>,
+    commands: Vec<(Regex, Rule)>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    #[serde(default)]
+    protected_paths: Vec<Rule>,
+    #[serde(default)]
+    forbidden_commands: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Rule {
+    pattern: String,
+    #[serde(default)]
+    reason: String,
+    /// Abort the run instead of failing the phase.
+    #[serde(default)]
+    critical: bool,
+}
+
+/// A file or command that broke a policy rule.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    /// `path` or `command`.
+    pub kind: &'static str,
+    pub subject: String,
+    pub pattern: String,
+    pub reason: String,
+    pub critical: bool,
+}
+
+impl Violation {
+    fn new(kind: &'static str, subject: &str, rule: &Rule) -> Self {
+        Self {
+            kind,
+            subject: subject.to_string(),
+            pattern: rule.pattern.clone(),
+            reason: rule.reason.clone(),
+            critical: rule.critical,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let what = if self.kind == "path" {
+            format!("changed protected path {}", self.subject)
+        } else {
+            format!("ran forbidden command `{}`", self.subject)
+        };
+        let mut out = format!("{} (rule `{}`", what, self.pattern);
+        if !self.reason.is_empty() {
+            out.push_str(&format!(": {}", self.reason));
+        }
+        out.push(')');
+        if self.critical {
+            out.push_str(" [critical]");
+        }
+        out
+    }
+}
+
+impl Policy {
+    /// Load `path`; a missing file is an empty policy.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let file: PolicyFile = toml::from_str(&content)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+
+        let mut policy = Self::default();
+        for rule in file.protected_paths {
+            let re = Regex::new(&glob_to_regex(&rule.pattern)).with_context(|| {
+                format!(
+                    "{}: invalid protected path {}",
+                    path.display(),
+                    rule.pattern
+                )
+            })?;
+            policy.paths.push((re, rule));
+        }
+        for rule in file.forbidden_commands {
+            let re = Regex::new(&rule.pattern).with_context(|| {
+                format!(
+                    "{}: invalid forbidden command pattern {}",
+                    path.display(),
+                    rule.pattern
+                )
+            })?;
+            policy.commands.push((re, rule));
+        }
+        Ok(policy)
+    }
+
+    /// Files among `changed` that match a protected path.
+    pub fn check_files(&self, changed: &[String]) -> Vec<Violation> {
+        changed
+            .iter()
+            .filter_map(|file| {
+                self.paths
+                    .iter()
+                    .find(|(re, _)| re.is_match(file))
+                    .map(|(_, rule)| Violation::new("path", file, rule))
+            })
+            .collect()
+    }
+
+    /// The first forbidden command rule `command` matches.
+    pub fn check_command(&self, command: &str) -> Option<Violation> {
+        self.commands
+            .iter()
+            .find(|(re, _)| re.is_match(command))
+            .map(|(_, rule)| Violation::new("command", command, rule))
+    }
+}
+
+/// Translate a gitignore-style glob into an anchored regex. `*` stays within
+/// one path segment, `**` spans segments, a match on a directory covers
+/// everything below it, and a pattern without `/` matches at any depth.
+pub fn glob_to_regex(glob: &str) -> String {
+    let glob = glob.trim().trim_start_matches("./");
+    let (glob, dir) = match glob.strip_suffix('/') {
+        Some(g) => (g, true),
+        None => (glob, false),
+    };
+    let mut out = String::from("^");
+    if !glob.contains('/') {
+        out.push_str("(?:.*/)?");
+    }
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    out.push_str("(?:.*/)?");
+                } else {
+                    out.push_str(".*");
+                }
+            }
+            '*' => out.push_str("[^/]*"),
+            '?' => out.push_str("[^/]"),
+            _ => out.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    out.push_str(if dir { "/.*$" } else { "(?:/.*)?$" });
+    out
+}
@@ -0,0 +1,74 @@
+use std::path::Path;
+
+use crate::session::SessionJson;
+use crate::spec::{
+    get_spec_section, get_spec_title, get_task_name, get_task_status, list_task_files,
+};
+
+/// Markdown pull request description for a finished spec: its goal, each
+/// task's outcome, the commits since the base and the review findings.
+pub fn pr_description(spec_dir: &Path, session: &SessionJson, commits: &[String]) -> String {
+    let mut out = format!("# {}\n\n", get_spec_title(spec_dir));
+    let goal = get_spec_section(spec_dir, "Goal");
+    if !goal.is_empty() {
+        out.push_str(&format!("{}\n\n", goal));
+    }
+
+    out.push_str("## Tasks\n\n");
+    for task in list_task_files(spec_dir) {
+        let name = get_task_name(&task);
+        let status = get_task_status(&task);
+        let check = if status.as_str() == "done" { "x" } else { " " };
+        let outcome = session
+            .iterations
+            .iter()
+            .rev()
+            .find(|i| i.task == name)
+            .map(|i| format!(" — {} (iteration {})", i.outcome, i.index))
+            .unwrap_or_default();
+        out.push_str(&format!("- [{}] {}{}\n", check, name, outcome));
+    }
+
+    if !commits.is_empty() {
+        out.push_str("\n## Commits\n\n");
+        for commit in commits {
+            out.push_str(&format!("- {}\n", commit));
+        }
+    }
+
+    let mut review = String::new();
+    let mut tasks: Vec<&str> = vec![];
+    for r in &session.reviews {
+        if !tasks.contains(&r.task.as_str()) {
+            tasks.push(&r.task);
+        }
+    }
+    for task in tasks {
+        let rounds: Vec<_> = session.reviews.iter().filter(|r| r.task == task).collect();
+        let resolved: usize = rounds.iter().map(|r| r.resolved.len()).sum();
+        let Some(last) = rounds.last() else {
+            continue;
+        };
+        review.push_str(&format!(
+            "- {}: {} review round(s), {} finding(s) resolved, final verdict {}\n",
+            task,
+            rounds.len(),
+            resolved,
+            last.status
+        ));
+        for f in &last.findings {
+            review.push_str(&format!(
+                "  - {} ({}) {} — {}\n",
+                f.id,
+                f.severity,
+                f.location(),
+                f.description
+            ));
+        }
+    }
+    if !review.is_empty() {
+        out.push_str("\n## Review\n\n");
+        out.push_str(&review);
+    }
+    out
+}
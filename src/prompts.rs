@@ -1,85 +1,295 @@
-use std::path::Path;
+use std::fs;
+use std::path::{Path, PathBuf};
 
+use anyhow::{bail, Context, Result};
+
+use crate::claude::Access;
 use crate::config::Config;
+use crate::spec::{get_task_files, get_task_name};
+use crate::template::{render, Vars};
+use crate::worktree::{commit_subject, SESSION_TRAILER};
 
-pub fn build_prompt(spec_dir: &Path, cfg: &Config) -> String {
-    let verify_section = if cfg.verify_command.is_empty() {
-        String::new()
-    } else {
+pub const PROMPT_TEMPLATES_DIR: &str = ".agents/templates/prompts";
+pub const TASK_TEMPLATE_PATH: &str = ".agents/templates/task.md";
+
+/// Version of the built-in prompt templates, bumped whenever the prompts'
+/// variables or the output they require change. Installed copies are
+/// stamped with it; unstamped copies predate stamping and count as 1.
+pub const PROMPT_TEMPLATE_VERSION: u32 = 2;
+
+const STAMP_PREFIX: &str = "<!-- spec-loop template v";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    Build,
+    Review,
+    Fix,
+    Plan,
+}
+
+impl PromptKind {
+    pub const ALL: [PromptKind; 4] = [Self::Build, Self::Review, Self::Fix, Self::Plan];
+
+    pub fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "build" => Ok(Self::Build),
+            "review" => Ok(Self::Review),
+            "fix" => Ok(Self::Fix),
+            "plan" => Ok(Self::Plan),
+            other => bail!(
+                "unknown prompt '{}' (expected build, review, fix or plan)",
+                other
+            ),
+        }
+    }
+
+    /// Review must not change the tree it is judging.
+    pub fn access(self) -> Access {
+        match self {
+            Self::Review => Access::ReadOnly,
+            _ => Access::ReadWrite,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Build => "build",
+            Self::Review => "review",
+            Self::Fix => "fix",
+            Self::Plan => "plan",
+        }
+    }
+
+    pub fn default_template(self) -> &'static str {
+        match self {
+            Self::Build => include_str!("../.agents/templates/prompts/build.md"),
+            Self::Review => include_str!("../.agents/templates/prompts/review.md"),
+            Self::Fix => include_str!("../.agents/templates/prompts/fix.md"),
+            Self::Plan => include_str!("../.agents/templates/prompts/plan.md"),
+        }
+    }
+
+    pub fn override_path(self) -> PathBuf {
+        Path::new(PROMPT_TEMPLATES_DIR).join(format!("{}.md", self.name()))
+    }
+
+    /// The built-in template as `init` installs it, under a stamp line with
+    /// the template version and a checksum of the text.
+    pub fn stamped_template(self) -> String {
+        let text = self.default_template();
         format!(
-            "- Run the verify command before finishing: `{}`",
-            cfg.verify_command
+            "{}{} {} -->\n{}",
+            STAMP_PREFIX,
+            PROMPT_TEMPLATE_VERSION,
+            checksum(text),
+            text
         )
-    };
+    }
+}
 
-    let test_section = if cfg.test_command.is_empty() {
-        String::new()
-    } else {
-        format!("- Run tests before finishing: `{}`", cfg.test_command)
+/// How an installed prompt template relates to the built-in one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateState {
+    Missing,
+    Current,
+    /// Older than this binary and unchanged since it was installed, so the
+    /// built-in replaces it.
+    Outdated,
+    /// Older than this binary and edited; it is still used.
+    OutdatedCustomized,
+}
+
+/// Split an installed template into its stamp (version and checksum) and
+/// the template text.
+fn split_stamp(text: &str) -> (Option<(u32, &str)>, &str) {
+    let Some(rest) = text.strip_prefix(STAMP_PREFIX) else {
+        return (None, text);
     };
+    let (line, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    let stamp = line.strip_suffix(" -->").and_then(|l| {
+        let (version, sum) = l.split_once(' ')?;
+        Some((version.parse().ok()?, sum))
+    });
+    match stamp {
+        Some(stamp) => (Some(stamp), body),
+        None => (None, text),
+    }
+}
 
-    let agents_context = if Path::new("AGENTS.md").exists() {
-        "\n## Project Rules\n\nRead and follow AGENTS.md in the project root.".to_string()
+fn template_state(kind: PromptKind, text: &str) -> TemplateState {
+    let (stamp, body) = split_stamp(text);
+    let version = stamp.map_or(1, |(v, _)| v);
+    if version >= PROMPT_TEMPLATE_VERSION || body == kind.default_template() {
+        TemplateState::Current
+    } else if stamp.is_some_and(|(_, sum)| sum == checksum(body)) {
+        TemplateState::Outdated
     } else {
-        String::new()
-    };
+        TemplateState::OutdatedCustomized
+    }
+}
 
-    format!(
-        "You are implementing exactly ONE pending task from a feature spec.\n\n## Spec\n\n{}\n\nRead spec.md, progress.md (if present), and tasks in {}/tasks/.{}\n\n## Workflow\n\n1. Identify the next eligible task (status `pending`, dependencies met).\n2. Claim it by setting task status: `pending -> in-progress`.\n3. Implement only the requested task scope (no unrelated refactors).\n4. Run verification and tests.\n{}\n{}\n5. Update task documentation:\n   - Fill **Done** checklist with specific evidence\n   - Add concrete command output notes\n   - Set status to `in-review` (not `done`)\n6. Append one entry to progress.md if present.\n7. If git is available, commit code changes with a specific message.\n   - Use `git add <specific files>` only\n   - Never stage spec files or progress.md\n\n## Constraints\n\n- Complete one task or report BLOCKED.\n- Keep response concise and factual.\n- Never mark a task `done` during build; review pass controls final completion.\n\n## CRITICAL OUTPUT CONTRACT\n\nOutput EXACTLY one final status line as the very last line:\n\nBUILD_STATUS: COMPLETED_TASK\nBUILD_STATUS: BLOCKED\nBUILD_STATUS: NO_PENDING_TASKS\n\nOptional promise tags:\n- If all tasks are complete: `<promise>COMPLETE</promise>` before final status\n- If blocked: `<promise>BLOCKED</promise>` before final status\n\nFormatting rules:\n- Status lines must be plain text (not in code blocks, not indented, no backticks)\n- The final BUILD_STATUS line must be the last line of the response\n",
-        spec_dir.display(),
-        spec_dir.display(),
-        agents_context,
-        verify_section,
-        test_section,
-    )
+/// State of the project's copy of each prompt template.
+pub fn installed_templates() -> Vec<(PromptKind, TemplateState)> {
+    PromptKind::ALL
+        .into_iter()
+        .map(|kind| {
+            let state = fs::read_to_string(kind.override_path())
+                .map(|text| template_state(kind, &text))
+                .unwrap_or(TemplateState::Missing);
+            (kind, state)
+        })
+        .collect()
 }
 
-pub fn review_prompt(spec_dir: &Path, cfg: &Config, before_sha: Option<&str>) -> String {
-    let diff_instruction = if let Some(sha) = before_sha.filter(|s| !s.is_empty()) {
-        format!("Review ONLY this range: `git diff {}..HEAD`.", sha)
-    } else {
-        "Review latest changes using `git diff main...HEAD` or `git diff --staged`.".to_string()
-    };
+/// FNV-1a, as hex; stable across builds, unlike `DefaultHasher`.
+fn checksum(text: &str) -> String {
+    let hash = text.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ u64::from(b)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
 
-    let verify_section = if cfg.verify_command.is_empty() {
-        String::new()
-    } else {
-        format!("- Run verify command: `{}`", cfg.verify_command)
-    };
+/// Inputs shared by all prompt templates.
+pub struct PromptContext<'a> {
+    pub spec_dir: &'a Path,
+    pub cfg: &'a Config,
+    pub task_file: Option<&'a Path>,
+    pub before_sha: Option<&'a str>,
+    pub review_findings: &'a str,
+    pub files_scope: &'a str,
+    /// spec-loop session id for the commit trailer; empty outside a run.
+    pub session_id: &'a str,
+}
 
-    let test_section = if cfg.test_command.is_empty() {
-        String::new()
-    } else {
-        format!("- Run test command: `{}`", cfg.test_command)
-    };
+/// Returns the project override when present, otherwise the built-in default,
+/// along with where it came from. An outdated override that was never edited
+/// is replaced by the built-in default.
+pub fn load_template(kind: PromptKind) -> Result<(String, String)> {
+    let path = kind.override_path();
+    if path.is_file() {
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        if template_state(kind, &text) != TemplateState::Outdated {
+            return Ok((split_stamp(&text).1.to_string(), path.display().to_string()));
+        }
+    }
+    Ok((kind.default_template().to_string(), "built-in".to_string()))
+}
 
-    format!(
-        "You are an independent reviewer. Verify, do not trust claims.\n\n## Scope\n\n{}\nSpec directory: {}\nDo not read the spec directory path itself as a file; read `spec.md`, `progress.md`, and `tasks/*.md` inside it.\nFocus only on the current task goals, acceptance criteria, and commits in the review range.\nIgnore unrelated style nits or broad refactor suggestions outside this task scope.\n\n## Checks\n\n- Project conventions from AGENTS.md (if present)\n- Task completeness and acceptance evidence\n- No debug leftovers or commented-out code\n- Reasonable structure and error handling\n- Test evidence for meaningful logic changes\n{}\n{}\n\nSeverity policy:\n- Must fix: correctness, broken behavior, failing tests/verify, security, data loss, blocker issues.\n- Should fix: improvement opportunities that do NOT block task completion.\n- Suggestions: optional ideas.\n\nIf verify/test commands fail, treat as must-fix.\n\n## Report format\n\nReport:\n- Must fix\n- Should fix\n- Suggestions\n\nThen output the following 4 lines as the final lines:\n\nREVIEW_STATUS: PASS\nMUST_FIX_COUNT: 0\nSHOULD_FIX_COUNT: 0\nSUGGESTION_COUNT: 0\n\nSet REVIEW_STATUS to FAIL only when must-fix issues exist.\nIf blocked, output `<promise>BLOCKED</promise>` before the status lines.\n\nFormatting rules:\n- Status lines must be plain text (not in code blocks, not indented, no backticks)\n- The SUGGESTION_COUNT line must be the last line of the response\n",
-        diff_instruction,
-        spec_dir.display(),
-        verify_section,
-        test_section,
-    )
+pub fn render_prompt(kind: PromptKind, ctx: &PromptContext) -> Result<String> {
+    let (template, source) = load_template(kind)?;
+    render(&template, &prompt_vars(ctx))
+        .with_context(|| format!("failed to render {} prompt ({})", kind.name(), source))
 }
 
-pub fn fix_prompt(spec_dir: &Path, cfg: &Config, review_excerpt: &str) -> String {
-    let verify_section = if cfg.verify_command.is_empty() {
-        String::new()
+fn prompt_vars(ctx: &PromptContext) -> Vars {
+    let mut vars = Vars::new();
+    vars.insert("spec_dir", ctx.spec_dir.display().to_string());
+    let files = ctx.task_file.map(get_task_files).unwrap_or_default();
+    let (verify_command, test_command) = ctx.cfg.commands_for(&files);
+    vars.insert("verify_command", verify_command);
+    vars.insert("test_command", test_command);
+    vars.insert(
+        "before_sha",
+        ctx.before_sha.unwrap_or_default().trim().to_string(),
+    );
+    vars.insert(
+        "review_findings",
+        ctx.review_findings.trim_end().to_string(),
+    );
+    vars.insert("files_scope", ctx.files_scope.trim_end().to_string());
+    vars.insert(
+        "files_scope_must_fix",
+        if ctx.cfg.files_scope_policy == "warn" {
+            String::new()
+        } else {
+            "true".to_string()
+        },
+    );
+    vars.insert(
+        "task_file",
+        ctx.task_file
+            .map(|p| p.display().to_string())
+            .unwrap_or_default(),
+    );
+    vars.insert(
+        "task_name",
+        ctx.task_file.map(get_task_name).unwrap_or_default(),
+    );
+    vars.insert(
+        "task_content",
+        ctx.task_file
+            .and_then(|p| fs::read_to_string(p).ok())
+            .map(|c| c.trim_end().to_string())
+            .unwrap_or_default(),
+    );
+    vars.insert(
+        "task_template",
+        fs::read_to_string(TASK_TEMPLATE_PATH)
+            .unwrap_or_else(|_| include_str!("../.agents/templates/task.md").to_string())
+            .trim_end()
+            .to_string(),
+    );
+    let (commit_message, commit_trailer) = if ctx.session_id.is_empty() {
+        (String::new(), String::new())
     } else {
-        format!("- Re-run verify command: `{}`", cfg.verify_command)
+        (
+            commit_subject(ctx.cfg, ctx.spec_dir, ctx.task_file, "build").unwrap_or_default(),
+            format!("{}: {}", SESSION_TRAILER, ctx.session_id),
+        )
     };
+    vars.insert("commit_message", commit_message);
+    vars.insert("commit_trailer", commit_trailer);
+    vars.insert(
+        "has_agents_md",
+        if Path::new("AGENTS.md").exists() {
+            "true".to_string()
+        } else {
+            String::new()
+        },
+    );
+    vars
+}
 
-    let test_section = if cfg.test_command.is_empty() {
-        String::new()
-    } else {
-        format!("- Re-run tests: `{}`", cfg.test_command)
-    };
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stamped_templates_round_trip() {
+        let kind = PromptKind::Build;
+        let stamped = kind.stamped_template();
+        let (stamp, body) = split_stamp(&stamped);
+        assert_eq!(stamp.map(|(v, _)| v), Some(PROMPT_TEMPLATE_VERSION));
+        assert_eq!(body, kind.default_template());
+        assert_eq!(template_state(kind, &stamped), TemplateState::Current);
+    }
 
-    format!(
-        "You are fixing review findings from a failed review.\n\n## Spec\n\n{}\nDo not read the spec directory path itself as a file; read `spec.md`, `progress.md`, and `tasks/*.md` inside it.\nFocus only on fixes that are directly tied to current task goals and the review findings.\n\n## Findings to fix\n\n{}\n\n## Workflow\n\n1. Fix all must-fix items.\n2. Fix should-fix items only when low-risk and quick.\n3. Keep scope narrow (no unrelated refactors).\n4. Re-run verification.\n{}\n{}\n5. If git is available, commit changes with explicit file staging only.\n\n## CRITICAL OUTPUT CONTRACT\n\nOutput one final status line as the very last line:\n\nBUILD_STATUS: FIXES_APPLIED\nBUILD_STATUS: BLOCKED\n\nIf blocked, output `<promise>BLOCKED</promise>` before final status.\n\nFormatting rules:\n- Status lines must be plain text (not in code blocks, not indented, no backticks)\n- The final BUILD_STATUS line must be the last line of the response\n",
-        spec_dir.display(),
-        review_excerpt,
-        verify_section,
-        test_section,
-    )
+    #[test]
+    fn older_templates_are_outdated() {
+        let kind = PromptKind::Review;
+        let body = "An older review prompt.\n";
+        let old = format!(
+            "{}{} {} -->\n{}",
+            STAMP_PREFIX,
+            PROMPT_TEMPLATE_VERSION - 1,
+            checksum(body),
+            body
+        );
+        assert_eq!(template_state(kind, &old), TemplateState::Outdated);
+        let edited = format!("{}\nExtra rule.\n", old);
+        assert_eq!(
+            template_state(kind, &edited),
+            TemplateState::OutdatedCustomized
+        );
+        // Unstamped copies predate stamping.
+        assert_eq!(
+            template_state(kind, "Custom prompt\n"),
+            TemplateState::OutdatedCustomized
+        );
+        assert_eq!(
+            template_state(kind, kind.default_template()),
+            TemplateState::Current
+        );
+    }
 }
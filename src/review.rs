@@ -0,0 +1,475 @@
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+
+use crate::claude::parse_kv;
+
+pub const REVIEW_STRATEGIES: [&str; 2] = ["single", "consensus"];
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ReviewFinding {
+    pub id: String,
+    pub severity: String,
+    pub file: String,
+    #[serde(default)]
+    pub line: Option<u32>,
+    pub description: String,
+    /// Under consensus review, the reviewers (1-based) that raised it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reviewers: Vec<u32>,
+}
+
+impl ReviewFinding {
+    pub fn location(&self) -> String {
+        match (self.file.as_str(), self.line) {
+            ("", _) => "-".to_string(),
+            (file, Some(line)) => format!("{}:{}", file, line),
+            (file, None) => file.to_string(),
+        }
+    }
+
+    /// Whether `other` reports the same problem: the same id, the same
+    /// `file:line`, or the same file and description.
+    fn same_as(&self, other: &ReviewFinding) -> bool {
+        if self.id == other.id {
+            return true;
+        }
+        if self.file != other.file {
+            return false;
+        }
+        match (self.line, other.line) {
+            (Some(a), Some(b)) if !self.file.is_empty() => a == b,
+            _ => normalize(&self.description) == normalize(&other.description),
+        }
+    }
+}
+
+/// Lowercase words, ignoring punctuation and spacing.
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn severity_rank(severity: &str) -> u8 {
+    match severity {
+        "must" => 0,
+        "should" => 1,
+        _ => 2,
+    }
+}
+
+/// Parsed review output: verdict, counts and `FINDING:` lines.
+#[derive(Debug, Clone, Default)]
+pub struct ReviewReport {
+    pub status: String,
+    pub must_fix_count: u32,
+    pub should_fix_count: u32,
+    pub findings: Vec<ReviewFinding>,
+    pub resolved: Vec<String>,
+    /// Problems with the output format, e.g. malformed findings or counts
+    /// that disagree with the findings.
+    pub errors: Vec<String>,
+}
+
+pub fn parse_review(output: &str) -> ReviewReport {
+    let mut report = ReviewReport {
+        status: parse_kv(output, "REVIEW_STATUS").unwrap_or_default(),
+        ..Default::default()
+    };
+
+    let mut seen = HashSet::new();
+    for line in output.lines() {
+        let Some(rest) = line.trim().strip_prefix("FINDING:") else {
+            continue;
+        };
+        match parse_finding(rest) {
+            Ok(finding) => {
+                if !seen.insert(finding.id.clone()) {
+                    report
+                        .errors
+                        .push(format!("duplicate finding id {}", finding.id));
+                    continue;
+                }
+                report.findings.push(finding);
+            }
+            Err(err) => report.errors.push(format!("{}: `{}`", err, line.trim())),
+        }
+    }
+
+    let tally = |severity: &str| {
+        report
+            .findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count() as u32
+    };
+    let must_found = tally("must");
+    let should_found = tally("should");
+
+    report.must_fix_count = parse_count(output, "MUST_FIX_COUNT", must_found, &mut report.errors);
+    report.should_fix_count =
+        parse_count(output, "SHOULD_FIX_COUNT", should_found, &mut report.errors);
+
+    if let Some(v) = parse_kv(output, "RESOLVED_FINDINGS") {
+        if !v.eq_ignore_ascii_case("none") {
+            report.resolved = v
+                .split([',', ' '])
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(ToOwned::to_owned)
+                .collect();
+        }
+    }
+
+    report
+}
+
+/// Reads an integer count; when it is missing or unparsable, falls back to
+/// the number of matching findings and records why.
+fn parse_count(output: &str, key: &str, found: u32, errors: &mut Vec<String>) -> u32 {
+    match parse_kv(output, key) {
+        None => {
+            errors.push(format!("{} missing", key));
+            found
+        }
+        Some(raw) => match raw.parse::<u32>() {
+            Ok(n) => {
+                if found > 0 && n != found {
+                    errors.push(format!("{} is {} but {} findings listed", key, n, found));
+                }
+                n.max(found)
+            }
+            Err(_) => {
+                errors.push(format!("{} is not an integer: '{}'", key, raw));
+                found
+            }
+        },
+    }
+}
+
+fn parse_finding(rest: &str) -> Result<ReviewFinding, String> {
+    let parts: Vec<&str> = rest.split('|').map(str::trim).collect();
+    if parts.len() < 4 {
+        return Err("finding needs 4 fields (id | severity | file:line | description)".into());
+    }
+    let id = parts[0].to_string();
+    if id.is_empty() || id.contains(char::is_whitespace) {
+        return Err("finding id must be a single word".into());
+    }
+    let severity = parts[1].to_lowercase();
+    let severity = match severity.as_str() {
+        "must" | "must-fix" | "must_fix" => "must",
+        "should" | "should-fix" | "should_fix" => "should",
+        "suggestion" | "suggest" => "suggestion",
+        _ => return Err(format!("unknown severity '{}'", parts[1])),
+    }
+    .to_string();
+    let (file, line) = parse_location(parts[2]);
+    // Descriptions may themselves contain `|`.
+    let description = parts[3..].join(" | ");
+    if description.is_empty() {
+        return Err("finding description is empty".into());
+    }
+    Ok(ReviewFinding {
+        id,
+        severity,
+        file,
+        line,
+        description,
+        reviewers: vec![],
+    })
+}
+
+fn parse_location(raw: &str) -> (String, Option<u32>) {
+    let raw = raw.trim().trim_matches('`');
+    if raw.is_empty() || raw == "-" {
+        return (String::new(), None);
+    }
+    if let Some((file, line)) = raw.rsplit_once(':') {
+        let line = line.split('-').next().unwrap_or_default();
+        if let Ok(n) = line.parse::<u32>() {
+            return (file.to_string(), Some(n));
+        }
+    }
+    (raw.to_string(), None)
+}
+
+/// Render findings as a markdown checklist for the fix and recheck prompts.
+pub fn findings_checklist(findings: &[ReviewFinding]) -> String {
+    findings
+        .iter()
+        .map(|f| {
+            format!(
+                "- [ ] {} ({}) {} — {}\n",
+                f.id,
+                f.severity,
+                f.location(),
+                f.description
+            )
+        })
+        .collect()
+}
+
+/// Ids from `previous` that were fixed, judged by the recheck's explicit
+/// `RESOLVED_FINDINGS` line or by the id no longer being reported.
+pub fn resolved_ids(previous: &[ReviewFinding], recheck: &ReviewReport) -> Vec<String> {
+    previous
+        .iter()
+        .filter(|f| {
+            recheck.resolved.contains(&f.id) || !recheck.findings.iter().any(|n| n.id == f.id)
+        })
+        .map(|f| f.id.clone())
+        .collect()
+}
+
+/// Combine independent reviews: the round passes when at least `quorum`
+/// reviewers passed. Findings from different reviewers that report the same
+/// problem are merged, keeping the highest severity and every reviewer that
+/// raised it. Ids are prefixed with the first reviewer (`R2-F1`) unless they
+/// already refer to a merged finding from an earlier round.
+pub fn merge_consensus(reports: &[(ReviewReport, bool)], quorum: u32) -> ReviewReport {
+    let passes = reports.iter().filter(|(_, passed)| *passed).count() as u32;
+    let mut merged = ReviewReport {
+        status: if passes >= quorum { "PASS" } else { "FAIL" }.to_string(),
+        ..Default::default()
+    };
+
+    for (i, (report, _)) in reports.iter().enumerate() {
+        let reviewer = i as u32 + 1;
+        for finding in &report.findings {
+            let mut finding = finding.clone();
+            if !is_merged_id(&finding.id) {
+                finding.id = format!("R{}-{}", reviewer, finding.id);
+            }
+            // A reviewer's own findings are never merged with each other.
+            let existing = merged
+                .findings
+                .iter_mut()
+                .find(|f| !f.reviewers.contains(&reviewer) && f.same_as(&finding));
+            match existing {
+                Some(existing) => {
+                    if severity_rank(&finding.severity) < severity_rank(&existing.severity) {
+                        existing.severity = finding.severity;
+                    }
+                    existing.reviewers.push(reviewer);
+                }
+                None => {
+                    finding.reviewers = vec![reviewer];
+                    merged.findings.push(finding);
+                }
+            }
+        }
+        for id in &report.resolved {
+            if !merged.resolved.contains(id) {
+                merged.resolved.push(id.clone());
+            }
+        }
+        merged.errors.extend(
+            report
+                .errors
+                .iter()
+                .map(|e| format!("reviewer {}: {}", i + 1, e)),
+        );
+        merged.should_fix_count = merged.should_fix_count.max(report.should_fix_count);
+        if !reports[i].1 {
+            merged.must_fix_count = merged.must_fix_count.max(report.must_fix_count);
+        }
+    }
+
+    let tally = |severity: &str| {
+        merged
+            .findings
+            .iter()
+            .filter(|f| f.severity == severity)
+            .count() as u32
+    };
+    if merged.status == "PASS" {
+        // Minority must-fix findings are recorded but do not block.
+        merged.must_fix_count = 0;
+    } else {
+        merged.must_fix_count = merged.must_fix_count.max(tally("must"));
+    }
+    merged.should_fix_count = merged.should_fix_count.max(tally("should"));
+    merged
+}
+
+fn is_merged_id(id: &str) -> bool {
+    id.split_once('-').is_some_and(|(r, _)| {
+        r.len() > 1 && r.starts_with('R') && r[1..].chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn review(status: &str, findings: &[&str]) -> ReviewReport {
+        let must = findings.iter().filter(|f| f.contains("| must")).count();
+        let should = findings.iter().filter(|f| f.contains("| should")).count();
+        let mut output = format!(
+            "REVIEW_STATUS: {}\nMUST_FIX_COUNT: {}\nSHOULD_FIX_COUNT: {}\n",
+            status, must, should
+        );
+        for finding in findings {
+            output.push_str(&format!("FINDING: {}\n", finding));
+        }
+        parse_review(&output)
+    }
+
+    #[test]
+    fn parses_findings_and_counts() {
+        let report = review(
+            "FAIL",
+            &[
+                "F1 | must-fix | `src/a.rs:3-7` | Off by one | in the loop",
+                "F2 | should | - | Missing docs",
+            ],
+        );
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!((report.must_fix_count, report.should_fix_count), (1, 1));
+        assert_eq!(report.findings[0].location(), "src/a.rs:3");
+        assert_eq!(report.findings[0].description, "Off by one | in the loop");
+        assert_eq!(report.findings[1].location(), "-");
+    }
+
+    #[test]
+    fn rejects_findings_without_an_id() {
+        let report = review(
+            "FAIL",
+            &[" | must | src/a.rs | Off by one", "F1 must src/a.rs"],
+        );
+        assert!(report.findings.is_empty());
+        assert_eq!(report.errors.len(), 2, "{:?}", report.errors);
+        assert!(report.errors[0].starts_with("finding id must be a single word"));
+        assert!(report.errors[1].starts_with("finding needs 4 fields"));
+        // MUST_FIX_COUNT says 1 but nothing parsed; the count is kept.
+        assert_eq!(report.must_fix_count, 1);
+    }
+
+    #[test]
+    fn rejects_duplicate_finding_ids() {
+        let report = review(
+            "FAIL",
+            &[
+                "F1 | must | src/a.rs:3 | Off by one",
+                "F1 | should | src/b.rs | Missing docs",
+            ],
+        );
+        assert_eq!(report.findings.len(), 1);
+        assert_eq!(report.findings[0].file, "src/a.rs");
+        assert!(report
+            .errors
+            .contains(&"duplicate finding id F1".to_string()));
+    }
+
+    #[test]
+    fn missing_counts_fall_back_to_the_findings() {
+        let report =
+            parse_review("REVIEW_STATUS: FAIL\nFINDING: F1 | must | src/a.rs | Off by one\n");
+        assert_eq!(report.must_fix_count, 1);
+        assert!(report
+            .errors
+            .contains(&"MUST_FIX_COUNT missing".to_string()));
+        assert!(report
+            .errors
+            .contains(&"SHOULD_FIX_COUNT missing".to_string()));
+    }
+
+    #[test]
+    fn consensus_passes_at_quorum() {
+        let pass = (review("PASS", &[]), true);
+        let fail = (
+            review("FAIL", &["F1 | must | src/a.rs:3 | Off by one"]),
+            false,
+        );
+
+        let merged = merge_consensus(&[pass.clone(), pass.clone(), fail.clone()], 2);
+        assert_eq!(merged.status, "PASS");
+        // The minority finding is kept but does not block.
+        assert_eq!(merged.must_fix_count, 0);
+        assert_eq!(merged.findings.len(), 1);
+
+        let merged = merge_consensus(&[pass, fail.clone(), fail], 2);
+        assert_eq!(merged.status, "FAIL");
+        assert_eq!(merged.must_fix_count, 1);
+    }
+
+    #[test]
+    fn consensus_merges_the_same_problem() {
+        let reports = [
+            (
+                review(
+                    "FAIL",
+                    &[
+                        "F1 | should | src/a.rs:3 | Off by one",
+                        "F2 | must | - | Missing tests for the parser.",
+                    ],
+                ),
+                false,
+            ),
+            (
+                review(
+                    "FAIL",
+                    &[
+                        "F1 | must | src/a.rs:3 | Loop bound is wrong",
+                        "F2 | should | - | missing  tests for the parser",
+                        "F3 | must | src/b.rs | Leaks a handle",
+                    ],
+                ),
+                false,
+            ),
+            (review("PASS", &[]), true),
+        ];
+        let merged = merge_consensus(&reports, 2);
+        let summary: Vec<(&str, &str, Vec<u32>)> = merged
+            .findings
+            .iter()
+            .map(|f| (f.id.as_str(), f.severity.as_str(), f.reviewers.clone()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("R1-F1", "must", vec![1, 2]),
+                ("R1-F2", "must", vec![1, 2]),
+                ("R2-F3", "must", vec![2]),
+            ]
+        );
+        assert_eq!(merged.must_fix_count, 3);
+    }
+
+    #[test]
+    fn consensus_keeps_one_reviewers_findings_apart() {
+        let reports = [(
+            review(
+                "FAIL",
+                &[
+                    "F1 | must | src/a.rs:3 | Off by one",
+                    "F2 | must | src/a.rs:3 | Unchecked unwrap",
+                ],
+            ),
+            false,
+        )];
+        assert_eq!(merge_consensus(&reports, 1).findings.len(), 2);
+    }
+
+    #[test]
+    fn consensus_keeps_merged_ids_on_recheck() {
+        let reports = [
+            (
+                review("FAIL", &["R2-F3 | must | src/b.rs | Still leaks"]),
+                false,
+            ),
+            (
+                review("FAIL", &["R2-F3 | must | src/b.rs | Leaks a handle"]),
+                false,
+            ),
+        ];
+        let merged = merge_consensus(&reports, 2);
+        assert_eq!(merged.findings.len(), 1);
+        assert_eq!(merged.findings[0].id, "R2-F3");
+        assert_eq!(merged.findings[0].reviewers, [1, 2]);
+    }
+}
@@ -0,0 +1,166 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::spec::TaskStatus;
+use crate::spec_guard::SpecSnapshot;
+use crate::util::{git, head_sha, RepoPaths};
+use crate::worktree::unrelated_changes;
+
+pub const ROLLBACK_MODES: [&str; 2] = ["reset", "revert"];
+pub const ROLLBACK_POLICIES: [&str; 3] = ["off", "reset", "revert"];
+/// Statuses a rolled-back task may be moved to.
+pub const ROLLBACK_STATUSES: [TaskStatus; 2] = [TaskStatus::Pending, TaskStatus::Blocked];
+
+/// Undo the commits in `before_sha..commit`, saving their diff to `patch`.
+///
+/// Refuses while files outside the spec and session directories have
+/// uncommitted changes. `reset` moves the branch back to `before_sha` and
+/// needs `commit` to still be HEAD; `revert` adds revert commits and works
+/// with later history. The spec directory keeps its current contents either
+/// way, so task status and progress notes survive. Returns the number of
+/// commits undone.
+pub fn rollback(
+    spec_dir: &Path,
+    session_dir: &Path,
+    before_sha: &str,
+    commit: &str,
+    mode: &str,
+    patch: &Path,
+) -> Result<usize> {
+    if before_sha.is_empty() {
+        bail!("no base commit was recorded for this iteration");
+    }
+    let unrelated = unrelated_changes(spec_dir, session_dir);
+    if !unrelated.is_empty() {
+        bail!(
+            "working tree has unrelated changes; commit or stash them first:\n  {}",
+            unrelated.join("\n  ")
+        );
+    }
+    let head = head_sha();
+    let commit = if commit.is_empty() {
+        head.as_str()
+    } else {
+        commit
+    };
+    if git(&["merge-base", "--is-ancestor", before_sha, commit]).is_err()
+        || git(&["merge-base", "--is-ancestor", commit, &head]).is_err()
+    {
+        bail!(
+            "{} is not on the current branch",
+            &before_sha[..before_sha.len().min(7)]
+        );
+    }
+    if mode == "reset" && commit != head {
+        bail!("HEAD has moved past the iteration's last commit; use --mode revert");
+    }
+
+    let range = format!("{}..{}", before_sha, commit);
+    let count = git(&["rev-list", "--count", &range])?
+        .parse::<usize>()
+        .unwrap_or(0);
+    if count == 0 {
+        return Ok(0);
+    }
+
+    let diff = git(&["diff", "--binary", before_sha, commit])?;
+    fs::write(patch, format!("{}\n", diff))
+        .with_context(|| format!("failed to write {}", patch.display()))?;
+
+    let saved = SpecSnapshot::take(spec_dir)?;
+    // Set the spec directory aside so the commits can be undone under it;
+    // it is restored below. Other local changes stay, as `--keep` and
+    // `revert` refuse to overwrite them.
+    discard_spec_changes(spec_dir)?;
+    let result = match mode {
+        "reset" => git(&["reset", "-q", "--keep", before_sha]).map(drop),
+        _ => git(&["revert", "--no-edit", &range])
+            .map(drop)
+            .inspect_err(|_| {
+                let _ = git(&["revert", "--abort"]);
+            }),
+    };
+    saved.restore()?;
+    result?;
+    Ok(count)
+}
+
+/// Return the spec directory to HEAD: tracked files are checked out and
+/// untracked ones removed.
+fn discard_spec_changes(spec_dir: &Path) -> Result<()> {
+    let Some(repo) = RepoPaths::discover() else {
+        return Ok(());
+    };
+    let spec = format!(":(top){}", repo.relative(&spec_dir.to_string_lossy()));
+    if !git(&["ls-tree", "-r", "--name-only", "HEAD", "--", &spec])?.is_empty() {
+        git(&["checkout", "-q", "HEAD", "--", &spec])?;
+    }
+    git(&["clean", "-q", "-f", "-d", "--", &spec])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestRepo;
+
+    const SPEC: &str = ".agents/specs/demo";
+    const SESSIONS: &str = "docs/sessions";
+
+    /// A repository with one commit to roll back; returns its base.
+    fn repo_with_iteration(name: &str) -> (TestRepo, String) {
+        let repo = TestRepo::new(name);
+        repo.write("docs/notes.md", "notes\n");
+        repo.write(&format!("{}/spec.md", SPEC), "# Spec: Demo\n");
+        let base = repo.commit("setup");
+        repo.write("src/a.rs", "a\n");
+        repo.commit("build");
+        (repo, base)
+    }
+
+    #[test]
+    fn refuses_with_a_dirty_file_next_to_the_session_dir() {
+        let (repo, base) = repo_with_iteration("rollback-refuse");
+        repo.write("docs/notes.md", "local edit\n");
+        let err = rollback(
+            Path::new(SPEC),
+            Path::new(SESSIONS),
+            &base,
+            "",
+            "reset",
+            Path::new("iteration.patch"),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("docs/notes.md"), "{}", err);
+        assert_eq!(repo.read("docs/notes.md"), "local edit\n");
+        assert_eq!(repo.read("src/a.rs"), "a\n");
+    }
+
+    #[test]
+    fn keeps_session_and_spec_changes() {
+        let (repo, base) = repo_with_iteration("rollback-keep");
+        repo.write(&format!("{}/session.md", SESSIONS), "log\n");
+        repo.write(&format!("{}/spec.md", SPEC), "# Spec: Demo\n\nEdited.\n");
+        repo.write(&format!("{}/progress.md", SPEC), "progress\n");
+        let count = rollback(
+            Path::new(SPEC),
+            Path::new(SESSIONS),
+            &base,
+            "",
+            "reset",
+            Path::new("iteration.patch"),
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(head_sha(), base);
+        assert!(!Path::new("src/a.rs").exists());
+        assert_eq!(repo.read(&format!("{}/session.md", SESSIONS)), "log\n");
+        assert_eq!(
+            repo.read(&format!("{}/spec.md", SPEC)),
+            "# Spec: Demo\n\nEdited.\n"
+        );
+        assert_eq!(repo.read(&format!("{}/progress.md", SPEC)), "progress\n");
+    }
+}
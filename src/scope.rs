@@ -0,0 +1,175 @@
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::policy::glob_to_regex;
+use crate::spec::get_task_files;
+use crate::util::{changed_files_since, RepoPaths};
+
+pub const SCOPE_POLICIES: [&str; 3] = ["warn", "must-fix", "block"];
+
+/// Comparison between the files a task declares and the files it changed.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeReport {
+    pub undeclared: Vec<String>,
+    pub untouched: Vec<String>,
+}
+
+impl ScopeReport {
+    pub fn is_clean(&self) -> bool {
+        self.undeclared.is_empty() && self.untouched.is_empty()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut out = String::new();
+        if !self.undeclared.is_empty() {
+            out.push_str("Changed but not declared in the task's Files section:\n");
+            for f in &self.undeclared {
+                out.push_str(&format!("- {}\n", f));
+            }
+        }
+        if !self.untouched.is_empty() {
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            out.push_str("Declared in the task's Files section but not changed:\n");
+            for f in &self.untouched {
+                out.push_str(&format!("- {}\n", f));
+            }
+        }
+        out
+    }
+}
+
+/// Compare `before_sha..HEAD` against the task's declared `## Files`.
+///
+/// Returns `None` when there is no range to compare or the task declares no
+/// files. Spec files are ignored; they are tracked separately. Declared paths
+/// and `spec_dir` are relative to the current directory, git's to the
+/// repository root; both are compared in the latter form.
+pub fn check_scope(task_file: &Path, spec_dir: &Path, before_sha: &str) -> Option<ScopeReport> {
+    if before_sha.is_empty() {
+        return None;
+    }
+    let declared = get_task_files(task_file);
+    if declared.is_empty() {
+        return None;
+    }
+
+    let repo = RepoPaths::discover()?;
+    let spec_prefix = format!("{}/", repo.relative(&spec_dir.to_string_lossy()));
+    let changed: Vec<String> = changed_files_since(before_sha)
+        .into_iter()
+        .filter(|f| !f.starts_with(&spec_prefix))
+        .collect();
+    let patterns: Vec<(String, String)> = declared
+        .into_iter()
+        .map(|d| (repo.relative(&d), d))
+        .collect();
+
+    let undeclared = changed
+        .iter()
+        .filter(|f| !patterns.iter().any(|(p, _)| path_matches(p, f)))
+        .cloned()
+        .collect();
+    let untouched = patterns
+        .iter()
+        .filter(|(p, _)| !changed.iter().any(|f| path_matches(p, f)))
+        .map(|(_, d)| d.clone())
+        .collect();
+
+    Some(ScopeReport {
+        undeclared,
+        untouched,
+    })
+}
+
+/// Tasks in `others` whose declared `## Files` cover the `changed` paths
+/// (repository-relative) when `task_file`'s own declaration covers none of
+/// them, i.e. the agent worked on another task.
+///
+/// Nothing is reported when `task_file` declares no files. Spec files are
+/// ignored, as in [`check_scope`].
+pub fn claimed_by_other_tasks(
+    task_file: &Path,
+    others: &[PathBuf],
+    spec_dir: &Path,
+    changed: &[String],
+) -> Vec<PathBuf> {
+    let Some(repo) = RepoPaths::discover() else {
+        return vec![];
+    };
+    let spec_prefix = format!("{}/", repo.relative(&spec_dir.to_string_lossy()));
+    let changed: Vec<String> = changed
+        .iter()
+        .filter(|f| !f.starts_with(&spec_prefix))
+        .cloned()
+        .collect();
+    let declared = |task: &Path| -> Vec<String> {
+        get_task_files(task)
+            .iter()
+            .map(|d| repo.relative(d))
+            .collect()
+    };
+    let own = declared(task_file);
+    if changed.is_empty() || own.is_empty() || covers_any(&own, &changed) {
+        return vec![];
+    }
+    others
+        .iter()
+        .filter(|task| task.as_path() != task_file && covers_any(&declared(task), &changed))
+        .cloned()
+        .collect()
+}
+
+fn covers_any(declared: &[String], changed: &[String]) -> bool {
+    changed
+        .iter()
+        .any(|f| declared.iter().any(|d| path_matches(d, f)))
+}
+
+/// A declared path matches itself, or anything below it when it names a
+/// directory. Paths with `*` or `?` are globs, as in the policy file.
+fn path_matches(declared: &str, changed: &str) -> bool {
+    if declared.contains(['*', '?']) {
+        return Regex::new(&glob_to_regex(declared)).is_ok_and(|re| re.is_match(changed));
+    }
+    let declared = declared.trim_end_matches('/');
+    changed == declared || changed.starts_with(&format!("{}/", declared))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_paths_and_directories() {
+        assert!(path_matches("src/a.rs", "src/a.rs"));
+        assert!(!path_matches("src/a.rs", "src/a.rs.bak"));
+        assert!(path_matches("src/api", "src/api/mod.rs"));
+        assert!(path_matches("src/api/", "src/api/v1/users.rs"));
+        assert!(!path_matches("src/api", "src/apis/mod.rs"));
+    }
+
+    #[test]
+    fn coverage_of_changed_files() {
+        let declared = vec!["src/api".to_string(), "tests/*.rs".to_string()];
+        assert!(covers_any(
+            &declared,
+            &["README.md".into(), "src/api/mod.rs".into()]
+        ));
+        assert!(covers_any(&declared, &["tests/api.rs".into()]));
+        assert!(!covers_any(&declared, &["src/cli.rs".into()]));
+        assert!(!covers_any(&[], &["src/api/mod.rs".into()]));
+    }
+
+    #[test]
+    fn globs() {
+        assert!(path_matches("src/*.rs", "src/a.rs"));
+        assert!(!path_matches("src/*.rs", "src/api/a.rs"));
+        assert!(path_matches("src/**/*.rs", "src/api/v1/a.rs"));
+        assert!(path_matches("src/**/*.rs", "src/a.rs"));
+        assert!(path_matches("tests/test_?.py", "tests/test_a.py"));
+        assert!(!path_matches("tests/test_?.py", "tests/test_ab.py"));
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
@@ -6,6 +7,7 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 use crate::config::Config;
+use crate::review::ReviewFinding;
 use crate::util::{format_cost, format_duration, now_human, now_iso};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -18,6 +20,39 @@ pub struct SessionIteration {
     pub must_fix_count: u32,
     pub should_fix_count: u32,
     pub commit: String,
+    /// HEAD before the iteration's build; the base for `spec-loop rollback`.
+    #[serde(default)]
+    pub before_sha: String,
+    pub timestamp: String,
+}
+
+/// One review round (initial review or recheck) with its parsed findings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionReview {
+    pub iteration: u32,
+    pub task: String,
+    pub round: u32,
+    pub status: String,
+    pub must_fix_count: u32,
+    pub should_fix_count: u32,
+    #[serde(default)]
+    pub findings: Vec<ReviewFinding>,
+    #[serde(default)]
+    pub resolved: Vec<String>,
+    #[serde(default)]
+    pub format_errors: Vec<String>,
+    pub timestamp: String,
+}
+
+/// A task status change and who made it: `harness`, `agent` or `user`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionStatusChange {
+    pub task: String,
+    pub from: String,
+    pub to: String,
+    pub actor: String,
+    #[serde(default)]
+    pub iteration: Option<u32>,
     pub timestamp: String,
 }
 
@@ -31,6 +66,17 @@ pub struct SessionInvocation {
     pub skip_review: bool,
     #[serde(default)]
     pub claude_session_ids: Vec<String>,
+    /// `PERMISSION_MODE` and, in `allowlist` mode, the tools each kind of
+    /// phase was allowed.
+    #[serde(default)]
+    pub permission_mode: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_tools: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub review_allowed_tools: Vec<String>,
+    /// `run --profile` name, if one was selected.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub profile: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,6 +89,11 @@ pub struct SessionJson {
     pub max_loops: u32,
     pub max_review_fix_loops: u32,
     pub max_tasks_per_run: u32,
+    /// Branch the spec's work happens on and the commit it started from.
+    #[serde(default)]
+    pub branch: String,
+    #[serde(default)]
+    pub base_sha: String,
     #[serde(default)]
     pub claude_sessions: Vec<String>,
     #[serde(default)]
@@ -50,6 +101,14 @@ pub struct SessionJson {
     #[serde(default)]
     pub iterations: Vec<SessionIteration>,
     #[serde(default)]
+    pub reviews: Vec<SessionReview>,
+    #[serde(default)]
+    pub history: Vec<SessionStatusChange>,
+    /// HEAD before each task's latest build, by task name; the base when a
+    /// later run picks the task up in review.
+    #[serde(default)]
+    pub task_bases: BTreeMap<String, String>,
+    #[serde(default)]
     pub ended_at: Option<String>,
     #[serde(default)]
     pub duration_seconds: Option<u64>,
@@ -161,9 +220,14 @@ pub fn ensure_session_initialized(
             max_loops: cfg.max_loops,
             max_review_fix_loops: cfg.max_review_fix_loops,
             max_tasks_per_run: cfg.max_tasks_per_run,
+            branch: String::new(),
+            base_sha: String::new(),
             claude_sessions: vec![],
             invocations: vec![],
             iterations: vec![],
+            reviews: vec![],
+            history: vec![],
+            task_bases: BTreeMap::new(),
             ended_at: None,
             duration_seconds: None,
             total_cost_usd: None,
@@ -191,6 +255,7 @@ pub fn ensure_session_initialized(
 pub fn append_run_invocation_header(
     session_path: &Path,
     mode: &str,
+    profile: &str,
     once: bool,
     max_tasks: u32,
     max_loops: u32,
@@ -203,11 +268,17 @@ pub fn append_run_invocation_header(
         .unwrap_or_else(|| "unknown-session".to_string());
 
     let started_at = now_iso();
+    let profile_line = if profile.is_empty() {
+        String::new()
+    } else {
+        format!("- Profile: {}\n", profile)
+    };
     let content = format!(
-        "\n## Invocation — {}\n- Spec-loop Session ID: {}\n- Mode: {}\n- Flags: once={}, max_tasks={}, max_loops={}, skip_review={}\n- Claude Sessions: recorded per phase below and in session.json invocations[].claude_session_ids\n\n",
+        "\n## Invocation — {}\n- Spec-loop Session ID: {}\n- Mode: {}\n{}- Flags: once={}, max_tasks={}, max_loops={}, skip_review={}\n- Claude Sessions: recorded per phase below and in session.json invocations[].claude_session_ids\n\n",
         now_human(),
         session_id,
         mode,
+        profile_line,
         once,
         max_tasks,
         max_loops,
@@ -226,6 +297,10 @@ pub fn append_run_invocation_header(
             max_loops,
             skip_review,
             claude_session_ids: vec![],
+            permission_mode: String::new(),
+            allowed_tools: vec![],
+            review_allowed_tools: vec![],
+            profile: profile.to_string(),
         });
         write_session_json(&json_path, &data)?;
     }
@@ -273,6 +348,7 @@ pub fn register_claude_session(session_path: &Path, claude_session_id: &str) ->
     write_session_json(&json_path, &data)
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn append_run_phase(
     session_path: &Path,
     phase_name: &str,
@@ -315,6 +391,37 @@ pub fn append_run_phase(
     append_text(&run_path, &content)
 }
 
+pub fn append_run_note(session_path: &Path, title: &str, body: &str) -> Result<()> {
+    let run_path = session_runlog_path(session_path);
+    let mut content = format!("### {}\n\n", title);
+    content.push_str(body);
+    if !body.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push('\n');
+    append_text(&run_path, &content)
+}
+
+pub fn record_review(session_path: &Path, review: SessionReview) -> Result<()> {
+    let json_path = session_json_path(session_path);
+    if !json_path.exists() {
+        return Ok(());
+    }
+    let mut data = read_session_json(&json_path)?;
+    data.reviews.push(review);
+    write_session_json(&json_path, &data)
+}
+
+pub fn record_status_change(session_path: &Path, change: SessionStatusChange) -> Result<()> {
+    let json_path = session_json_path(session_path);
+    if !json_path.exists() {
+        return Ok(());
+    }
+    let mut data = read_session_json(&json_path)?;
+    data.history.push(change);
+    write_session_json(&json_path, &data)
+}
+
 pub struct IterationLogInput<'a> {
     pub index: u32,
     pub task_name: &'a str,
@@ -324,6 +431,7 @@ pub struct IterationLogInput<'a> {
     pub must_fix_count: u32,
     pub should_fix_count: u32,
     pub commit_sha: &'a str,
+    pub before_sha: &'a str,
 }
 
 pub fn append_iteration_log(session_path: &Path, input: IterationLogInput<'_>) -> Result<()> {
@@ -365,6 +473,7 @@ pub fn append_iteration_log(session_path: &Path, input: IterationLogInput<'_>) -
             must_fix_count: input.must_fix_count,
             should_fix_count: input.should_fix_count,
             commit: input.commit_sha.to_string(),
+            before_sha: input.before_sha.to_string(),
             timestamp: now_iso(),
         });
         write_session_json(&json_path, &data)?;
@@ -438,6 +547,67 @@ pub fn session_started_epoch(session_path: &Path) -> i64 {
     parse_iso_epoch(&started).unwrap_or_else(|| Utc::now().timestamp())
 }
 
+/// Base commit recorded by the latest session of `spec_dir` on `branch`.
+pub fn session_base_for_spec(session_dir: &Path, spec_dir: &Path, branch: &str) -> Option<String> {
+    let spec = spec_dir.display().to_string();
+    list_session_dirs_desc(session_dir)
+        .into_iter()
+        .filter_map(|dir| read_session_json(&session_json_path(&dir)).ok())
+        .find(|data| data.spec == spec && data.branch == branch && !data.base_sha.is_empty())
+        .map(|data| data.base_sha)
+}
+
+pub fn record_session_base(session_path: &Path, branch: &str, base_sha: &str) -> Result<()> {
+    let json_path = session_json_path(session_path);
+    if !json_path.exists() {
+        return Ok(());
+    }
+    let mut data = read_session_json(&json_path)?;
+    data.branch = branch.to_string();
+    data.base_sha = base_sha.to_string();
+    write_session_json(&json_path, &data)
+}
+
+/// Record HEAD before `task`'s build.
+pub fn record_task_base(session_path: &Path, task: &str, before_sha: &str) -> Result<()> {
+    let json_path = session_json_path(session_path);
+    if !json_path.exists() || before_sha.is_empty() {
+        return Ok(());
+    }
+    let mut data = read_session_json(&json_path)?;
+    data.task_bases
+        .insert(task.to_string(), before_sha.to_string());
+    write_session_json(&json_path, &data)
+}
+
+/// HEAD before `task`'s latest recorded build, if any.
+pub fn task_base(session_path: &Path, task: &str) -> Option<String> {
+    read_session_json(&session_json_path(session_path))
+        .ok()?
+        .task_bases
+        .remove(task)
+}
+
+/// Record the current invocation's permission setup for audit.
+pub fn record_permissions(
+    session_path: &Path,
+    mode: &str,
+    allowed_tools: Vec<String>,
+    review_allowed_tools: Vec<String>,
+) -> Result<()> {
+    let json_path = session_json_path(session_path);
+    if !json_path.exists() {
+        return Ok(());
+    }
+    let mut data = read_session_json(&json_path)?;
+    if let Some(invocation) = data.invocations.last_mut() {
+        invocation.permission_mode = mode.to_string();
+        invocation.allowed_tools = allowed_tools;
+        invocation.review_allowed_tools = review_allowed_tools;
+    }
+    write_session_json(&json_path, &data)
+}
+
 pub fn session_latest_for_spec(session_dir: &Path, spec_dir: &Path) -> Option<PathBuf> {
     let spec = spec_dir.display().to_string();
     for dir in list_session_dirs_desc(session_dir) {
@@ -26,6 +26,34 @@ impl TaskStatus {
         }
     }
 
+    /// Statuses a task may be set to by hand.
+    pub const SETTABLE: [TaskStatus; 5] = [
+        Self::Pending,
+        Self::InProgress,
+        Self::InReview,
+        Self::Done,
+        Self::Blocked,
+    ];
+
+    /// Whether the task lifecycle allows moving from `self` to `to`.
+    ///
+    /// pending -> in-progress -> in-review -> done. Any task may become
+    /// blocked or be reset to pending, and in-review may go back to
+    /// in-progress. A task with an unreadable status may be set to anything.
+    pub fn can_transition(self, to: TaskStatus) -> bool {
+        use TaskStatus::*;
+        match (self, to) {
+            (_, Unknown) => false,
+            (from, to) if from == to => false,
+            (Unknown, _) => true,
+            (_, Blocked | Pending) => true,
+            (Pending, InProgress) => true,
+            (InProgress, InReview) => true,
+            (InReview, InProgress | Done) => true,
+            _ => false,
+        }
+    }
+
     pub fn as_str(self) -> &'static str {
         match self {
             Self::Pending => "pending",
@@ -63,6 +91,34 @@ pub fn get_spec_name(spec_dir: &Path) -> String {
         .unwrap_or_else(|| "unknown-spec".to_string())
 }
 
+/// Title from spec.md's first heading, without a leading `Spec:`.
+pub fn get_spec_title(spec_dir: &Path) -> String {
+    let content = fs::read_to_string(spec_dir.join("spec.md")).unwrap_or_default();
+    content
+        .lines()
+        .find_map(|l| l.strip_prefix("# "))
+        .map(|t| t.trim().trim_start_matches("Spec:").trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| get_spec_name(spec_dir))
+}
+
+/// Body of spec.md's `## <heading>` section, trimmed.
+pub fn get_spec_section(spec_dir: &Path, heading: &str) -> String {
+    let content = fs::read_to_string(spec_dir.join("spec.md")).unwrap_or_default();
+    let mut body = vec![];
+    let mut inside = false;
+    for line in content.lines() {
+        if let Some(h) = line.strip_prefix("## ") {
+            inside = h.trim().eq_ignore_ascii_case(heading);
+            continue;
+        }
+        if inside {
+            body.push(line);
+        }
+    }
+    body.join("\n").trim().to_string()
+}
+
 pub fn list_task_files(spec_dir: &Path) -> Vec<PathBuf> {
     let mut files = vec![];
     let tasks_dir = spec_dir.join("tasks");
@@ -109,7 +165,88 @@ pub fn get_task_name(task_file: &Path) -> String {
         .unwrap_or_else(|| task_file.display().to_string())
 }
 
+/// Paths listed under the task's `## Files` section, e.g. ``- `src/a.rs` — create``.
+pub fn get_task_files(task_file: &Path) -> Vec<String> {
+    let Ok(content) = fs::read_to_string(task_file) else {
+        return vec![];
+    };
+    let mut files = vec![];
+    let mut in_files = false;
+    for line in content.lines() {
+        if let Some(heading) = line.strip_prefix("## ") {
+            in_files = heading.trim().eq_ignore_ascii_case("files");
+            continue;
+        }
+        if !in_files {
+            continue;
+        }
+        let Some(item) = line.trim().strip_prefix("- ") else {
+            continue;
+        };
+        let path = match item.split('`').nth(1) {
+            Some(quoted) => quoted.trim(),
+            None => item
+                .split(" — ")
+                .next()
+                .unwrap_or_default()
+                .split(" - ")
+                .next()
+                .unwrap_or_default()
+                .trim(),
+        };
+        let path = path.trim_start_matches("./");
+        if !path.is_empty() && !path.contains(' ') {
+            files.push(path.to_string());
+        }
+    }
+    files
+}
+
+/// Move a task to `new_status`, refusing transitions the lifecycle does not
+/// allow. Setting the current status again is a no-op.
 pub fn set_task_status(task_file: &Path, new_status: TaskStatus) -> Result<()> {
+    let current = get_task_status(task_file);
+    if current == new_status {
+        return Ok(());
+    }
+    if !current.can_transition(new_status) {
+        return Err(anyhow!(
+            "illegal status change for {}: {} -> {}",
+            task_file.display(),
+            current.as_str(),
+            new_status.as_str()
+        ));
+    }
+    write_task_status(task_file, new_status)
+}
+
+/// Append a `> History:` line to the task's header block, after the last
+/// header line that follows `> Status:`.
+pub fn append_task_history(task_file: &Path, entry: &str) -> Result<()> {
+    let content = fs::read_to_string(task_file)
+        .with_context(|| format!("failed to read {}", task_file.display()))?;
+    let status_re = Regex::new(r"^\s*>?\s*Status:").expect("valid regex");
+    let mut lines: Vec<&str> = content.lines().collect();
+    let line = format!("> History: {}", entry);
+    let at = match lines.iter().position(|l| status_re.is_match(l)) {
+        Some(start) => {
+            let mut end = start + 1;
+            while end < lines.len() && lines[end].trim_start().starts_with('>') {
+                end += 1;
+            }
+            end
+        }
+        None => lines.len(),
+    };
+    lines.insert(at, &line);
+    let mut out = lines.join("\n");
+    out.push('\n');
+    fs::write(task_file, out).with_context(|| format!("failed to write {}", task_file.display()))
+}
+
+/// Write `new_status` without lifecycle checks; used to restore a status the
+/// agent changed illegally and for explicit manual overrides.
+pub fn write_task_status(task_file: &Path, new_status: TaskStatus) -> Result<()> {
     let content = fs::read_to_string(task_file)
         .with_context(|| format!("failed to read {}", task_file.display()))?;
     let re = Regex::new(r"^(\s*>?\s*Status:\s*).*$").expect("valid regex");
@@ -135,6 +272,69 @@ pub fn set_task_status(task_file: &Path, new_status: TaskStatus) -> Result<()> {
     Ok(())
 }
 
+/// Replace the first `> Key: …` line in `content`, keeping everything else.
+pub fn set_field(content: &str, key: &str, value: &str) -> String {
+    let re = Regex::new(&format!(r"^\s*>?\s*{}:", regex::escape(key))).expect("valid regex");
+    let mut replaced = false;
+    let mut out = String::with_capacity(content.len() + 32);
+    for line in content.lines() {
+        if !replaced && re.is_match(line) {
+            out.push_str(&format!("> {}: {}\n", key, value));
+            replaced = true;
+        } else {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Resolve a task by number (`3`, `03`), file name or path.
+pub fn find_task(spec_dir: &Path, ident: &str) -> Result<PathBuf> {
+    let ident = ident.trim();
+    let path = Path::new(ident);
+    if path.is_file() {
+        return Ok(path.to_path_buf());
+    }
+    let tasks = list_task_files(spec_dir);
+    let found = match ident.parse::<u32>() {
+        Ok(n) => tasks.into_iter().find(|t| get_task_number(t) == Some(n)),
+        Err(_) => tasks.into_iter().find(|t| {
+            t.file_name().is_some_and(|f| {
+                let stem = f.to_string_lossy().trim_end_matches(".md").to_string();
+                stem == ident.trim_end_matches(".md")
+                    || stem.trim_start_matches(|c: char| c.is_ascii_digit())
+                        == format!("-{}", ident)
+            })
+        }),
+    };
+    found.ok_or_else(|| anyhow!("no task '{}' in {}", ident, spec_dir.display()))
+}
+
+/// Append an entry to the `## Log` of the spec's progress.md.
+pub fn append_progress(spec_dir: &Path, entry: &str) -> Result<()> {
+    let path = spec_dir.join("progress.md");
+    let mut content = fs::read_to_string(&path)
+        .unwrap_or_else(|_| format!("# Progress: {}\n\n## Log\n", get_spec_name(spec_dir)));
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push('\n');
+    content.push_str(entry.trim_end());
+    content.push('\n');
+    fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Number for a new task: one past the highest existing `NN-` prefix.
+pub fn next_task_number(spec_dir: &Path) -> u32 {
+    list_task_files(spec_dir)
+        .iter()
+        .filter_map(|f| get_task_number(f))
+        .max()
+        .unwrap_or(0)
+        + 1
+}
+
 pub fn count_status(spec_dir: &Path, status: TaskStatus) -> usize {
     list_task_files(spec_dir)
         .into_iter()
@@ -180,25 +380,158 @@ pub fn status_signature(spec_dir: &Path) -> String {
     signature
 }
 
-pub fn find_next_task(spec_dir: &Path) -> Option<PathBuf> {
-    for f in list_task_files(spec_dir) {
-        if get_task_status(&f) == TaskStatus::Pending {
-            return Some(f);
+/// Task number taken from the `NN-` file name prefix.
+pub fn get_task_number(task_file: &Path) -> Option<u32> {
+    let name = task_file.file_name()?.to_string_lossy().to_string();
+    let digits: String = name.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Task numbers listed in the `> Depends on:` line ("none" yields nothing).
+pub fn get_task_depends(task_file: &Path) -> Vec<u32> {
+    let re = Regex::new(r"^\s*>?\s*Depends on:\s*(.*)$").expect("valid regex");
+    let num = Regex::new(r"\d+").expect("valid regex");
+    let Ok(content) = fs::read_to_string(task_file) else {
+        return vec![];
+    };
+    for line in content.lines() {
+        if let Some(caps) = re.captures(line) {
+            let value = caps.get(1).map(|m| m.as_str()).unwrap_or_default();
+            return num
+                .find_iter(value)
+                .filter_map(|m| m.as_str().parse().ok())
+                .collect();
         }
     }
-    None
+    vec![]
 }
 
-pub fn find_open_task(spec_dir: &Path) -> Option<PathBuf> {
-    for f in list_task_files(spec_dir) {
-        if matches!(
-            get_task_status(&f),
-            TaskStatus::Pending | TaskStatus::InProgress | TaskStatus::InReview
-        ) {
-            return Some(f);
+/// Value of the `> Size:` line, e.g. `S`, `M` or `L`.
+pub fn get_task_size(task_file: &Path) -> String {
+    let re = Regex::new(r"^\s*>?\s*Size:\s*(.+?)\s*$").expect("valid regex");
+    let Ok(content) = fs::read_to_string(task_file) else {
+        return String::new();
+    };
+    content
+        .lines()
+        .find_map(|line| re.captures(line))
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().to_string())
+        .unwrap_or_default()
+}
+
+/// Problems with the spec's task files as the harness reads them: file names,
+/// numbering, title, status, size, dependencies, Files and Acceptance.
+pub fn validate_task_files(spec_dir: &Path) -> Vec<String> {
+    let name_re = Regex::new(r"^\d{2}-[a-z0-9][a-z0-9._-]*\.md$").expect("valid regex");
+    let title_re = Regex::new(r"^# Task (\d+):\s*\S").expect("valid regex");
+    let depends_re = Regex::new(r"(?m)^\s*>?\s*Depends on:").expect("valid regex");
+
+    let files = list_task_files(spec_dir);
+    if files.is_empty() {
+        return vec![format!(
+            "no task files in {}",
+            spec_dir.join("tasks").display()
+        )];
+    }
+
+    let mut problems = vec![];
+    let numbers: Vec<u32> = files.iter().filter_map(|f| get_task_number(f)).collect();
+    let mut sorted = numbers.clone();
+    sorted.sort_unstable();
+    sorted.dedup();
+    if sorted.len() != numbers.len() {
+        problems.push("task numbers are not unique".to_string());
+    }
+    if sorted != (1..=sorted.len() as u32).collect::<Vec<_>>() {
+        problems.push("task numbers must run from 01 without gaps".to_string());
+    }
+
+    for file in &files {
+        let name = file
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut problem = |msg: String| problems.push(format!("{}: {}", name, msg));
+        if !name_re.is_match(&name) {
+            problem("file name must look like NN-short-name.md".to_string());
+        }
+        let number = get_task_number(file).unwrap_or_default();
+        let content = fs::read_to_string(file).unwrap_or_default();
+
+        let title = content.lines().find(|l| l.starts_with("# ")).unwrap_or("");
+        match title_re.captures(title) {
+            Some(caps) if caps[1].parse::<u32>().ok() == Some(number) => {}
+            Some(_) => problem(format!("title number does not match {:02}", number)),
+            None => problem("title must be `# Task N: <Short Name>`".to_string()),
+        }
+        if get_task_status(file) != TaskStatus::Pending {
+            problem("status must be pending".to_string());
+        }
+        if !["S", "M", "L"].contains(&get_task_size(file).as_str()) {
+            problem("size must be S, M or L".to_string());
+        }
+        if !depends_re.is_match(&content) {
+            problem("missing `> Depends on:` line".to_string());
+        }
+        for dep in get_task_depends(file) {
+            if !numbers.contains(&dep) {
+                problem(format!("depends on unknown task {}", dep));
+            } else if dep >= number {
+                problem(format!(
+                    "depends on task {}, which is not an earlier task",
+                    dep
+                ));
+            }
+        }
+        if get_task_files(file).is_empty() {
+            problem("`## Files` lists no files".to_string());
+        }
+        if !content.lines().any(|l| l.trim() == "## Acceptance") {
+            problem("missing `## Acceptance` section".to_string());
         }
     }
-    None
+    problems
+}
+
+/// Dependencies that are not `done` yet. Unknown task numbers count as unmet.
+pub fn unmet_dependencies(spec_dir: &Path, task_file: &Path) -> Vec<u32> {
+    let tasks = list_task_files(spec_dir);
+    get_task_depends(task_file)
+        .into_iter()
+        .filter(|dep| {
+            !tasks
+                .iter()
+                .any(|t| get_task_number(t) == Some(*dep) && get_task_status(t) == TaskStatus::Done)
+        })
+        .collect()
+}
+
+pub fn task_statuses(spec_dir: &Path) -> Vec<(PathBuf, TaskStatus)> {
+    list_task_files(spec_dir)
+        .into_iter()
+        .map(|f| {
+            let status = get_task_status(&f);
+            (f, status)
+        })
+        .collect()
+}
+
+/// First pending task whose dependencies are all done.
+pub fn find_next_task(spec_dir: &Path) -> Option<PathBuf> {
+    list_task_files(spec_dir).into_iter().find(|f| {
+        get_task_status(f) == TaskStatus::Pending && unmet_dependencies(spec_dir, f).is_empty()
+    })
+}
+
+pub fn find_open_task(spec_dir: &Path) -> Option<PathBuf> {
+    list_task_files(spec_dir)
+        .into_iter()
+        .find(|f| match get_task_status(f) {
+            TaskStatus::InProgress | TaskStatus::InReview => true,
+            TaskStatus::Pending => unmet_dependencies(spec_dir, f).is_empty(),
+            _ => false,
+        })
 }
 
 pub fn resolve_spec_dir(specs_dir: &Path, explicit_spec: Option<&str>) -> Result<PathBuf> {
@@ -268,3 +601,52 @@ pub fn resolve_spec_dir(specs_dir: &Path, explicit_spec: Option<&str>) -> Result
         "no active specs found (no pending/in-progress/in-review tasks). pass --spec <path>"
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::TaskStatus::*;
+
+    #[test]
+    fn lifecycle_transitions() {
+        let legal = [
+            (Pending, InProgress),
+            (Pending, Blocked),
+            (InProgress, InReview),
+            (InProgress, Pending),
+            (InProgress, Blocked),
+            (InReview, InProgress),
+            (InReview, Done),
+            (InReview, Pending),
+            (InReview, Blocked),
+            (Done, Pending),
+            (Done, Blocked),
+            (Blocked, Pending),
+        ];
+        let illegal = [
+            (Pending, InReview),
+            (Pending, Done),
+            (InProgress, Done),
+            (Done, InProgress),
+            (Done, InReview),
+            (Blocked, InProgress),
+            (Blocked, Done),
+            (Pending, Pending),
+            (Done, Done),
+            (Pending, Unknown),
+        ];
+        for (from, to) in legal {
+            assert!(from.can_transition(to), "{:?} -> {:?}", from, to);
+        }
+        for (from, to) in illegal {
+            assert!(!from.can_transition(to), "{:?} -> {:?}", from, to);
+        }
+    }
+
+    #[test]
+    fn unknown_status_may_become_anything_known() {
+        for to in [Pending, InProgress, InReview, Done, Blocked] {
+            assert!(Unknown.can_transition(to), "unknown -> {:?}", to);
+        }
+        assert!(!Unknown.can_transition(Unknown));
+    }
+}
@@ -0,0 +1,393 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use walkdir::WalkDir;
+
+use crate::spec::{get_task_status, write_task_status};
+use crate::util::{changed_files_since, git, git_with_index, RepoPaths};
+use crate::worktree::{normalize, under, SESSION_TRAILER};
+
+/// Sections of the current task file the agent may edit.
+const EDITABLE_SECTIONS: [&str; 2] = ["Done", "Notes"];
+
+/// Contents of every file in a spec directory at one point in time.
+pub struct SpecSnapshot {
+    dir: PathBuf,
+    files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl SpecSnapshot {
+    pub fn take(dir: &Path) -> Result<Self> {
+        let mut files = vec![];
+        for entry in WalkDir::new(dir).into_iter().flatten() {
+            if entry.file_type().is_file() {
+                let path = entry.into_path();
+                let bytes = fs::read(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                files.push((path, bytes));
+            }
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            files,
+        })
+    }
+
+    fn get(&self, path: &Path) -> Option<&[u8]> {
+        self.files
+            .iter()
+            .find(|(p, _)| p == path)
+            .map(|(_, b)| b.as_slice())
+    }
+
+    /// Put the directory back exactly as it was, removing files added since.
+    pub fn restore(&self) -> Result<()> {
+        for entry in WalkDir::new(&self.dir).into_iter().flatten() {
+            if entry.file_type().is_file() && self.get(entry.path()).is_none() {
+                fs::remove_file(entry.path())
+                    .with_context(|| format!("failed to remove {}", entry.path().display()))?;
+            }
+        }
+        for (path, bytes) in &self.files {
+            write_file(path, bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Revert edits made since the snapshot that the agent is not allowed to
+    /// make, returning a description of each. Allowed: the Status line and
+    /// the Done and Notes sections of `task_file`, appends to progress.md,
+    /// and status or `> History:` lines in any task file (the status
+    /// lifecycle check owns those).
+    pub fn enforce(&self, task_file: Option<&Path>) -> Result<Vec<String>> {
+        let mut reverted = vec![];
+        let progress = self.dir.join("progress.md");
+
+        for (path, old) in &self.files {
+            let Ok(new) = fs::read(path) else {
+                write_file(path, old)?;
+                reverted.push(format!("{} was deleted", path.display()));
+                continue;
+            };
+            if new == *old {
+                continue;
+            }
+            if *path == progress {
+                if !new.starts_with(old) {
+                    write_file(path, old)?;
+                    reverted.push(format!("{} was rewritten, not appended to", path.display()));
+                }
+                continue;
+            }
+            if !is_task_file(path) {
+                write_file(path, old)?;
+                reverted.push(format!("{} was modified", path.display()));
+                continue;
+            }
+
+            let current = task_file == Some(path.as_path());
+            let old_text = String::from_utf8_lossy(old);
+            let new_text = String::from_utf8_lossy(&new);
+            if fixed_part(&old_text, current) == fixed_part(&new_text, current) {
+                continue;
+            }
+            let status = get_task_status(path);
+            let mut restored = old_text.to_string();
+            for line in new_text.lines().filter(|l| is_history(l)) {
+                if !old_text.lines().any(|l| l == line) {
+                    restored = insert_history(&restored, line);
+                }
+            }
+            if current {
+                for heading in EDITABLE_SECTIONS {
+                    if let Some(body) = section_body(&new_text, heading) {
+                        restored = replace_section(&restored, heading, &body);
+                    }
+                }
+            }
+            write_file(path, restored.as_bytes())?;
+            write_task_status(path, status)?;
+            reverted.push(format!(
+                "{} was edited outside {}",
+                path.display(),
+                if current {
+                    "its Status, Done and Notes sections"
+                } else {
+                    "its status"
+                }
+            ));
+        }
+
+        for entry in WalkDir::new(&self.dir).into_iter().flatten() {
+            let path = entry.path();
+            if entry.file_type().is_file() && self.get(path).is_none() && path != progress {
+                fs::remove_file(path)
+                    .with_context(|| format!("failed to remove {}", path.display()))?;
+                reverted.push(format!("{} was added", path.display()));
+            }
+        }
+        Ok(reverted)
+    }
+}
+
+/// Spec files changed in the commits since `base`.
+pub fn committed_spec_files(spec_dir: &Path, base: &str) -> Vec<String> {
+    let Some(repo) = RepoPaths::discover() else {
+        return vec![];
+    };
+    let spec = repo.relative(&spec_dir.to_string_lossy());
+    changed_files_since(base)
+        .into_iter()
+        .filter(|p| under(p, &spec))
+        .collect()
+}
+
+/// Back spec file changes out of the commits since `base` with one new
+/// commit, leaving the working tree and anything else staged as they are.
+pub fn uncommit_spec_files(
+    spec_dir: &Path,
+    base: &str,
+    subject: &str,
+    session_id: &str,
+) -> Result<()> {
+    let spec = normalize(spec_dir);
+    git(&["restore", "--source", base, "--staged", "--", &spec])?;
+    if git(&["diff", "--cached", "--quiet", "HEAD", "--", &spec]).is_ok() {
+        return Ok(());
+    }
+
+    // Build the commit in a scratch index holding HEAD plus the restored
+    // spec files, so nothing else staged is swept into it.
+    let index = git(&["rev-parse", "--git-path", "spec-loop-index"])?;
+    let trailer = format!("{}: {}", SESSION_TRAILER, session_id);
+    let result = git_with_index(&index, &["read-tree", "HEAD"])
+        .and_then(|_| {
+            git_with_index(
+                &index,
+                &["restore", "--source", base, "--staged", "--", &spec],
+            )
+        })
+        .and_then(|_| {
+            git_with_index(
+                &index,
+                &["commit", "--quiet", "-m", subject, "-m", &trailer],
+            )
+        });
+    fs::remove_file(&index).ok();
+    result
+}
+
+fn write_file(path: &Path, bytes: &[u8]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create {}", parent.display()))?;
+    }
+    fs::write(path, bytes).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn is_task_file(path: &Path) -> bool {
+    path.parent()
+        .and_then(Path::file_name)
+        .is_some_and(|d| d == "tasks")
+        && path.extension().is_some_and(|e| e == "md")
+}
+
+fn is_history(line: &str) -> bool {
+    line.trim_start().starts_with("> History:")
+}
+
+/// The task file without the parts the agent may change.
+fn fixed_part(content: &str, current: bool) -> String {
+    let status = Regex::new(r"^\s*>?\s*Status:").expect("valid regex");
+    let mut out = String::new();
+    let mut editable = false;
+    for line in content.lines() {
+        if let Some(h) = line.strip_prefix("## ") {
+            editable = current
+                && EDITABLE_SECTIONS
+                    .iter()
+                    .any(|s| h.trim().eq_ignore_ascii_case(s));
+        } else if editable || status.is_match(line) || is_history(line) {
+            continue;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    out
+}
+
+fn section_body(content: &str, heading: &str) -> Option<String> {
+    let mut body = None;
+    for line in content.lines() {
+        if let Some(h) = line.strip_prefix("## ") {
+            if body.is_some() {
+                break;
+            }
+            if h.trim().eq_ignore_ascii_case(heading) {
+                body = Some(String::new());
+            }
+            continue;
+        }
+        if let Some(b) = body.as_mut() {
+            b.push_str(line);
+            b.push('\n');
+        }
+    }
+    body
+}
+
+fn replace_section(content: &str, heading: &str, body: &str) -> String {
+    let mut out = String::new();
+    let mut skipping = false;
+    for line in content.lines() {
+        if let Some(h) = line.strip_prefix("## ") {
+            skipping = false;
+            out.push_str(line);
+            out.push('\n');
+            if h.trim().eq_ignore_ascii_case(heading) {
+                out.push_str(body);
+                skipping = true;
+            }
+            continue;
+        }
+        if !skipping {
+            out.push_str(line);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Insert a `> History:` line after the existing header block.
+fn insert_history(content: &str, line: &str) -> String {
+    let mut lines: Vec<&str> = content.lines().collect();
+    let at = lines
+        .iter()
+        .rposition(|l| l.trim_start().starts_with('>'))
+        .map_or(lines.len(), |i| i + 1);
+    lines.insert(at, line);
+    let mut out = lines.join("\n");
+    out.push('\n');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+    use crate::spec::TaskStatus;
+    use crate::util::testing::TestRepo;
+
+    const TASK: &str = "# Task 1: Alpha\n\n> Status: in-progress\n\n## Files\n\n- `src/a.rs`\n\n## Done\n\n## Notes\n";
+    const OTHER: &str =
+        "# Task 2: Beta\n\n> Status: pending\n\n## Files\n\n- `src/b.rs`\n\n## Done\n\n## Notes\n";
+
+    /// A spec directory with two tasks and the paths of both task files.
+    fn spec(name: &str) -> (PathBuf, PathBuf, PathBuf) {
+        let dir = env::temp_dir().join(format!("spec-loop-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let task = dir.join("tasks/01-alpha.md");
+        let other = dir.join("tasks/02-beta.md");
+        write_file(&dir.join("spec.md"), b"# Spec: Demo\n").unwrap();
+        write_file(&task, TASK.as_bytes()).unwrap();
+        write_file(&other, OTHER.as_bytes()).unwrap();
+        (dir, task, other)
+    }
+
+    fn read(path: &Path) -> String {
+        fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn current_task_may_edit_status_done_and_notes() {
+        let (dir, task, _) = spec("guard-current");
+        let snapshot = SpecSnapshot::take(&dir).unwrap();
+        let edited = TASK
+            .replace("in-progress", "in-review")
+            .replace("## Done\n", "## Done\n\n- Added src/a.rs\n");
+        fs::write(&task, &edited).unwrap();
+
+        assert!(snapshot.enforce(Some(&task)).unwrap().is_empty());
+        assert_eq!(read(&task), edited);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn edits_to_other_tasks_are_reverted_but_their_status_kept() {
+        let (dir, task, other) = spec("guard-other");
+        let snapshot = SpecSnapshot::take(&dir).unwrap();
+        fs::write(
+            &other,
+            OTHER
+                .replace("pending", "blocked")
+                .replace("src/b.rs", "src/c.rs")
+                .replace("## Notes\n", "## Notes\n\nNot mine.\n"),
+        )
+        .unwrap();
+        fs::write(dir.join("spec.md"), "# Spec: Demo\n\nMore scope.\n").unwrap();
+
+        let reverted = snapshot.enforce(Some(&task)).unwrap();
+        assert_eq!(reverted.len(), 2, "{:?}", reverted);
+        assert_eq!(read(&other), OTHER.replace("pending", "blocked"));
+        assert_eq!(get_task_status(&other), TaskStatus::Blocked);
+        assert_eq!(read(&dir.join("spec.md")), "# Spec: Demo\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_new_progress_file_is_kept_and_other_new_files_removed() {
+        let (dir, task, _) = spec("guard-progress");
+        let snapshot = SpecSnapshot::take(&dir).unwrap();
+        fs::write(dir.join("progress.md"), "# Progress\n\n- Task 1 built\n").unwrap();
+        fs::write(dir.join("tasks/03-extra.md"), OTHER).unwrap();
+
+        let reverted = snapshot.enforce(Some(&task)).unwrap();
+        assert_eq!(reverted.len(), 1, "{:?}", reverted);
+        assert!(reverted[0].ends_with("03-extra.md was added"));
+        assert_eq!(
+            read(&dir.join("progress.md")),
+            "# Progress\n\n- Task 1 built\n"
+        );
+        assert!(!dir.join("tasks/03-extra.md").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn committed_spec_edits_are_backed_out() {
+        let repo = TestRepo::new("guard-uncommit");
+        repo.write("specs/demo/spec.md", "# Spec: Demo\n");
+        let base = repo.commit("setup");
+        repo.write("specs/demo/spec.md", "# Spec: Demo\n\nMore scope.\n");
+        repo.write("src/a.rs", "a\n");
+        repo.commit("build");
+        repo.write("notes.md", "staged by the agent\n");
+        git(&["add", "notes.md"]).unwrap();
+
+        let spec_dir = Path::new("specs/demo");
+        assert_eq!(
+            committed_spec_files(spec_dir, &base),
+            ["specs/demo/spec.md"]
+        );
+        uncommit_spec_files(spec_dir, &base, "Back out spec edits", "s1").unwrap();
+
+        assert!(committed_spec_files(spec_dir, &base).is_empty());
+        assert_eq!(
+            git(&["show", "--name-only", "--format=", "HEAD"]).unwrap(),
+            "specs/demo/spec.md"
+        );
+        assert_eq!(changed_files_since(&base), ["src/a.rs"]);
+        assert!(git(&["log", "-1", "--format=%B"])
+            .unwrap()
+            .contains("Spec-Loop-Session: s1"));
+        // The edit stays in the working tree; the agent's staging is untouched.
+        assert_eq!(
+            repo.read("specs/demo/spec.md"),
+            "# Spec: Demo\n\nMore scope.\n"
+        );
+        let status = git(&["status", "--porcelain"]).unwrap();
+        assert_eq!(status, "A  notes.md\n M specs/demo/spec.md");
+    }
+}
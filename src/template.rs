@@ -0,0 +1,230 @@
+use std::collections::HashMap;
+
+use anyhow::{anyhow, bail, Result};
+
+/// Variables available to a template. A variable is truthy when non-empty.
+pub type Vars = HashMap<&'static str, String>;
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Var(String),
+    If(String),
+    Else,
+    EndIf,
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Var(String),
+    If {
+        name: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// Render `{{name}}` substitutions and `{{#if name}}…{{else}}…{{/if}}` blocks.
+///
+/// Block tags that sit alone on a line are dropped together with that line,
+/// so templates can put them on their own lines without leaving blank gaps.
+pub fn render(template: &str, vars: &Vars) -> Result<String> {
+    let tokens = tokenize(template)?;
+    let mut iter = tokens.into_iter();
+    let (nodes, terminator) = parse(&mut iter)?;
+    if let Some(tok) = terminator {
+        bail!("unexpected {} without matching {{{{#if}}}}", describe(&tok));
+    }
+    let mut out = String::with_capacity(template.len());
+    render_nodes(&nodes, vars, &mut out)?;
+    Ok(out)
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut rest = src;
+    // Whether everything emitted since the last newline is whitespace.
+    let mut line_clean = true;
+
+    while let Some(start) = rest.find("{{") {
+        let mut text = rest[..start].to_string();
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| anyhow!("unclosed template tag near `{{{{{}`", preview(after)))?;
+        let tag = after[..end].trim();
+        let mut remainder = &after[end + 2..];
+
+        line_clean = match text.rfind('\n') {
+            Some(pos) => text[pos + 1..].trim().is_empty(),
+            None => line_clean && text.trim().is_empty(),
+        };
+
+        let token = if let Some(name) = tag.strip_prefix("#if ") {
+            Token::If(parse_name(name)?)
+        } else if tag == "else" {
+            Token::Else
+        } else if tag == "/if" {
+            Token::EndIf
+        } else if tag.starts_with('#') || tag.starts_with('/') {
+            bail!("unsupported template tag `{{{{{}}}}}`", tag);
+        } else {
+            Token::Var(parse_name(tag)?)
+        };
+
+        if !matches!(token, Token::Var(_)) {
+            let line_end = remainder.find('\n');
+            let trailing = &remainder[..line_end.unwrap_or(remainder.len())];
+            if line_clean && trailing.trim().is_empty() {
+                let keep = text.rfind('\n').map(|pos| pos + 1).unwrap_or(0);
+                text.truncate(keep);
+                remainder = match line_end {
+                    Some(pos) => &remainder[pos + 1..],
+                    None => "",
+                };
+            }
+        } else {
+            line_clean = false;
+        }
+
+        if !text.is_empty() {
+            tokens.push(Token::Text(text));
+        }
+        tokens.push(token);
+        rest = remainder;
+    }
+
+    if !rest.is_empty() {
+        tokens.push(Token::Text(rest.to_string()));
+    }
+    Ok(tokens)
+}
+
+fn parse_name(raw: &str) -> Result<String> {
+    let name = raw.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        bail!("invalid template variable name `{}`", name);
+    }
+    Ok(name.to_string())
+}
+
+fn parse(iter: &mut impl Iterator<Item = Token>) -> Result<(Vec<Node>, Option<Token>)> {
+    let mut nodes = vec![];
+    while let Some(tok) = iter.next() {
+        match tok {
+            Token::Text(t) => nodes.push(Node::Text(t)),
+            Token::Var(v) => nodes.push(Node::Var(v)),
+            Token::If(name) => {
+                let (then, term) = parse(iter)?;
+                let otherwise = match term {
+                    Some(Token::EndIf) => vec![],
+                    Some(Token::Else) => match parse(iter)? {
+                        (nodes, Some(Token::EndIf)) => nodes,
+                        _ => bail!("`{{{{#if {}}}}}` is missing `{{{{/if}}}}`", name),
+                    },
+                    _ => bail!("`{{{{#if {}}}}}` is missing `{{{{/if}}}}`", name),
+                };
+                nodes.push(Node::If {
+                    name,
+                    then,
+                    otherwise,
+                });
+            }
+            Token::Else | Token::EndIf => return Ok((nodes, Some(tok))),
+        }
+    }
+    Ok((nodes, None))
+}
+
+fn render_nodes(nodes: &[Node], vars: &Vars, out: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(t) => out.push_str(t),
+            Node::Var(name) => out.push_str(lookup(vars, name)?),
+            Node::If {
+                name,
+                then,
+                otherwise,
+            } => {
+                if lookup(vars, name)?.is_empty() {
+                    render_nodes(otherwise, vars, out)?;
+                } else {
+                    render_nodes(then, vars, out)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn lookup<'a>(vars: &'a Vars, name: &str) -> Result<&'a str> {
+    vars.get(name)
+        .map(String::as_str)
+        .ok_or_else(|| anyhow!("unknown template variable `{}`", name))
+}
+
+fn describe(tok: &Token) -> &'static str {
+    match tok {
+        Token::Else => "`{{else}}`",
+        _ => "`{{/if}}`",
+    }
+}
+
+fn preview(s: &str) -> String {
+    s.chars().take(20).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(pairs: &[(&'static str, &str)]) -> Vars {
+        pairs.iter().map(|(k, v)| (*k, v.to_string())).collect()
+    }
+
+    #[test]
+    fn nested_if_and_else() {
+        let template = "{{#if a}}A{{#if b}}B{{else}}!B{{/if}}{{else}}!A{{#if b}}B{{/if}}{{/if}}";
+        let cases = [
+            ("x", "x", "AB"),
+            ("x", "", "A!B"),
+            ("", "x", "!AB"),
+            ("", "", "!A"),
+        ];
+        for (a, b, expected) in cases {
+            let out = render(template, &vars(&[("a", a), ("b", b)])).unwrap();
+            assert_eq!(out, expected, "a={:?} b={:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn block_tags_on_their_own_lines_leave_no_gaps() {
+        let template = "one\n{{#if a}}\ntwo {{a}}\n{{else}}\nnone\n{{/if}}\nthree\n";
+        let out = render(template, &vars(&[("a", "2")])).unwrap();
+        assert_eq!(out, "one\ntwo 2\nthree\n");
+        let out = render(template, &vars(&[("a", "")])).unwrap();
+        assert_eq!(out, "one\nnone\nthree\n");
+    }
+
+    #[test]
+    fn unknown_variables_are_errors() {
+        let err = render("{{missing}}", &Vars::new()).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("unknown template variable `missing`"));
+        // Also in a branch that is not taken.
+        assert!(render("{{#if a}}{{missing}}{{/if}}", &vars(&[("a", "")])).is_ok());
+        assert!(render("{{#if missing}}x{{/if}}", &Vars::new()).is_err());
+    }
+
+    #[test]
+    fn unbalanced_blocks_are_errors() {
+        let a = vars(&[("a", "x")]);
+        assert!(render("{{#if a}}x", &a).is_err());
+        assert!(render("x{{/if}}", &a).is_err());
+        assert!(render("{{else}}", &a).is_err());
+        assert!(render("{{#each a}}{{/each}}", &a).is_err());
+        assert!(render("{{a", &a).is_err());
+    }
+}
@@ -1,9 +1,16 @@
 use std::env;
 use std::io::{self, IsTerminal};
 
+use serde_json::{Map, Value};
+
+use crate::cli::OutputMode;
+use crate::util::now_iso;
+
 pub struct Ui {
+    mode: OutputMode,
     color: bool,
     unicode: bool,
+    interactive: bool,
 }
 
 #[derive(Clone, Copy)]
@@ -25,16 +32,64 @@ struct Symbols {
 }
 
 impl Ui {
-    pub fn new() -> Self {
-        let color = io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none();
+    pub fn new(mode: OutputMode) -> Self {
+        let human = mode == OutputMode::Human;
+        let interactive = human && io::stdout().is_terminal();
+        let color = interactive && env::var_os("NO_COLOR").is_none();
         let locale = env::var("LC_ALL")
             .ok()
             .or_else(|| env::var("LC_CTYPE").ok())
             .or_else(|| env::var("LANG").ok())
             .unwrap_or_default()
             .to_lowercase();
-        let unicode = locale.contains("utf-8") || locale.contains("utf8");
-        Self { color, unicode }
+        let unicode = human && (locale.contains("utf-8") || locale.contains("utf8"));
+        Self {
+            mode,
+            color,
+            unicode,
+            interactive,
+        }
+    }
+
+    /// True when a live spinner may redraw the terminal.
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    pub fn is_jsonl(&self) -> bool {
+        self.mode == OutputMode::Jsonl
+    }
+
+    /// Emit a structured event. Only the jsonl renderer prints anything.
+    pub fn emit(&self, event: &str, fields: Value) {
+        if !self.is_jsonl() {
+            return;
+        }
+        let mut obj = Map::new();
+        obj.insert("event".to_string(), Value::from(event));
+        obj.insert("ts".to_string(), Value::from(now_iso()));
+        if let Value::Object(extra) = fields {
+            obj.extend(extra);
+        }
+        println!("{}", Value::Object(obj));
+    }
+
+    /// Print a line of human output; suppressed in jsonl mode.
+    pub fn line(&self, text: &str) {
+        if !self.is_jsonl() {
+            println!("{text}");
+        }
+    }
+
+    pub fn blank(&self) {
+        self.line("");
+    }
+
+    fn log(&self, level: &str, message: &str) {
+        self.emit(
+            "log",
+            serde_json::json!({ "level": level, "message": message }),
+        );
     }
 
     fn symbols(&self) -> Symbols {
@@ -92,12 +147,12 @@ impl Ui {
     }
 
     pub fn print_header(&self, version: &str) {
-        println!();
-        println!(
+        self.blank();
+        self.line(&format!(
             "  {} {}",
             self.bold("spec-loop"),
             self.dim(&format!("v{}", version))
-        );
+        ));
     }
 
     pub fn separator(&self, label: &str, width: usize) {
@@ -105,46 +160,54 @@ impl Ui {
         let base = format!("{}{} {} ", s.box_h, s.box_h, label);
         let rem = width.saturating_sub(label.len() + 5);
         let pad = s.box_h.repeat(rem);
-        println!();
-        println!("  {}", self.dim(&format!("{}{}", base, pad)));
+        self.blank();
+        self.line(&format!("  {}", self.dim(&format!("{}{}", base, pad))));
     }
 
     pub fn phase(&self, label: &str) {
         let s = self.symbols();
-        println!();
-        println!("  {} {}", self.style("34", s.phase), self.bold(label));
+        self.blank();
+        self.line(&format!(
+            "  {} {}",
+            self.style("34", s.phase),
+            self.bold(label)
+        ));
     }
 
     pub fn step_info(&self, message: &str) {
         let s = self.symbols();
-        println!("      {} {}", self.style("34", s.arrow), message);
+        self.log("info", message);
+        self.line(&format!("      {} {}", self.style("34", s.arrow), message));
     }
 
     pub fn step_ok(&self, message: &str) {
         let s = self.symbols();
-        println!("      {} {}", self.style("32", s.ok), message);
+        self.log("ok", message);
+        self.line(&format!("      {} {}", self.style("32", s.ok), message));
     }
 
     pub fn step_warn(&self, message: &str) {
         let s = self.symbols();
-        println!("      {} {}", self.style("33", s.warn), message);
+        self.log("warn", message);
+        self.line(&format!("      {} {}", self.style("33", s.warn), message));
     }
 
     pub fn step_error(&self, message: &str) {
         let s = self.symbols();
+        self.log("error", message);
         eprintln!("      {} {}", self.style("31", s.fail), message);
     }
 
     pub fn task_complete(&self, task_name: &str, remaining: usize) {
         let s = self.symbols();
-        println!();
-        println!(
+        self.blank();
+        self.line(&format!(
             "  {} {} {} {} remaining",
             self.style("32", s.ok),
             self.bold(task_name),
             self.dim(s.arrow),
             self.dim(&remaining.to_string())
-        );
+        ));
     }
 
     pub fn box_header(&self, label: &str, width: usize) {
@@ -152,14 +215,14 @@ impl Ui {
         let inner = width.saturating_sub(2);
         let rem = inner.saturating_sub(label.len() + 3);
         let pad = s.box_h.repeat(rem);
-        println!();
-        println!(
+        self.blank();
+        self.line(&format!(
             "  {}",
             self.style(
                 "36",
                 &format!("{}{} {} {}{}", s.box_tl, s.box_h, label, pad, s.box_tr)
             )
-        );
+        ));
     }
 
     pub fn box_line(&self, content: &str, width: usize) {
@@ -172,12 +235,13 @@ impl Ui {
         } else {
             String::new()
         };
-        println!(
-            "  {}{}{}",
+        self.line(&format!(
+            "  {}{}{}{}",
             self.style("36", s.box_v),
-            format!("{}{}", content, pad),
+            content,
+            pad,
             self.style("36", s.box_v)
-        );
+        ));
     }
 
     pub fn box_empty(&self, width: usize) {
@@ -188,10 +252,10 @@ impl Ui {
         let s = self.symbols();
         let inner = width.saturating_sub(2);
         let line = s.box_h.repeat(inner);
-        println!(
+        self.line(&format!(
             "  {}",
             self.style("36", &format!("{}{}{}", s.box_bl, line, s.box_br))
-        );
+        ));
     }
 
     pub fn progress_bar(&self, current: usize, total: usize, width: usize, label: &str) -> String {
@@ -224,6 +288,18 @@ impl Ui {
     pub fn arrow(&self) -> &'static str {
         self.symbols().arrow
     }
+
+    pub fn check(&self) -> &'static str {
+        self.symbols().ok
+    }
+
+    pub fn ellipsis(&self) -> &'static str {
+        if self.unicode {
+            "…"
+        } else {
+            "..."
+        }
+    }
 }
 
 fn truncate_to_width(input: &str, max_width: usize, unicode: bool) -> String {
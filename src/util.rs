@@ -1,3 +1,4 @@
+use anyhow::{bail, Context, Result};
 use chrono::Utc;
 use regex::Regex;
 use std::env;
@@ -12,6 +13,10 @@ pub fn now_human() -> String {
     chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
 }
 
+pub fn today() -> String {
+    chrono::Local::now().format("%Y-%m-%d").to_string()
+}
+
 pub fn now_stamp() -> String {
     chrono::Local::now().format("%Y%m%d_%H%M%S").to_string()
 }
@@ -61,6 +66,136 @@ pub fn head_sha() -> String {
     }
 }
 
+/// Files changed in `base..HEAD`, relative to the repository root.
+pub fn changed_files_since(base: &str) -> Vec<String> {
+    match Command::new("git")
+        .args(["diff", "--name-only", "-z", &format!("{}..HEAD", base)])
+        .output()
+    {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout)
+            .split('\0')
+            .filter(|l| !l.is_empty())
+            .map(ToOwned::to_owned)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// `s` quoted for a POSIX shell; plain words are left as they are.
+pub fn shell_quote(s: &str) -> String {
+    let plain = |c: char| c.is_ascii_alphanumeric() || "-_./@%+=:,".contains(c);
+    if !s.is_empty() && s.chars().all(plain) {
+        return s.to_string();
+    }
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+/// Run git with `args`, returning trimmed stdout or git's error output.
+pub fn git(args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+/// Run git against the index file `index` instead of the repository's.
+pub fn git_with_index(index: &str, args: &[&str]) -> Result<()> {
+    let out = Command::new("git")
+        .env("GIT_INDEX_FILE", index)
+        .args(args)
+        .output()
+        .context("failed to run git")?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Paths with uncommitted changes, untracked files included.
+pub fn dirty_paths() -> Vec<String> {
+    match Command::new("git")
+        .args(["status", "--porcelain", "-z", "--untracked-files=all"])
+        .output()
+    {
+        Ok(out) if out.status.success() => parse_status_z(&String::from_utf8_lossy(&out.stdout)),
+        _ => vec![],
+    }
+}
+
+/// Paths in `git status --porcelain -z` output. A rename lists its
+/// destination and its source; a copy only its destination.
+fn parse_status_z(out: &str) -> Vec<String> {
+    let mut paths = vec![];
+    let mut entries = out.split('\0');
+    while let Some(entry) = entries.next() {
+        if entry.len() < 4 {
+            continue;
+        }
+        let (status, path) = entry.split_at(3);
+        paths.push(path.to_string());
+        if status.contains(['R', 'C']) {
+            if let Some(source) = entries.next().filter(|_| status.contains('R')) {
+                paths.push(source.to_string());
+            }
+        }
+    }
+    paths
+}
+
+/// Maps paths given relative to the current directory, or absolute, onto
+/// the repository-root-relative form git reports them in.
+pub struct RepoPaths {
+    top: PathBuf,
+    cwd: PathBuf,
+}
+
+impl RepoPaths {
+    pub fn discover() -> Option<Self> {
+        let top = git(&["rev-parse", "--show-toplevel"]).ok()?;
+        Some(Self {
+            top: Path::new(&top).canonicalize().ok()?,
+            cwd: env::current_dir().ok()?.canonicalize().ok()?,
+        })
+    }
+
+    /// `path` relative to the repository root; paths outside it are
+    /// returned as given.
+    pub fn relative(&self, path: &str) -> String {
+        let given = Path::new(path);
+        let joined = if given.is_absolute() {
+            given.canonicalize().unwrap_or_else(|_| given.to_path_buf())
+        } else {
+            self.cwd.join(given)
+        };
+        let mut out = PathBuf::new();
+        for part in joined.components() {
+            match part {
+                std::path::Component::CurDir => {}
+                std::path::Component::ParentDir => {
+                    out.pop();
+                }
+                other => out.push(other),
+            }
+        }
+        match out.strip_prefix(&self.top) {
+            Ok(rel) => rel.to_string_lossy().replace('\\', "/"),
+            Err(_) => path.to_string(),
+        }
+    }
+}
+
 pub fn command_exists(cmd: &str) -> bool {
     if cmd.contains('/') {
         Path::new(cmd).exists()
@@ -76,3 +211,122 @@ pub fn command_exists(cmd: &str) -> bool {
             .unwrap_or(false)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::testing::TestRepo;
+    use super::*;
+
+    #[test]
+    fn shell_quoting() {
+        assert_eq!(shell_quote("packages/api-v2"), "packages/api-v2");
+        assert_eq!(shell_quote("my app"), "'my app'");
+        assert_eq!(shell_quote("a;rm -rf ~"), "'a;rm -rf ~'");
+        assert_eq!(shell_quote("it's"), r"'it'\''s'");
+        assert_eq!(shell_quote(""), "''");
+    }
+
+    #[test]
+    fn status_entries_are_split_on_nul() {
+        let out = " M src/a b.rs\0R  src/new.rs\0src/old.rs\0C  src/copy.rs\0src/a b.rs\0?? docs/caf\u{e9}.md\0";
+        assert_eq!(
+            parse_status_z(out),
+            [
+                "src/a b.rs",
+                "src/new.rs",
+                "src/old.rs",
+                "src/copy.rs",
+                "docs/caf\u{e9}.md"
+            ]
+        );
+        assert!(parse_status_z("").is_empty());
+    }
+
+    #[test]
+    fn dirty_paths_are_not_quoted() {
+        let repo = TestRepo::new("dirty-paths");
+        repo.write("src/old name.rs", "old\n");
+        repo.commit("setup");
+        git(&["mv", "src/old name.rs", "src/new.rs"]).unwrap();
+        repo.write("docs/caf\u{e9} notes.md", "notes\n");
+        let mut paths = dirty_paths();
+        paths.sort();
+        assert_eq!(
+            paths,
+            ["docs/caf\u{e9} notes.md", "src/new.rs", "src/old name.rs"]
+        );
+    }
+}
+
+/// Scratch git repositories for tests of code that runs git in the current
+/// directory.
+#[cfg(test)]
+pub mod testing {
+    use std::env;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Mutex, MutexGuard};
+
+    use super::git;
+
+    /// Serializes tests that change the current directory.
+    static CWD: Mutex<()> = Mutex::new(());
+
+    /// A fresh repository with one empty commit; it is the current directory
+    /// until dropped.
+    pub struct TestRepo {
+        pub dir: PathBuf,
+        previous: PathBuf,
+        _cwd: MutexGuard<'static, ()>,
+    }
+
+    impl TestRepo {
+        pub fn new(name: &str) -> Self {
+            let guard = CWD.lock().unwrap_or_else(|e| e.into_inner());
+            let dir = env::temp_dir().join(format!("spec-loop-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            let previous = env::current_dir().unwrap();
+            env::set_current_dir(&dir).unwrap();
+            for args in [
+                &["init", "-q"][..],
+                &["config", "user.name", "test"],
+                &["config", "user.email", "test@example.com"],
+                &["commit", "-q", "--allow-empty", "-m", "init"],
+            ] {
+                git(args).unwrap();
+            }
+            Self {
+                dir,
+                previous,
+                _cwd: guard,
+            }
+        }
+
+        pub fn write(&self, path: &str, content: &str) {
+            let path = Path::new(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            fs::write(path, content).unwrap();
+        }
+
+        pub fn read(&self, path: &str) -> String {
+            fs::read_to_string(path).unwrap_or_default()
+        }
+
+        /// Commit everything and return the new HEAD.
+        pub fn commit(&self, message: &str) -> String {
+            git(&["add", "-A"]).unwrap();
+            git(&["commit", "-q", "-m", message]).unwrap();
+            git(&["rev-parse", "HEAD"]).unwrap()
+        }
+    }
+
+    impl Drop for TestRepo {
+        fn drop(&mut self) {
+            let _ = env::set_current_dir(&self.previous);
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
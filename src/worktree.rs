@@ -0,0 +1,199 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::config::Config;
+use crate::spec::{get_spec_name, get_task_name, get_task_number};
+use crate::template::{render, Vars};
+use crate::util::{dirty_paths, git, git_with_index, head_sha, slugify, RepoPaths};
+
+pub const BRANCH_STRATEGIES: [&str; 2] = ["current", "per-spec"];
+pub const DIRTY_TREE_POLICIES: [&str; 3] = ["warn", "refuse", "stash"];
+pub const BRANCH_POLICIES: [&str; 3] = ["warn", "refuse", "branch"];
+pub const IN_PROGRESS_POLICIES: [&str; 2] = ["warn", "refuse"];
+pub const COMMIT_POLICIES: [&str; 3] = ["off", "commit", "fail"];
+/// Trailer key linking commits to a spec-loop session.
+pub const SESSION_TRAILER: &str = "Spec-Loop-Session";
+
+pub fn is_git_repo() -> bool {
+    git(&["rev-parse", "--is-inside-work-tree"]).is_ok_and(|out| out == "true")
+}
+
+/// Changed paths outside the spec directory and the session directory,
+/// i.e. changes spec-loop did not make.
+pub fn unrelated_changes(spec_dir: &Path, session_dir: &Path) -> Vec<String> {
+    let Some(repo) = RepoPaths::discover() else {
+        return vec![];
+    };
+    let spec = repo.relative(&spec_dir.to_string_lossy());
+    let sessions = repo.relative(&normalize(session_dir));
+    dirty_paths()
+        .into_iter()
+        .filter(|p| !under(p, &spec) && !under(p, &sessions))
+        .collect()
+}
+
+/// Name of a merge, rebase, cherry-pick or revert that is still in progress.
+pub fn operation_in_progress() -> Option<&'static str> {
+    [
+        ("MERGE_HEAD", "merge"),
+        ("rebase-merge", "rebase"),
+        ("rebase-apply", "rebase"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+    ]
+    .into_iter()
+    .find(|(marker, _)| {
+        git(&["rev-parse", "--git-path", marker]).is_ok_and(|p| Path::new(&p).exists())
+    })
+    .map(|(_, name)| name)
+}
+
+/// Feature branch spec-loop creates for a spec.
+pub fn spec_branch(spec_name: &str) -> String {
+    format!("spec/{}", slugify(spec_name))
+}
+
+/// Switch to `branch`, creating it at HEAD when it does not exist yet.
+/// Uncommitted changes are carried over. Returns whether it was created.
+pub fn switch_branch(branch: &str) -> Result<bool> {
+    let exists = git(&[
+        "rev-parse",
+        "--verify",
+        "--quiet",
+        &format!("refs/heads/{}", branch),
+    ])
+    .is_ok();
+    if exists {
+        git(&["switch", "--quiet", branch])?;
+    } else {
+        git(&["switch", "--quiet", "-c", branch])?;
+    }
+    Ok(!exists)
+}
+
+/// Stash `paths` (tracked or untracked, relative to the repository root as
+/// [`dirty_paths`] reports them) under `message`.
+pub fn stash_paths(paths: &[String], message: &str) -> Result<()> {
+    let mut args = vec![
+        "stash",
+        "push",
+        "--include-untracked",
+        "--message",
+        message,
+        "--",
+    ];
+    let specs = top_pathspecs(paths);
+    args.extend(specs.iter().map(String::as_str));
+    git(&args)?;
+    Ok(())
+}
+
+/// Commit subject for a task from `COMMIT_TEMPLATE`.
+pub fn commit_subject(
+    cfg: &Config,
+    spec_dir: &Path,
+    task_file: Option<&Path>,
+    phase: &str,
+) -> Result<String> {
+    let mut vars = Vars::new();
+    vars.insert("spec", get_spec_name(spec_dir));
+    vars.insert("phase", phase.to_string());
+    vars.insert(
+        "task_number",
+        task_file
+            .and_then(get_task_number)
+            .map(|n| n.to_string())
+            .unwrap_or_default(),
+    );
+    vars.insert(
+        "task_title",
+        task_file
+            .map(|f| {
+                let name = get_task_name(f);
+                match name.split_once(": ") {
+                    Some((prefix, title)) if prefix.starts_with("Task ") => title.to_string(),
+                    _ => name,
+                }
+            })
+            .unwrap_or_default(),
+    );
+    Ok(render(&cfg.commit_template, &vars)?.trim().to_string())
+}
+
+/// Commit `paths` (relative to the repository root) as they are in the
+/// working tree with `subject` and the session trailer. Anything else already
+/// staged stays staged and out of the commit. Returns the new commit's sha.
+pub fn commit_paths(paths: &[String], subject: &str, session_id: &str) -> Result<String> {
+    let specs = top_pathspecs(paths);
+    let mut add = vec!["add", "-A", "--"];
+    add.extend(specs.iter().map(String::as_str));
+    let base: &[&str] = if head_sha().is_empty() {
+        &["read-tree", "--empty"]
+    } else {
+        &["read-tree", "HEAD"]
+    };
+
+    // Build the commit in a scratch index holding HEAD plus `paths`.
+    let index = git(&["rev-parse", "--git-path", "spec-loop-index"])?;
+    let trailer = format!("{}: {}", SESSION_TRAILER, session_id);
+    let result = git_with_index(&index, base)
+        .and_then(|_| git_with_index(&index, &add))
+        .and_then(|_| {
+            git_with_index(
+                &index,
+                &["commit", "--quiet", "-m", subject, "-m", &trailer],
+            )
+        });
+    fs::remove_file(&index).ok();
+    result?;
+
+    let mut reset = vec!["reset", "-q", "HEAD", "--"];
+    reset.extend(specs.iter().map(String::as_str));
+    git(&reset)?;
+    git(&["rev-parse", "HEAD"])
+}
+
+/// Pathspecs for repository-root-relative `paths`, whatever the current
+/// directory.
+fn top_pathspecs(paths: &[String]) -> Vec<String> {
+    paths.iter().map(|p| format!(":(top){}", p)).collect()
+}
+
+pub fn normalize(path: &Path) -> String {
+    let s = path.to_string_lossy();
+    s.trim_start_matches("./").trim_end_matches('/').to_string()
+}
+
+pub fn under(path: &str, dir: &str) -> bool {
+    !dir.is_empty() && (path == dir || path.starts_with(&format!("{}/", dir)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::testing::TestRepo;
+
+    #[test]
+    fn commit_paths_leaves_other_staged_files_out() {
+        let repo = TestRepo::new("commit-paths");
+        repo.write("src/a.rs", "a\n");
+        repo.write("src/old.rs", "old\n");
+        repo.commit("setup");
+        repo.write("src/a.rs", "a2\n");
+        repo.write("src/new.rs", "new\n");
+        fs::remove_file("src/old.rs").unwrap();
+        repo.write("notes.md", "staged by the agent\n");
+        git(&["add", "notes.md"]).unwrap();
+
+        let paths = ["src/a.rs", "src/new.rs", "src/old.rs"].map(String::from);
+        commit_paths(&paths, "Task 1: Alpha", "s1").unwrap();
+
+        let committed = git(&["show", "--name-only", "--format=", "HEAD"]).unwrap();
+        assert_eq!(committed.lines().collect::<Vec<_>>(), paths);
+        let message = git(&["log", "-1", "--format=%B"]).unwrap();
+        assert!(message.contains("Spec-Loop-Session: s1"));
+        assert_eq!(git(&["status", "--porcelain"]).unwrap(), "A  notes.md");
+    }
+}